@@ -44,8 +44,9 @@ popd
 # Update HTML index page.
 cp "$RESOURCE_DIR"/* "$NIGHTLY_DIR/output"
 
-# Put the json data in a JS object for consumption by frontend
-(echo "var data = "; cat "$NIGHTLY_DIR/data/output.json") > "$NIGHTLY_DIR/data/output.js"
+# Put the json data in a JS object for consumption by frontend. The tests
+# write one JSON object per line, so join the lines into an array.
+(echo "var data = ["; sed '$!s/$/,/' "$NIGHTLY_DIR/data/output.jsonl"; echo "]") > "$NIGHTLY_DIR/data/output.js"
 # Copy json directory to the artifact
 cp -r "$NIGHTLY_DIR/data" "$NIGHTLY_DIR/output/data"
 
@@ -0,0 +1,704 @@
+//! Procedural macros that remove the boilerplate of defining a new domain
+//! for ruler.
+//!
+//! `define_synth_language!` wraps `egg::define_language!` and additionally
+//! generates the `SynthLanguage` impl for the enum: `to_var`, `mk_var`,
+//! `is_constant`, `mk_constant`, `eval` from per-operator semantics, and
+//! optionally a z3-backed `validate_smt` (and `validate`, with the default
+//! timeout) from per-operator encodings.
+//!
+//! ```ignore
+//! ruler::define_synth_language! {
+//!     #[derive(Serialize, Deserialize)]
+//!     pub enum Bool {
+//!         "~" = Not(Id) {
+//!             eval: |x| Some(!x),
+//!             z3: |_ctx, x| x.not(),
+//!         },
+//!         "&" = And([Id; 2]) {
+//!             eval: |x, y| Some(*x & *y),
+//!             z3: |ctx, x, y| z3::ast::Bool::and(ctx, &[x, y]),
+//!         },
+//!         #[lit]
+//!         Lit(bool),
+//!         #[var]
+//!         Var(egg::Symbol),
+//!     }
+//!
+//!     z3 {
+//!         sort: z3::ast::Bool,
+//!         var: |ctx, name| z3::ast::Bool::new_const(ctx, name),
+//!         lit: |ctx, c| z3::ast::Bool::from_bool(ctx, *c),
+//!         value: |b| b.as_bool(),
+//!     }
+//!
+//!     impl {
+//!         fn initialize_vars(egraph: &mut EGraph<Self, SynthAnalysis>, vars: &[String]) {
+//!             // ...
+//!         }
+//!     }
+//! }
+//! ```
+//!
+//! The `#[lit]` variant's payload becomes `SynthLanguage::Constant`.
+//! `eval` closures receive one `&Constant` per child and return an
+//! `Option<Constant>`; `z3` closures receive the context and one `&sort` per
+//! child. The optional `value` closure reads a constant back out of a z3
+//! model value, which lets `validate` report counterexamples. Any item
+//! written in the trailing `impl` block is copied into the `SynthLanguage`
+//! impl and takes precedence over the generated one.
+
+use proc_macro::TokenStream;
+use proc_macro2::{Span, TokenStream as TokenStream2};
+use quote::{format_ident, quote};
+use syn::{
+    braced, parenthesized,
+    parse::{Parse, ParseStream},
+    parse_macro_input,
+    punctuated::Punctuated,
+    Attribute, Error, Expr, ExprClosure, Ident, ImplItem, LitStr, Pat, Result, Token, Type,
+    Visibility,
+};
+
+/// Define a `SynthLanguage` together with its underlying `egg` language.
+/// See the crate documentation for the accepted syntax.
+#[proc_macro]
+pub fn define_synth_language(input: TokenStream) -> TokenStream {
+    let lang = parse_macro_input!(input as SynthLang);
+    expand(lang)
+        .unwrap_or_else(|err| err.to_compile_error())
+        .into()
+}
+
+struct SynthLang {
+    attrs: Vec<Attribute>,
+    vis: Visibility,
+    name: Ident,
+    variants: Vec<Variant>,
+    z3: Option<Z3Config>,
+    items: Vec<ImplItem>,
+}
+
+enum Role {
+    Op,
+    Lit,
+    Var,
+}
+
+struct Variant {
+    role: Role,
+    op: Option<LitStr>,
+    name: Ident,
+    field: Option<Type>,
+    eval: Option<ExprClosure>,
+    z3: Option<ExprClosure>,
+}
+
+struct Z3Config {
+    sort: Type,
+    var: ExprClosure,
+    lit: ExprClosure,
+    value: Option<ExprClosure>,
+}
+
+mod kw {
+    syn::custom_keyword!(z3);
+    syn::custom_keyword!(eval);
+    syn::custom_keyword!(sort);
+    syn::custom_keyword!(var);
+    syn::custom_keyword!(lit);
+    syn::custom_keyword!(value);
+}
+
+impl Parse for SynthLang {
+    fn parse(input: ParseStream) -> Result<Self> {
+        let attrs = input.call(Attribute::parse_outer)?;
+        let vis = input.parse()?;
+        input.parse::<Token![enum]>()?;
+        let name = input.parse()?;
+
+        let content;
+        braced!(content in input);
+        let variants: Punctuated<Variant, Token![,]> = content.parse_terminated(Variant::parse)?;
+
+        let mut z3 = None;
+        let mut items = vec![];
+        while !input.is_empty() {
+            if input.peek(kw::z3) {
+                let tok = input.parse::<kw::z3>()?;
+                if z3.is_some() {
+                    return Err(Error::new(tok.span, "duplicate z3 block"));
+                }
+                z3 = Some(input.parse()?);
+            } else {
+                input.parse::<Token![impl]>()?;
+                let content;
+                braced!(content in input);
+                while !content.is_empty() {
+                    items.push(content.parse()?);
+                }
+            }
+        }
+
+        Ok(Self {
+            attrs,
+            vis,
+            name,
+            variants: variants.into_iter().collect(),
+            z3,
+            items,
+        })
+    }
+}
+
+impl Parse for Variant {
+    fn parse(input: ParseStream) -> Result<Self> {
+        let mut role = Role::Op;
+        for attr in input.call(Attribute::parse_outer)? {
+            if attr.path.is_ident("lit") {
+                role = Role::Lit;
+            } else if attr.path.is_ident("var") {
+                role = Role::Var;
+            } else {
+                return Err(Error::new_spanned(attr, "expected #[lit] or #[var]"));
+            }
+        }
+
+        let op = if input.peek(LitStr) {
+            let op = input.parse()?;
+            input.parse::<Token![=]>()?;
+            Some(op)
+        } else {
+            None
+        };
+        let name = input.parse()?;
+        let field = if input.peek(syn::token::Paren) {
+            let content;
+            parenthesized!(content in input);
+            Some(content.parse()?)
+        } else {
+            None
+        };
+
+        let mut eval = None;
+        let mut z3 = None;
+        if input.peek(syn::token::Brace) {
+            let content;
+            braced!(content in input);
+            while !content.is_empty() {
+                if content.peek(kw::eval) {
+                    content.parse::<kw::eval>()?;
+                    content.parse::<Token![:]>()?;
+                    eval = Some(content.parse()?);
+                } else {
+                    content.parse::<kw::z3>()?;
+                    content.parse::<Token![:]>()?;
+                    z3 = Some(content.parse()?);
+                }
+                if !content.is_empty() {
+                    content.parse::<Token![,]>()?;
+                }
+            }
+        }
+
+        Ok(Self {
+            role,
+            op,
+            name,
+            field,
+            eval,
+            z3,
+        })
+    }
+}
+
+impl Parse for Z3Config {
+    fn parse(input: ParseStream) -> Result<Self> {
+        let content;
+        let brace = braced!(content in input);
+        let mut sort = None;
+        let mut var = None;
+        let mut lit = None;
+        let mut value = None;
+        while !content.is_empty() {
+            if content.peek(kw::sort) {
+                content.parse::<kw::sort>()?;
+                content.parse::<Token![:]>()?;
+                sort = Some(content.parse()?);
+            } else if content.peek(kw::var) {
+                content.parse::<kw::var>()?;
+                content.parse::<Token![:]>()?;
+                var = Some(content.parse()?);
+            } else if content.peek(kw::value) {
+                content.parse::<kw::value>()?;
+                content.parse::<Token![:]>()?;
+                value = Some(content.parse()?);
+            } else {
+                content.parse::<kw::lit>()?;
+                content.parse::<Token![:]>()?;
+                lit = Some(content.parse()?);
+            }
+            if !content.is_empty() {
+                content.parse::<Token![,]>()?;
+            }
+        }
+
+        let missing = |field| Error::new(brace.span, format!("z3 block is missing `{}`", field));
+        Ok(Self {
+            sort: sort.ok_or_else(|| missing("sort"))?,
+            var: var.ok_or_else(|| missing("var"))?,
+            lit: lit.ok_or_else(|| missing("lit"))?,
+            value,
+        })
+    }
+}
+
+impl Variant {
+    /// Number of children of an operator, if its field has a shape we
+    /// know how to destructure (`Id` or `[Id; N]`).
+    fn arity(&self) -> Option<usize> {
+        match &self.field {
+            None => Some(0),
+            Some(Type::Path(p)) if p.path.is_ident("Id") => Some(1),
+            Some(Type::Array(arr)) => match (&*arr.elem, &arr.len) {
+                (Type::Path(p), Expr::Lit(len)) if p.path.is_ident("Id") => match &len.lit {
+                    syn::Lit::Int(n) => n.base10_parse().ok(),
+                    _ => None,
+                },
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+
+    /// The pattern matching this variant in `Lang::Name(..)` position,
+    /// binding children to `ids`.
+    fn pattern(&self, lang: &Ident, ids: &[Ident]) -> TokenStream2 {
+        let name = &self.name;
+        match &self.field {
+            None => quote!(#lang::#name),
+            Some(Type::Array(_)) => quote!(#lang::#name([#(#ids),*])),
+            Some(_) => quote!(#lang::#name(#(#ids),*)),
+        }
+    }
+}
+
+/// Inline a closure by binding each of its parameters to the corresponding
+/// argument, so that parameter types come from the arguments rather than
+/// from closure inference.
+fn inline(closure: &ExprClosure, args: &[TokenStream2]) -> Result<TokenStream2> {
+    if closure.inputs.len() != args.len() {
+        return Err(Error::new_spanned(
+            &closure.inputs,
+            format!("expected a closure of {} arguments", args.len()),
+        ));
+    }
+    let pats: Vec<&Pat> = closure.inputs.iter().collect();
+    let body = &closure.body;
+    Ok(quote!({
+        #(let #pats = #args;)*
+        #body
+    }))
+}
+
+fn children(n: usize) -> Vec<Ident> {
+    (0..n).map(|i| format_ident!("__c{}", i)).collect()
+}
+
+fn find_role<'a>(lang: &'a SynthLang, role: fn(&Role) -> bool, attr: &str) -> Result<&'a Variant> {
+    let mut found = lang.variants.iter().filter(|v| role(&v.role));
+    match (found.next(), found.next()) {
+        (Some(v), None) if v.field.is_some() => Ok(v),
+        (Some(v), None) => Err(Error::new_spanned(
+            &v.name,
+            format!("#[{}] variant must carry a value", attr),
+        )),
+        (None, _) => Err(Error::new(
+            lang.name.span(),
+            format!("expected exactly one #[{}] variant", attr),
+        )),
+        (Some(_), Some(v)) => Err(Error::new_spanned(
+            &v.name,
+            format!("duplicate #[{}] variant", attr),
+        )),
+    }
+}
+
+fn expand(lang: SynthLang) -> Result<TokenStream2> {
+    let name = &lang.name;
+    let lit = find_role(&lang, |r| matches!(r, Role::Lit), "lit")?;
+    let var = find_role(&lang, |r| matches!(r, Role::Var), "var")?;
+    let (lit_name, var_name) = (&lit.name, &var.name);
+    let constant = lit.field.as_ref().unwrap();
+    let ops: Vec<&Variant> = lang
+        .variants
+        .iter()
+        .filter(|v| matches!(v.role, Role::Op))
+        .collect();
+
+    let provided = |item: &str| {
+        lang.items.iter().any(|i| match i {
+            ImplItem::Method(m) => m.sig.ident == item,
+            _ => false,
+        })
+    };
+
+    let attrs = &lang.attrs;
+    let vis = &lang.vis;
+    let variants = lang.variants.iter().map(|v| {
+        let vname = &v.name;
+        let field = v.field.as_ref().map(|ty| quote!((#ty)));
+        match &v.op {
+            Some(op) => quote!(#op = #vname #field),
+            None => quote!(#vname #field),
+        }
+    });
+
+    let mut generated = vec![];
+
+    if !provided("eval") {
+        let mut arms = vec![];
+        for op in &ops {
+            let arity = op.arity().ok_or_else(|| {
+                Error::new_spanned(&op.field, "cannot derive eval for this field")
+            })?;
+            let eval = op.eval.as_ref().ok_or_else(|| {
+                Error::new_spanned(
+                    &op.name,
+                    "missing `eval`; give one or write `fn eval` by hand",
+                )
+            })?;
+            let ids = children(arity);
+            let pat = op.pattern(name, &ids);
+            let vals: Vec<Ident> = (0..arity).map(|i| format_ident!("__v{}", i)).collect();
+            let args: Vec<TokenStream2> = vals.iter().map(|v| quote!(#v)).collect();
+            let body = inline(eval, &args)?;
+            let arm = if arity == 0 {
+                quote!(#pat => vec![#body; cvec_len],)
+            } else {
+                let cvecs: Vec<Ident> = (0..arity).map(|i| format_ident!("__cvec{}", i)).collect();
+                quote!(#pat => {
+                    #(let #cvecs = get_cvec(#ids);)*
+                    let len = [#(#cvecs.len()),*].iter().copied().min().unwrap();
+                    (0..len)
+                        .map(|i| match (#(&#cvecs[i],)*) {
+                            (#(Some(#vals),)*) => #body,
+                            _ => None,
+                        })
+                        .collect::<Vec<_>>()
+                })
+            };
+            arms.push(arm);
+        }
+        generated.push(quote! {
+            #[allow(unused_mut, unused_variables)]
+            fn eval<'a, F>(&'a self, cvec_len: usize, mut get_cvec: F) -> ::ruler::CVec<Self>
+            where
+                F: FnMut(&'a ::ruler::Id) -> &'a ::ruler::CVec<Self>,
+            {
+                match self {
+                    #(#arms)*
+                    #name::#lit_name(c) => vec![Some(c.clone()); cvec_len],
+                    #name::#var_name(_) => vec![],
+                }
+            }
+        });
+    }
+
+    if !provided("to_var") {
+        generated.push(quote! {
+            fn to_var(&self) -> Option<::ruler::Symbol> {
+                if let #name::#var_name(v) = self {
+                    Some(*v)
+                } else {
+                    None
+                }
+            }
+        });
+    }
+
+    if !provided("mk_var") {
+        generated.push(quote! {
+            fn mk_var(sym: ::ruler::Symbol) -> Self {
+                #name::#var_name(sym)
+            }
+        });
+    }
+
+    if !provided("is_constant") {
+        generated.push(quote! {
+            fn is_constant(&self) -> bool {
+                matches!(self, #name::#lit_name(_))
+            }
+        });
+    }
+
+    if !provided("mk_constant") {
+        generated.push(quote! {
+            fn mk_constant(
+                c: Self::Constant,
+                _egraph: &mut ::ruler::EGraph<Self, ::ruler::SynthAnalysis>,
+            ) -> Self {
+                #name::#lit_name(c)
+            }
+        });
+    }
+
+    match (&lang.z3, provided("validate")) {
+        (_, true) => (),
+        (None, false) => {
+            return Err(Error::new(
+                Span::call_site(),
+                "expected a z3 block or a hand-written `fn validate`",
+            ))
+        }
+        (Some(_), false) => generated.push(quote! {
+            fn validate(
+                lhs: &::ruler::Pattern<Self>,
+                rhs: &::ruler::Pattern<Self>,
+            ) -> ::ruler::ValidationResult<Self> {
+                Self::validate_smt(lhs, rhs, ::ruler::SMT_TIMEOUT)
+            }
+        }),
+    }
+    if let (Some(z3), false) = (&lang.z3, provided("validate_smt")) {
+        generated.push(expand_validate_smt(name, lit, var, &ops, z3)?);
+    }
+
+    let items = &lang.items;
+    Ok(quote! {
+        ::egg::define_language! {
+            #(#attrs)*
+            #vis enum #name {
+                #(#variants),*
+            }
+        }
+
+        impl ::ruler::SynthLanguage for #name {
+            type Constant = #constant;
+
+            #(#generated)*
+
+            #(#items)*
+        }
+    })
+}
+
+fn expand_validate_smt(
+    name: &Ident,
+    lit: &Variant,
+    var: &Variant,
+    ops: &[&Variant],
+    z3: &Z3Config,
+) -> Result<TokenStream2> {
+    let (lit_name, var_name) = (&lit.name, &var.name);
+    let sort = &z3.sort;
+    let var_body = inline(&z3.var, &[quote!(ctx), quote!(v.to_string())])?;
+    let lit_body = inline(&z3.lit, &[quote!(ctx), quote!(c)])?;
+    let constant = lit.field.as_ref().unwrap();
+
+    let mut arms = vec![];
+    let mut complete = true;
+    for op in ops {
+        let (encoding, arity) = match (&op.z3, op.arity()) {
+            (Some(encoding), Some(arity)) => (encoding, arity),
+            (Some(_), None) => {
+                return Err(Error::new_spanned(
+                    &op.field,
+                    "cannot derive z3 for this field",
+                ))
+            }
+            (None, _) => {
+                complete = false;
+                continue;
+            }
+        };
+        let ids = children(arity);
+        let pat = op.pattern(name, &ids);
+        let mut args = vec![quote!(ctx)];
+        args.extend(ids.iter().map(|id| quote!(&buf[usize::from(*#id)])));
+        let body = inline(encoding, &args)?;
+        arms.push(quote!(#pat => #body,));
+    }
+    let counterexample = match &z3.value {
+        Some(value) => {
+            let var_ast = inline(&z3.var, &[quote!(ctx), quote!(&name[1..])])?;
+            let value_body = inline(value, &[quote!(&ast)])?;
+            quote!(solver.get_model().and_then(|model| {
+                lhs.vars()
+                    .into_iter()
+                    .map(|var| {
+                        let name = var.to_string();
+                        let ast: #sort = #var_ast;
+                        let ast = model.eval(&ast, true)?;
+                        let value: Option<#constant> = #value_body;
+                        value.map(|c| (var, c))
+                    })
+                    .collect()
+            }))
+        }
+        None => quote!(None),
+    };
+
+    // Rules mentioning an operator without an encoding can't be checked.
+    let fallback = if complete {
+        quote!()
+    } else {
+        quote!(op => return Err(op.to_string()),)
+    };
+
+    Ok(quote! {
+        fn validate_smt(
+            lhs: &::ruler::Pattern<Self>,
+            rhs: &::ruler::Pattern<Self>,
+            timeout: ::std::time::Duration,
+        ) -> ::ruler::ValidationResult<Self> {
+            #[allow(unused_imports)]
+            use ::z3::ast::Ast;
+
+            #[allow(unused_variables)]
+            fn egg_to_z3<'a>(
+                ctx: &'a ::z3::Context,
+                expr: &'a [#name],
+            ) -> Result<#sort, String> {
+                #[allow(unused_imports)]
+                use ::z3::ast::Ast;
+
+                let mut buf: Vec<#sort> = vec![];
+                for node in expr.iter() {
+                    let ast = match node {
+                        #name::#var_name(v) => #var_body,
+                        #name::#lit_name(c) => #lit_body,
+                        #(#arms)*
+                        #fallback
+                    };
+                    buf.push(ast);
+                }
+                Ok(buf.pop().unwrap())
+            }
+
+            ::ruler::with_z3_solver(timeout, |ctx, solver| {
+                let lexpr = Self::instantiate(lhs);
+                let rexpr = Self::instantiate(rhs);
+                match (
+                    egg_to_z3(ctx, lexpr.as_ref()),
+                    egg_to_z3(ctx, rexpr.as_ref()),
+                ) {
+                    (Ok(l), Ok(r)) => {
+                        solver.assert(&l._eq(&r).not());
+                        match solver.check() {
+                            ::z3::SatResult::Sat => {
+                                ::ruler::ValidationResult::Invalid(#counterexample)
+                            }
+                            ::z3::SatResult::Unsat => ::ruler::ValidationResult::Valid,
+                            ::z3::SatResult::Unknown => ::ruler::ValidationResult::Unknown(
+                                ::ruler::UnknownReason::from_z3(solver),
+                            ),
+                        }
+                    }
+                    (Err(op), _) | (_, Err(op)) => {
+                        ::ruler::ValidationResult::Unknown(::ruler::UnknownReason::UnsupportedOp(op))
+                    }
+                }
+            })
+        }
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn expand_str(input: &str) -> Result<syn::File> {
+        let lang: SynthLang = syn::parse_str(input)?;
+        syn::parse2(expand(lang)?)
+    }
+
+    const BOOL: &str = r#"
+        #[derive(Serialize, Deserialize)]
+        pub enum Bool {
+            "~" = Not(Id) {
+                eval: |x| Some(!x),
+                z3: |_ctx, x| x.not(),
+            },
+            "&" = And([Id; 2]) {
+                eval: |x, y| Some(*x & *y),
+                z3: |ctx, x, y| z3::ast::Bool::and(ctx, &[x, y]),
+            },
+            #[lit]
+            Lit(bool),
+            #[var]
+            Var(egg::Symbol),
+        }
+
+        z3 {
+            sort: z3::ast::Bool,
+            var: |ctx, name| z3::ast::Bool::new_const(ctx, name),
+            lit: |ctx, c| z3::ast::Bool::from_bool(ctx, *c),
+            value: |b| b.as_bool(),
+        }
+
+        impl {
+            fn initialize_vars(egraph: &mut EGraph<Self, SynthAnalysis>, vars: &[String]) {}
+        }
+    "#;
+
+    fn methods(file: &syn::File) -> Vec<String> {
+        file.items
+            .iter()
+            .filter_map(|item| match item {
+                syn::Item::Impl(imp) => Some(imp),
+                _ => None,
+            })
+            .flat_map(|imp| imp.items.iter())
+            .filter_map(|item| match item {
+                ImplItem::Method(m) => Some(m.sig.ident.to_string()),
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn generates_impl() {
+        let file = expand_str(BOOL).unwrap();
+        assert_eq!(
+            methods(&file),
+            vec![
+                "eval",
+                "to_var",
+                "mk_var",
+                "is_constant",
+                "mk_constant",
+                "validate",
+                "validate_smt",
+                "initialize_vars"
+            ]
+        );
+    }
+
+    #[test]
+    fn hand_written_items_win() {
+        let input = BOOL.replace("impl {", "impl { fn is_constant(&self) -> bool { false }");
+        let file = expand_str(&input).unwrap();
+        let methods = methods(&file);
+        assert_eq!(methods.iter().filter(|m| *m == "is_constant").count(), 1);
+        assert_eq!(methods.last().unwrap(), "initialize_vars");
+    }
+
+    #[test]
+    fn errors() {
+        let no_lit = BOOL.replace("#[lit]", "");
+        assert!(expand_str(&no_lit).is_err());
+
+        let no_eval = BOOL.replace("eval: |x| Some(!x),", "");
+        assert!(expand_str(&no_eval).is_err());
+
+        let bad_arity = BOOL.replace("eval: |x, y|", "eval: |x|");
+        assert!(expand_str(&bad_arity).is_err());
+
+        let z3_start = BOOL.find("z3 {").unwrap();
+        let impl_start = BOOL.find("impl {").unwrap();
+        let no_validator = format!("{}{}", &BOOL[..z3_start], &BOOL[impl_start..]);
+        assert!(expand_str(&no_validator).is_err());
+    }
+}
@@ -1,11 +1,16 @@
 use std::fmt;
 use std::ops::*;
 
+use egg::{ENodeOrVar, PatternAst};
+use num::{BigUint, One, ToPrimitive, Zero};
 use rand::prelude::Distribution;
 use rand::Rng;
 use serde::Deserialize;
 use serde::Serialize;
 
+use crate::enumo::{Rule, Ruleset};
+use crate::{Pattern, SynthLanguage, Validator};
+
 // General bitvector implementation
 #[derive(Copy, Clone, Hash, PartialOrd, Ord, PartialEq, Eq, Serialize, Deserialize)]
 #[serde(transparent)]
@@ -25,6 +30,34 @@ impl<const N: Inner> BV<N> {
         Self(n.into() & Self::ALL_ONES.0)
     }
 
+    // The constants as functions, like `WideBV` has them, for `impl_bv!`
+
+    pub fn zero() -> Self {
+        Self::ZERO
+    }
+
+    pub fn all_ones() -> Self {
+        Self::ALL_ONES
+    }
+
+    pub fn min_value() -> Self {
+        Self::MIN
+    }
+
+    pub fn max_value() -> Self {
+        Self::MAX
+    }
+
+    pub fn to_biguint(&self) -> BigUint {
+        BigUint::from(self.0)
+    }
+
+    /// The low `N` bits of `n`.
+    pub fn from_biguint(n: &BigUint) -> Self {
+        let low = n & BigUint::from(Inner::MAX);
+        Self::new(low.to_u128().unwrap())
+    }
+
     pub fn wrapping_add(self, rhs: Self) -> Self {
         Self::new(self.0.wrapping_add(rhs.0))
     }
@@ -41,6 +74,11 @@ impl<const N: Inner> BV<N> {
         Self::new(self.0.wrapping_neg())
     }
 
+    /// Every `N`-bit value, in increasing order.
+    pub fn all() -> impl Iterator<Item = Self> {
+        (0..=Self::ALL_ONES.0).map(Self)
+    }
+
     pub fn my_shl(self, rhs: Self) -> Self {
         if rhs.0 >= N {
             Self::ZERO
@@ -56,6 +94,124 @@ impl<const N: Inner> BV<N> {
             Self::new(self.0 >> rhs.0)
         }
     }
+
+    // The operations below follow the SMT-LIB definitions, including what
+    // happens when dividing by zero.
+
+    pub fn from_bool(b: bool) -> Self {
+        Self(b as Inner)
+    }
+
+    pub fn is_negative(self) -> bool {
+        self.0 & Self::MIN.0 != 0
+    }
+
+    /// Dividing by zero gives all ones.
+    pub fn udiv(self, rhs: Self) -> Self {
+        if rhs.0 == 0 {
+            Self::ALL_ONES
+        } else {
+            Self(self.0 / rhs.0)
+        }
+    }
+
+    /// The remainder of dividing by zero is the dividend.
+    pub fn urem(self, rhs: Self) -> Self {
+        if rhs.0 == 0 {
+            self
+        } else {
+            Self(self.0 % rhs.0)
+        }
+    }
+
+    /// `udiv` on the magnitudes, negated if the signs differ.
+    pub fn sdiv(self, rhs: Self) -> Self {
+        match (self.is_negative(), rhs.is_negative()) {
+            (false, false) => self.udiv(rhs),
+            (true, false) => self.wrapping_neg().udiv(rhs).wrapping_neg(),
+            (false, true) => self.udiv(rhs.wrapping_neg()).wrapping_neg(),
+            (true, true) => self.wrapping_neg().udiv(rhs.wrapping_neg()),
+        }
+    }
+
+    /// `urem` on the magnitudes, with the sign of the dividend.
+    pub fn srem(self, rhs: Self) -> Self {
+        let rem = self.abs().urem(rhs.abs());
+        if self.is_negative() {
+            rem.wrapping_neg()
+        } else {
+            rem
+        }
+    }
+
+    fn abs(self) -> Self {
+        if self.is_negative() {
+            self.wrapping_neg()
+        } else {
+            self
+        }
+    }
+
+    /// Shifts in copies of the sign bit.
+    pub fn ashr(self, rhs: Self) -> Self {
+        let fill = if self.is_negative() {
+            Self::ALL_ONES
+        } else {
+            Self::ZERO
+        };
+        if rhs.0 == 0 {
+            self
+        } else if rhs.0 >= N {
+            fill
+        } else {
+            Self::new(self.my_shr(rhs).0 | (fill.0 << (N - rhs.0)))
+        }
+    }
+
+    /// Rotates by `rhs` modulo the width.
+    pub fn rotl(self, rhs: Self) -> Self {
+        let k = rhs.0 % N;
+        if k == 0 {
+            self
+        } else {
+            Self::new((self.0 << k) | (self.0 >> (N - k)))
+        }
+    }
+
+    /// Rotates by `rhs` modulo the width.
+    pub fn rotr(self, rhs: Self) -> Self {
+        let k = rhs.0 % N;
+        if k == 0 {
+            self
+        } else {
+            self.rotl(Self(N - k))
+        }
+    }
+
+    pub fn slt(self, rhs: Self) -> bool {
+        (self.0 ^ Self::MIN.0) < (rhs.0 ^ Self::MIN.0)
+    }
+
+    pub fn sle(self, rhs: Self) -> bool {
+        !rhs.slt(self)
+    }
+
+    /// The SMT-LIB2 literal, `(_ bvX N)`.
+    pub fn to_smtlib(self) -> String {
+        format!("(_ bv{} {})", self.0, N)
+    }
+
+    /// Parse an SMT-LIB2 literal: `#b...`, `#x...` or `(_ bvX N)`.
+    pub fn from_smtlib(s: &str) -> Option<Self> {
+        if let Some(bits) = s.strip_prefix("#b") {
+            Inner::from_str_radix(bits, 2).ok().map(Self::new)
+        } else if let Some(hex) = s.strip_prefix("#x") {
+            Inner::from_str_radix(hex, 16).ok().map(Self::new)
+        } else {
+            let value = s.strip_prefix("(_ bv")?.split(' ').next()?;
+            value.parse().ok().map(Self::new)
+        }
+    }
 }
 
 impl<const N: Inner> Not for BV<N> {
@@ -126,10 +282,384 @@ impl<const N: Inner> From<Inner> for BV<N> {
     }
 }
 
-// Macro for specializing BV to different sized bitvectors
+/// A bitvector of any width, backed by a big integer, for widths `BV`
+/// can't hold (256, 512, ...). It has the same operations as `BV`, but
+/// since it isn't `Copy` its constants are only available as functions
+/// (`WideBV::zero()` rather than `BV::ZERO`). Build a domain on it with
+/// `impl_bv!(256, WideBV)`.
+#[derive(Clone, Hash, PartialOrd, Ord, PartialEq, Eq)]
+pub struct WideBV<const N: Inner>(pub BigUint);
+
+impl<const N: Inner> WideBV<N> {
+    pub fn new(n: impl Into<BigUint>) -> Self {
+        Self(n.into() & Self::all_ones().0)
+    }
+
+    pub fn zero() -> Self {
+        Self(BigUint::zero())
+    }
+
+    pub fn all_ones() -> Self {
+        Self((BigUint::one() << N as usize) - 1u32)
+    }
+
+    pub fn min_value() -> Self {
+        Self(BigUint::one() << (N - 1) as usize)
+    }
+
+    pub fn max_value() -> Self {
+        Self(Self::all_ones().0 >> 1)
+    }
+
+    pub fn to_biguint(&self) -> BigUint {
+        self.0.clone()
+    }
+
+    /// The low `N` bits of `n`.
+    pub fn from_biguint(n: &BigUint) -> Self {
+        Self::new(n.clone())
+    }
+
+    pub fn wrapping_add(self, rhs: Self) -> Self {
+        Self::new(self.0 + rhs.0)
+    }
+
+    pub fn wrapping_sub(self, rhs: Self) -> Self {
+        Self::new(self.0 + Self::all_ones().0 + 1u32 - rhs.0)
+    }
+
+    pub fn wrapping_mul(self, rhs: Self) -> Self {
+        Self::new(self.0 * rhs.0)
+    }
+
+    pub fn wrapping_neg(self) -> Self {
+        Self::zero().wrapping_sub(self)
+    }
+
+    /// Every `N`-bit value, in increasing order.
+    pub fn all() -> impl Iterator<Item = Self> {
+        let max = Self::all_ones().0;
+        std::iter::successors(Some(BigUint::zero()), move |x| (x < &max).then(|| x + 1u32))
+            .map(Self)
+    }
+
+    pub fn my_shl(self, rhs: Self) -> Self {
+        if rhs.0 >= BigUint::from(N) {
+            Self::zero()
+        } else {
+            Self::new(self.0 << rhs.0.to_usize().unwrap())
+        }
+    }
+
+    pub fn my_shr(self, rhs: Self) -> Self {
+        if rhs.0 >= BigUint::from(N) {
+            Self::zero()
+        } else {
+            Self::new(self.0 >> rhs.0.to_usize().unwrap())
+        }
+    }
+
+    // As for `BV`, the operations below follow the SMT-LIB definitions.
+
+    pub fn from_bool(b: bool) -> Self {
+        Self(BigUint::from(b as u32))
+    }
+
+    pub fn is_negative(&self) -> bool {
+        self.0 >= Self::min_value().0
+    }
+
+    /// Dividing by zero gives all ones.
+    pub fn udiv(self, rhs: Self) -> Self {
+        if rhs.0.is_zero() {
+            Self::all_ones()
+        } else {
+            Self(self.0 / rhs.0)
+        }
+    }
+
+    /// The remainder of dividing by zero is the dividend.
+    pub fn urem(self, rhs: Self) -> Self {
+        if rhs.0.is_zero() {
+            self
+        } else {
+            Self(self.0 % rhs.0)
+        }
+    }
+
+    /// `udiv` on the magnitudes, negated if the signs differ.
+    pub fn sdiv(self, rhs: Self) -> Self {
+        match (self.is_negative(), rhs.is_negative()) {
+            (false, false) => self.udiv(rhs),
+            (true, false) => self.wrapping_neg().udiv(rhs).wrapping_neg(),
+            (false, true) => self.udiv(rhs.wrapping_neg()).wrapping_neg(),
+            (true, true) => self.wrapping_neg().udiv(rhs.wrapping_neg()),
+        }
+    }
+
+    /// `urem` on the magnitudes, with the sign of the dividend.
+    pub fn srem(self, rhs: Self) -> Self {
+        let negative = self.is_negative();
+        let rem = self.abs().urem(rhs.abs());
+        if negative {
+            rem.wrapping_neg()
+        } else {
+            rem
+        }
+    }
+
+    fn abs(self) -> Self {
+        if self.is_negative() {
+            self.wrapping_neg()
+        } else {
+            self
+        }
+    }
+
+    /// Shifts in copies of the sign bit.
+    pub fn ashr(self, rhs: Self) -> Self {
+        let fill = if self.is_negative() {
+            Self::all_ones()
+        } else {
+            Self::zero()
+        };
+        if rhs.0.is_zero() {
+            self
+        } else if rhs.0 >= BigUint::from(N) {
+            fill
+        } else {
+            let k = rhs.0.to_usize().unwrap();
+            Self::new((self.0 >> k) | (fill.0 << (N as usize - k)))
+        }
+    }
+
+    /// Rotates by `rhs` modulo the width.
+    pub fn rotl(self, rhs: Self) -> Self {
+        let k = (rhs.0 % BigUint::from(N)).to_usize().unwrap();
+        if k == 0 {
+            self
+        } else {
+            Self::new((&self.0 << k) | (self.0 >> (N as usize - k)))
+        }
+    }
+
+    /// Rotates by `rhs` modulo the width.
+    pub fn rotr(self, rhs: Self) -> Self {
+        let k = (rhs.0 % BigUint::from(N)).to_usize().unwrap();
+        if k == 0 {
+            self
+        } else {
+            self.rotl(Self::from(N - k as Inner))
+        }
+    }
+
+    pub fn slt(self, rhs: Self) -> bool {
+        let min = Self::min_value().0;
+        (self.0 ^ &min) < (rhs.0 ^ &min)
+    }
+
+    pub fn sle(self, rhs: Self) -> bool {
+        !rhs.slt(self)
+    }
+
+    /// The SMT-LIB2 literal, `(_ bvX N)`.
+    pub fn to_smtlib(&self) -> String {
+        format!("(_ bv{} {})", self.0, N)
+    }
+
+    /// Parse an SMT-LIB2 literal: `#b...`, `#x...` or `(_ bvX N)`.
+    pub fn from_smtlib(s: &str) -> Option<Self> {
+        if let Some(bits) = s.strip_prefix("#b") {
+            BigUint::parse_bytes(bits.as_bytes(), 2).map(Self::new)
+        } else if let Some(hex) = s.strip_prefix("#x") {
+            BigUint::parse_bytes(hex.as_bytes(), 16).map(Self::new)
+        } else {
+            let value = s.strip_prefix("(_ bv")?.split(' ').next()?;
+            BigUint::parse_bytes(value.as_bytes(), 10).map(Self::new)
+        }
+    }
+}
+
+impl<const N: Inner> Not for WideBV<N> {
+    type Output = Self;
+
+    fn not(self) -> Self::Output {
+        Self(self.0 ^ Self::all_ones().0)
+    }
+}
+
+impl<const N: Inner> BitAnd for WideBV<N> {
+    type Output = Self;
+
+    fn bitand(self, rhs: Self) -> Self::Output {
+        Self(self.0 & rhs.0)
+    }
+}
+
+impl<const N: Inner> BitOr for WideBV<N> {
+    type Output = Self;
+
+    fn bitor(self, rhs: Self) -> Self::Output {
+        Self(self.0 | rhs.0)
+    }
+}
+
+impl<const N: Inner> BitXor for WideBV<N> {
+    type Output = Self;
+
+    fn bitxor(self, rhs: Self) -> Self::Output {
+        Self(self.0 ^ rhs.0)
+    }
+}
+
+impl<const N: Inner> fmt::Debug for WideBV<N> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Display::fmt(&self.0, f)
+    }
+}
+
+impl<const N: Inner> fmt::Display for WideBV<N> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Display::fmt(&self.0, f)
+    }
+}
+
+impl<const N: Inner> std::str::FromStr for WideBV<N> {
+    type Err = num::bigint::ParseBigIntError;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if let Some(stripped) = s.strip_prefix("#b") {
+            return <BigUint as num::Num>::from_str_radix(stripped, 2).map(Self::new);
+        }
+        s.parse::<BigUint>().map(Self::new)
+    }
+}
+
+impl<const N: Inner> From<Inner> for WideBV<N> {
+    fn from(v: Inner) -> Self {
+        Self::new(v)
+    }
+}
+
+/// `value` as a `width`-bit z3 literal. It's built 64 bits at a time, since
+/// z3's numeral constructors stop at 64 bits.
+pub fn z3_bv_literal<'a>(ctx: &'a z3::Context, value: &BigUint, width: u32) -> z3::ast::BV<'a> {
+    let digits = value.to_u64_digits();
+    let digit = |i: u32| digits.get(i as usize).copied().unwrap_or(0);
+    let chunks = (width + 63) / 64;
+    let top_width = width - 64 * (chunks - 1);
+    let mut literal = z3::ast::BV::from_u64(ctx, digit(chunks - 1), top_width);
+    for i in (0..chunks - 1).rev() {
+        literal = literal.concat(&z3::ast::BV::from_u64(ctx, digit(i), 64));
+    }
+    literal
+}
+
+/// The value of a z3 bitvector numeral of any width, e.g. one from a
+/// model. `None` if `bv` isn't a numeral.
+pub fn z3_bv_value(bv: &z3::ast::BV) -> Option<BigUint> {
+    use z3::ast::Ast;
+
+    let width = bv.get_size();
+    let mut value = BigUint::zero();
+    for lo in (0..width).step_by(64).rev() {
+        let hi = (lo + 63).min(width - 1);
+        let chunk = bv.extract(hi, lo).simplify().as_u64()?;
+        value = (value << 64) | BigUint::from(chunk);
+    }
+    Some(value)
+}
+
+/// A bitvector language made by `impl_bv!`, so rules can be ported
+/// between widths.
+pub trait BvLanguage: SynthLanguage {
+    const WIDTH: Inner;
+
+    fn lit_value(&self) -> Option<BigUint>;
+
+    fn mk_lit(value: BigUint) -> Self;
+}
+
+/// `c` at width `from` as a constant at width `to`. Zero, `MIN` and `MAX`
+/// keep their meaning; anything else is sign-extended (or truncated), so
+/// `ALL_ONES` and other small negative numbers keep theirs too.
+pub fn port_constant(c: &BigUint, from: Inner, to: Inner) -> BigUint {
+    let ones = |w: Inner| (BigUint::one() << w as usize) - 1u32;
+    let min = |w: Inner| BigUint::one() << (w - 1) as usize;
+    let max = |w: Inner| ones(w) >> 1;
+    if c.is_zero() {
+        BigUint::zero()
+    } else if *c == min(from) {
+        min(to)
+    } else if *c == max(from) {
+        max(to)
+    } else if c >= &min(from) {
+        // Sign-extend, then keep the low `to` bits
+        let extended = c | (ones(from.max(to)) ^ ones(from));
+        extended & ones(to)
+    } else {
+        c & ones(to)
+    }
+}
+
+fn port_pattern<F: BvLanguage, T: BvLanguage>(pattern: &Pattern<F>) -> Option<Pattern<T>> {
+    let mut ast = PatternAst::default();
+    for node in pattern.ast.as_ref() {
+        ast.add(match node {
+            ENodeOrVar::Var(v) => ENodeOrVar::Var(*v),
+            ENodeOrVar::ENode(n) => ENodeOrVar::ENode(match n.lit_value() {
+                Some(c) => T::mk_lit(port_constant(&c, F::WIDTH, T::WIDTH)),
+                None => T::from_op(&n.to_string(), n.children().to_vec()).ok()?,
+            }),
+        });
+    }
+    Some(Pattern::from(ast))
+}
+
+/// `rule` at width `T::WIDTH`, with constants translated by `port_constant`.
+/// `None` if `T` lacks one of its operators.
+pub fn port_rule<F: BvLanguage, T: BvLanguage>(rule: &Rule<F>) -> Option<Rule<T>> {
+    Rule::new(&port_pattern(&rule.lhs)?, &port_pattern(&rule.rhs)?)
+}
+
+/// Port `rules` to width `T::WIDTH` and check them there. Returns the rules
+/// that still hold, and the ones that don't generalize, with their
+/// validation recorded. Rules that can't be expressed at the new width are
+/// dropped.
+pub fn port_ruleset<F: BvLanguage, T: BvLanguage>(
+    rules: &Ruleset<F>,
+    validator: &dyn Validator<T>,
+) -> (Ruleset<T>, Ruleset<T>) {
+    let mut ported = Ruleset::default();
+    for rule in rules.0.values() {
+        if let Some(rule) = port_rule(rule) {
+            ported.add(rule);
+        }
+    }
+
+    let mut validations = ported.validate_all(validator);
+    let mut sound = Ruleset::default();
+    let mut unsound = Ruleset::default();
+    for (name, mut rule) in ported {
+        let validation = validations.remove(&name).unwrap();
+        if validation.is_valid() {
+            sound.add(rule);
+        } else {
+            rule.validation = Some(validation);
+            unsound.add(rule);
+        }
+    }
+    (sound, unsound)
+}
+
+// Macro for specializing BV to different sized bitvectors. The constants
+// are `BV`s, or, with `impl_bv!(256, WideBV)`, big-integer-backed `WideBV`s
+// for widths over 128 bits.
 #[macro_export]
 macro_rules! impl_bv {
     ($n:literal) => {
+        $crate::impl_bv!($n, BV);
+    };
+    ($n:literal, $bv:ident) => {
         use $crate::*;
 
         use rand::prelude::*;
@@ -138,7 +668,7 @@ macro_rules! impl_bv {
         use std::fmt;
         use std::ops::*;
 
-        pub type BV = $crate::BV::<$n>;
+        pub type BV = $crate::$bv::<$n>;
 
         egg::define_language! {
           pub enum Bv {
@@ -152,11 +682,52 @@ macro_rules! impl_bv {
                   "&" = And([Id; 2]),
                   "|" = Or([Id; 2]),
                   "^" = Xor([Id; 2]),
+                  "udiv" = UDiv([Id; 2]),
+                  "sdiv" = SDiv([Id; 2]),
+                  "urem" = URem([Id; 2]),
+                  "srem" = SRem([Id; 2]),
+                  "ashr" = AShr([Id; 2]),
+                  "rotl" = RotL([Id; 2]),
+                  "rotr" = RotR([Id; 2]),
+                  // Comparisons give 1 or 0
+                  "ult" = Ult([Id; 2]),
+                  "ule" = Ule([Id; 2]),
+                  "ugt" = Ugt([Id; 2]),
+                  "uge" = Uge([Id; 2]),
+                  "slt" = Slt([Id; 2]),
+                  "sle" = Sle([Id; 2]),
+                  "sgt" = Sgt([Id; 2]),
+                  "sge" = Sge([Id; 2]),
                   Lit(BV),
                   Var(egg::Symbol),
               }
         }
 
+        impl Bv {
+            pub fn is_comparison(&self) -> bool {
+                matches!(
+                    self,
+                    Bv::Ult(_) | Bv::Ule(_) | Bv::Ugt(_) | Bv::Uge(_)
+                        | Bv::Slt(_) | Bv::Sle(_) | Bv::Sgt(_) | Bv::Sge(_)
+                )
+            }
+        }
+
+        impl BvLanguage for Bv {
+            const WIDTH: u128 = $n;
+
+            fn lit_value(&self) -> Option<num::BigUint> {
+                match self {
+                    Bv::Lit(c) => Some(c.to_biguint()),
+                    _ => None,
+                }
+            }
+
+            fn mk_lit(value: num::BigUint) -> Self {
+                Bv::Lit(BV::from_biguint(&value))
+            }
+        }
+
         impl SynthLanguage for Bv {
             type Constant = BV;
 
@@ -165,19 +736,37 @@ macro_rules! impl_bv {
                 F: FnMut(&'a Id) -> &'a CVec<Self>,
             {
                 match self {
-                    Bv::Neg(a) => map!(get_cvec, a => Some(a.wrapping_neg())),
-                    Bv::Not(a) => map!(get_cvec, a => Some(a.not())),
+                    // Cloned rather than copied, so `WideBV` works too
+                    Bv::Neg(a) => map!(get_cvec, a => Some(a.clone().wrapping_neg())),
+                    Bv::Not(a) => map!(get_cvec, a => Some(!a.clone())),
 
-                    Bv::Add([a, b]) => map!(get_cvec, a, b => Some(a.wrapping_add(*b))),
-                    Bv::Sub([a, b]) => map!(get_cvec, a, b => Some(a.wrapping_sub(*b))),
-                    Bv::Mul([a, b]) => map!(get_cvec, a, b => Some(a.wrapping_mul(*b))),
+                    Bv::Add([a, b]) => map!(get_cvec, a, b => Some(a.clone().wrapping_add(b.clone()))),
+                    Bv::Sub([a, b]) => map!(get_cvec, a, b => Some(a.clone().wrapping_sub(b.clone()))),
+                    Bv::Mul([a, b]) => map!(get_cvec, a, b => Some(a.clone().wrapping_mul(b.clone()))),
 
-                    Bv::Shl([a, b]) => map!(get_cvec, a, b => Some(a.my_shl(*b))),
-                    Bv::Shr([a, b]) => map!(get_cvec, a, b => Some(a.my_shr(*b))),
+                    Bv::Shl([a, b]) => map!(get_cvec, a, b => Some(a.clone().my_shl(b.clone()))),
+                    Bv::Shr([a, b]) => map!(get_cvec, a, b => Some(a.clone().my_shr(b.clone()))),
 
-                    Bv::And([a, b]) => map!(get_cvec, a, b => Some(*a & *b)),
-                    Bv::Or([a, b]) => map!(get_cvec, a, b => Some(*a | *b)),
-                    Bv::Xor([a, b]) => map!(get_cvec, a, b => Some(*a ^ *b)),
+                    Bv::And([a, b]) => map!(get_cvec, a, b => Some(a.clone() & b.clone())),
+                    Bv::Or([a, b]) => map!(get_cvec, a, b => Some(a.clone() | b.clone())),
+                    Bv::Xor([a, b]) => map!(get_cvec, a, b => Some(a.clone() ^ b.clone())),
+
+                    Bv::UDiv([a, b]) => map!(get_cvec, a, b => Some(a.clone().udiv(b.clone()))),
+                    Bv::SDiv([a, b]) => map!(get_cvec, a, b => Some(a.clone().sdiv(b.clone()))),
+                    Bv::URem([a, b]) => map!(get_cvec, a, b => Some(a.clone().urem(b.clone()))),
+                    Bv::SRem([a, b]) => map!(get_cvec, a, b => Some(a.clone().srem(b.clone()))),
+                    Bv::AShr([a, b]) => map!(get_cvec, a, b => Some(a.clone().ashr(b.clone()))),
+                    Bv::RotL([a, b]) => map!(get_cvec, a, b => Some(a.clone().rotl(b.clone()))),
+                    Bv::RotR([a, b]) => map!(get_cvec, a, b => Some(a.clone().rotr(b.clone()))),
+
+                    Bv::Ult([a, b]) => map!(get_cvec, a, b => Some(BV::from_bool(a < b))),
+                    Bv::Ule([a, b]) => map!(get_cvec, a, b => Some(BV::from_bool(a <= b))),
+                    Bv::Ugt([a, b]) => map!(get_cvec, a, b => Some(BV::from_bool(a > b))),
+                    Bv::Uge([a, b]) => map!(get_cvec, a, b => Some(BV::from_bool(a >= b))),
+                    Bv::Slt([a, b]) => map!(get_cvec, a, b => Some(BV::from_bool(a.clone().slt(b.clone())))),
+                    Bv::Sle([a, b]) => map!(get_cvec, a, b => Some(BV::from_bool(a.clone().sle(b.clone())))),
+                    Bv::Sgt([a, b]) => map!(get_cvec, a, b => Some(BV::from_bool(b.clone().slt(a.clone())))),
+                    Bv::Sge([a, b]) => map!(get_cvec, a, b => Some(BV::from_bool(b.clone().sle(a.clone())))),
 
                     Bv::Lit(n) => vec![Some(n.clone()); cvec_len],
                     Bv::Var(_) => vec![],
@@ -189,12 +778,65 @@ macro_rules! impl_bv {
                 F: FnMut(&'a Id) -> &'a Interval<Self::Constant>,
             {
                 match self {
-                    Bv::Lit(c) => Interval::new(Some(*c), Some(*c)),
-                    // Todo- proper interval analysis. For now it's just constant folding
+                    Bv::Lit(c) => Interval::constant(c.clone()),
+                    Bv::Var(_) => Interval::new(Some(BV::zero()), Some(BV::all_ones())),
+                    Bv::Add([a, b]) => get_interval(a).add(get_interval(b)),
+                    Bv::Sub([a, b]) => get_interval(a).sub(get_interval(b)),
+                    Bv::Mul([a, b]) => get_interval(a).mul(get_interval(b)),
+                    Bv::Not(a) => Interval::constant(BV::all_ones()).sub(get_interval(a)),
+                    // a & b is at most min(a, b), and a | b at least max(a, b)
+                    Bv::And([a, b]) => Interval::new(Some(BV::zero()), get_interval(a).min(get_interval(b)).high),
+                    Bv::Or([a, b]) => Interval::new(get_interval(a).max(get_interval(b)).low, Some(BV::all_ones())),
+                    // Dividing by zero gives all ones, but the remainder is the dividend
+                    Bv::UDiv([a, b]) if get_interval(b).is_nonzero() => {
+                        Interval::new(Some(BV::zero()), get_interval(a).high.clone())
+                    }
+                    Bv::Shr([a, _]) | Bv::URem([a, _]) => {
+                        Interval::new(Some(BV::zero()), get_interval(a).high.clone())
+                    }
+                    _ if self.is_comparison() => Interval::new(Some(BV::zero()), Some(BV::from(1))),
+                    // Anything that can wrap around is unbounded
                     _ => Interval::default()
                 }
             }
 
+            fn mk_known_bits<'a, F>(&'a self, mut get_known_bits: F) -> Option<KnownBits>
+            where
+                F: FnMut(&'a Id) -> &'a Option<KnownBits>,
+            {
+                let mut get = |id: &'a Id| {
+                    get_known_bits(id).clone().unwrap_or_else(|| KnownBits::unknown($n))
+                };
+                Some(match self {
+                    Bv::Lit(c) => KnownBits::constant($n, &c.to_biguint()),
+                    Bv::Var(_) => KnownBits::unknown($n),
+                    Bv::Neg(a) => get(a).neg(),
+                    Bv::Not(a) => get(a).not(),
+                    Bv::Add([a, b]) => get(a).add(&get(b)),
+                    Bv::Sub([a, b]) => get(a).sub(&get(b)),
+                    Bv::Mul([a, b]) => get(a).mul(&get(b)),
+                    Bv::Shl([a, b]) => get(a).shl(&get(b)),
+                    Bv::Shr([a, b]) => get(a).lshr(&get(b)),
+                    Bv::AShr([a, b]) => get(a).ashr(&get(b)),
+                    Bv::RotL([a, b]) => get(a).rotl(&get(b)),
+                    Bv::RotR([a, b]) => get(a).rotr(&get(b)),
+                    Bv::And([a, b]) => get(a).and(&get(b)),
+                    Bv::Or([a, b]) => get(a).or(&get(b)),
+                    Bv::Xor([a, b]) => get(a).xor(&get(b)),
+                    _ if self.is_comparison() => KnownBits::boolean($n),
+                    _ => KnownBits::unknown($n),
+                })
+            }
+
+            // Fold classes whose bits are all known
+            fn custom_modify(egraph: &mut EGraph<Self, SynthAnalysis>, id: Id) {
+                let known_bits = egraph[id].data.known_bits.as_ref();
+                if let Some(c) = known_bits.and_then(|k| k.as_constant()).map(BV::from_biguint) {
+                    let added = egraph.add(Bv::Lit(c));
+                    egraph.union(id, added);
+                }
+            }
+
             fn to_var(&self) -> Option<Symbol> {
                 if let Bv::Var(sym) = self {
                     Some(*sym)
@@ -215,42 +857,134 @@ macro_rules! impl_bv {
                 Bv::Lit(c)
             }
 
-            fn initialize_vars(egraph: &mut EGraph<Self, SynthAnalysis>, vars: &[String]) {
-                //   let mut consts: Vec<Option<BV>> = (0..1u64 << $n).map(|i| Some((i as u32).into())).collect();
-                let mut consts = vec![];
-
-                for i in 0..2 {
-                    let i = BV::from(i);
-                    consts.push(Some(BV::MIN.wrapping_add(i)));
-                    consts.push(Some(BV::MAX.wrapping_sub(i)));
-                    consts.push(Some(i));
-                    consts.push(Some(i.wrapping_neg()));
+            fn sort_values(_sort: &Sort) -> Vec<BV> {
+                BvSampler::default().values($n).iter().map(BV::from_biguint).collect()
+            }
+
+            fn sort_edge_cases(_sort: &Sort) -> Vec<BV> {
+                BvSampler::edge_cases($n).iter().map(BV::from_biguint).collect()
+            }
+
+            // Wide bitvectors often agree on every corner case, so mix in the
+            // other kinds of values, with a cap on the number of environments
+            fn default_sampler() -> CvecSampler {
+                if $n >= 32 {
+                    CvecSampler::edge_cases(512).with_bv(BvSampler::mixed($n))
+                } else {
+                    CvecSampler::default()
                 }
-                consts.sort();
-                consts.dedup();
+            }
 
-                let mut cvecs = self_product(&consts, vars.len());
+            // Every variable ranges over the values the egraph's `BvSampler` picks
+            fn initialize_vars(egraph: &mut EGraph<Self, SynthAnalysis>, vars: &[String]) {
+                let sampler = egraph.analysis.sampler;
+                let values = sampler.bv.values($n).iter().map(BV::from_biguint).collect::<Vec<_>>();
+                let domain = Domain::new(values).with_edge_cases(Self::sort_edge_cases(&Sort::default()));
+                sampler.initialize(egraph, vars, &vec![domain; vars.len()]);
+            }
 
-                egraph.analysis.cvec_len = cvecs[0].len();
+            fn all_values(_sort: &Sort) -> Option<Vec<BV>> {
+                if $n <= 8 {
+                    Some(BV::all().collect())
+                } else {
+                    None
+                }
+            }
 
-                for (i, v) in vars.iter().enumerate() {
-                    let id = egraph.add(Bv::Var(Symbol::from(v.clone())));
-                    egraph[id].data.cvec = cvecs[i].clone()
+            fn smtlib_term(&self, args: &[String]) -> Option<String> {
+                let op = match self {
+                    Bv::Lit(c) => return Some(c.to_smtlib()),
+                    Bv::Var(_) => return None,
+                    Bv::Add(_) => "bvadd",
+                    Bv::Sub(_) => "bvsub",
+                    Bv::Mul(_) => "bvmul",
+                    Bv::Neg(_) => "bvneg",
+                    Bv::Not(_) => "bvnot",
+                    Bv::Shl(_) => "bvshl",
+                    Bv::Shr(_) => "bvlshr",
+                    Bv::And(_) => "bvand",
+                    Bv::Or(_) => "bvor",
+                    Bv::Xor(_) => "bvxor",
+                    Bv::UDiv(_) => "bvudiv",
+                    Bv::SDiv(_) => "bvsdiv",
+                    Bv::URem(_) => "bvurem",
+                    Bv::SRem(_) => "bvsrem",
+                    Bv::AShr(_) => "bvashr",
+                    // Standard SMT-LIB only rotates by constants
+                    Bv::RotL(_) | Bv::RotR(_) => {
+                        let (x, width) = (&args[0], BV::from($n).to_smtlib());
+                        let k = format!("(bvurem {} {})", args[1], width);
+                        let (towards, away) = match self {
+                            Bv::RotL(_) => ("bvshl", "bvlshr"),
+                            _ => ("bvlshr", "bvshl"),
+                        };
+                        return Some(format!(
+                            "(bvor ({} {} {}) ({} {} (bvsub {} {})))",
+                            towards, x, k, away, x, width, k
+                        ));
+                    }
+                    Bv::Ult(_) => "bvult",
+                    Bv::Ule(_) => "bvule",
+                    Bv::Ugt(_) => "bvugt",
+                    Bv::Uge(_) => "bvuge",
+                    Bv::Slt(_) => "bvslt",
+                    Bv::Sle(_) => "bvsle",
+                    Bv::Sgt(_) => "bvsgt",
+                    Bv::Sge(_) => "bvsge",
+                };
+                let term = format!("({} {})", op, args.join(" "));
+                if self.is_comparison() {
+                    Some(format!(
+                        "(ite {} {} {})",
+                        term,
+                        BV::from(1).to_smtlib(),
+                        BV::zero().to_smtlib()
+                    ))
+                } else {
+                    Some(term)
                 }
             }
 
+            fn smtlib_sort(_sort: &Sort) -> Option<String> {
+                Some(format!("(_ BitVec {})", $n))
+            }
+
+            fn smtlib_logic() -> &'static str {
+                "QF_BV"
+            }
+
+            fn constant_from_smtlib(value: &str) -> Option<BV> {
+                BV::from_smtlib(value)
+            }
+
+            // Small widths are checked by enumeration, the rest by z3
             fn validate(
                 lhs: &Pattern<Self>,
                 rhs: &Pattern<Self>,
-            ) -> ValidationResult {
+            ) -> ValidationResult<Self> {
+                match Exhaustive::default().validate(lhs, rhs) {
+                    ValidationResult::Unknown(_) => Self::validate_smt(lhs, rhs, SMT_TIMEOUT),
+                    result => result,
+                }
+            }
+
+            fn validate_smt(
+                lhs: &Pattern<Self>,
+                rhs: &Pattern<Self>,
+                timeout: std::time::Duration,
+            ) -> ValidationResult<Self> {
                 use z3::{*, ast::Ast};
 
                 fn egg_to_z3<'a>(ctx: &'a z3::Context, expr: &[Bv]) -> z3::ast::BV<'a> {
                     let mut buf: Vec<z3::ast::BV> = vec![];
+                    // Comparisons give 1 or 0
+                    let one = z3::ast::BV::from_u64(ctx, 1, $n);
+                    let zero = z3::ast::BV::from_u64(ctx, 0, $n);
+                    let bit = |b: z3::ast::Bool<'a>| b.ite(&one, &zero);
                     for node in expr.as_ref().iter() {
                         match node {
                             Bv::Var(v) => buf.push(z3::ast::BV::new_const(&ctx, v.to_string(), $n)),
-                            Bv::Lit(c) => buf.push(z3::ast::BV::from_u64(&ctx, c.0 as u64, $n)),
+                            Bv::Lit(c) => buf.push(z3_bv_literal(ctx, &c.to_biguint(), $n)),
                             Bv::Add([a, b]) => buf.push(buf[usize::from(*a)].bvadd(&buf[usize::from(*b)])),
                             Bv::Sub([a, b]) => buf.push(buf[usize::from(*a)].bvsub(&buf[usize::from(*b)])),
                             Bv::Mul([a, b]) => buf.push(buf[usize::from(*a)].bvmul(&buf[usize::from(*b)])),
@@ -261,23 +995,49 @@ macro_rules! impl_bv {
                             Bv::Xor([a, b]) => buf.push(buf[usize::from(*a)].bvxor(&buf[usize::from(*b)])),
                             Bv::Not(a) => buf.push(buf[usize::from(*a)].bvnot()),
                             Bv::Neg(a) => buf.push(buf[usize::from(*a)].bvneg()),
+                            Bv::UDiv([a, b]) => buf.push(buf[usize::from(*a)].bvudiv(&buf[usize::from(*b)])),
+                            Bv::SDiv([a, b]) => buf.push(buf[usize::from(*a)].bvsdiv(&buf[usize::from(*b)])),
+                            Bv::URem([a, b]) => buf.push(buf[usize::from(*a)].bvurem(&buf[usize::from(*b)])),
+                            Bv::SRem([a, b]) => buf.push(buf[usize::from(*a)].bvsrem(&buf[usize::from(*b)])),
+                            Bv::AShr([a, b]) => buf.push(buf[usize::from(*a)].bvashr(&buf[usize::from(*b)])),
+                            Bv::RotL([a, b]) => buf.push(buf[usize::from(*a)].bvrotl(&buf[usize::from(*b)])),
+                            Bv::RotR([a, b]) => buf.push(buf[usize::from(*a)].bvrotr(&buf[usize::from(*b)])),
+                            Bv::Ult([a, b]) => buf.push(bit(buf[usize::from(*a)].bvult(&buf[usize::from(*b)]))),
+                            Bv::Ule([a, b]) => buf.push(bit(buf[usize::from(*a)].bvule(&buf[usize::from(*b)]))),
+                            Bv::Ugt([a, b]) => buf.push(bit(buf[usize::from(*a)].bvugt(&buf[usize::from(*b)]))),
+                            Bv::Uge([a, b]) => buf.push(bit(buf[usize::from(*a)].bvuge(&buf[usize::from(*b)]))),
+                            Bv::Slt([a, b]) => buf.push(bit(buf[usize::from(*a)].bvslt(&buf[usize::from(*b)]))),
+                            Bv::Sle([a, b]) => buf.push(bit(buf[usize::from(*a)].bvsle(&buf[usize::from(*b)]))),
+                            Bv::Sgt([a, b]) => buf.push(bit(buf[usize::from(*a)].bvsgt(&buf[usize::from(*b)]))),
+                            Bv::Sge([a, b]) => buf.push(bit(buf[usize::from(*a)].bvsge(&buf[usize::from(*b)]))),
                         }
                     }
                     buf.pop().unwrap()
                 }
 
-                let mut cfg = z3::Config::new();
-                cfg.set_timeout_msec(1000);
-                let ctx = z3::Context::new(&cfg);
-                let solver = z3::Solver::new(&ctx);
-                let lexpr = egg_to_z3(&ctx, Self::instantiate(lhs).as_ref());
-                let rexpr = egg_to_z3(&ctx, Self::instantiate(rhs).as_ref());
-                solver.assert(&lexpr._eq(&rexpr).not());
-                match solver.check() {
-                    SatResult::Sat => ValidationResult::Invalid,
-                    SatResult::Unsat => ValidationResult::Valid,
-                    SatResult::Unknown => ValidationResult::Unknown
-                }
+                with_z3_solver(timeout, |ctx, solver| {
+                    let lexpr = egg_to_z3(ctx, Self::instantiate(lhs).as_ref());
+                    let rexpr = egg_to_z3(ctx, Self::instantiate(rhs).as_ref());
+                    solver.assert(&lexpr._eq(&rexpr).not());
+                    match solver.check() {
+                        SatResult::Sat => {
+                            let counterexample = solver.get_model().and_then(|model| {
+                                lhs.vars()
+                                    .into_iter()
+                                    .map(|var| {
+                                        let name = var.to_string();
+                                        let ast = z3::ast::BV::new_const(ctx, &name[1..], $n);
+                                        let value = z3_bv_value(&model.eval(&ast, true)?)?;
+                                        Some((var, BV::from_biguint(&value)))
+                                    })
+                                    .collect()
+                            });
+                            ValidationResult::Invalid(counterexample)
+                        }
+                        SatResult::Unsat => ValidationResult::Valid,
+                        SatResult::Unknown => ValidationResult::Unknown(UnknownReason::from_z3(solver)),
+                    }
+                })
             }
         }
     };
@@ -300,5 +1060,120 @@ pub mod tests {
         assert_eq!(BV4::NEG_ONE.wrapping_neg(), one);
         assert_eq!(BV4::MIN.wrapping_mul(BV::NEG_ONE), BV::MIN);
         assert_eq!(BV4::MIN.wrapping_neg(), BV::MIN);
+
+        assert_eq!(BV4::all().count(), 16);
+        assert_eq!(BV4::all().last(), Some(BV4::ALL_ONES));
+    }
+
+    #[test]
+    fn smtlib_semantics() {
+        let bv = |n: i8| BV4::new(n as u8 & 0b1111);
+
+        assert_eq!(bv(7).udiv(bv(0)), BV4::ALL_ONES);
+        assert_eq!(bv(7).urem(bv(0)), bv(7));
+        assert_eq!(bv(7).udiv(bv(2)), bv(3));
+
+        assert_eq!(bv(-7).sdiv(bv(2)), bv(-3));
+        assert_eq!(bv(7).sdiv(bv(-2)), bv(-3));
+        assert_eq!(bv(-7).sdiv(bv(-2)), bv(3));
+        assert_eq!(bv(5).sdiv(bv(0)), bv(-1));
+        assert_eq!(bv(-5).sdiv(bv(0)), bv(1));
+        assert_eq!(BV4::MIN.sdiv(bv(-1)), BV4::MIN);
+
+        assert_eq!(bv(-7).srem(bv(2)), bv(-1));
+        assert_eq!(bv(7).srem(bv(-2)), bv(1));
+        assert_eq!(bv(-5).srem(bv(0)), bv(-5));
+
+        assert_eq!(bv(-8).ashr(bv(2)), bv(-2));
+        assert_eq!(bv(4).ashr(bv(2)), bv(1));
+        assert_eq!(bv(-8).ashr(bv(9)), bv(-1));
+
+        assert_eq!(BV4::new(0b1001u8).rotl(bv(1)), BV4::new(0b0011u8));
+        assert_eq!(BV4::new(0b1001u8).rotr(bv(1)), BV4::new(0b1100u8));
+        assert_eq!(BV4::new(0b1001u8).rotl(bv(5)), BV4::new(0b0011u8));
+
+        assert!(bv(-1).slt(bv(0)));
+        assert!(!BV4::ALL_ONES.lt(&bv(0)));
+        assert!(bv(3).sle(bv(3)));
+    }
+
+    #[test]
+    fn port_constants() {
+        let port = |c: u128, from, to| port_constant(&BigUint::from(c), from, to);
+        let to32 = |c| port(c, 4, 32);
+        assert_eq!(to32(0), BigUint::zero());
+        assert_eq!(to32(1), BigUint::one());
+        assert_eq!(to32(BV4::MIN.0), BV::<32>::MIN.to_biguint());
+        assert_eq!(to32(BV4::MAX.0), BV::<32>::MAX.to_biguint());
+        assert_eq!(to32(BV4::ALL_ONES.0), BV::<32>::ALL_ONES.to_biguint());
+        assert_eq!(to32(0b1110), BV::<32>::ALL_ONES.to_biguint() - 1u32);
+        assert_eq!(
+            port(BV::<32>::ALL_ONES.0, 32, 4),
+            BV4::ALL_ONES.to_biguint()
+        );
+        assert_eq!(port(BV::<128>::MIN.0, 128, 4), BV4::MIN.to_biguint());
+        assert_eq!(port(BV4::MAX.0, 4, 128), BV::<128>::MAX.to_biguint());
+        assert_eq!(port(BV4::MIN.0, 4, 256), WideBV::<256>::min_value().0);
+        assert_eq!(port(BV4::ALL_ONES.0, 4, 512), WideBV::<512>::all_ones().0);
+    }
+
+    #[test]
+    fn wide_bv_agrees_with_bv() {
+        type BV64 = BV<64>;
+        type Wide64 = WideBV<64>;
+        let values = [0, 1, 5, 63, 64, 1 << 40, BV64::MAX.0, BV64::MIN.0, !0 - 6];
+        let wide = |x: BV64| Wide64::from(x.0);
+        for &x in &values {
+            let x = BV64::new(x);
+            assert_eq!(wide(x.wrapping_neg()), wide(x).wrapping_neg());
+            assert_eq!(wide(!x), !wide(x));
+            for &y in &values {
+                let y = BV64::new(y);
+                let (wx, wy) = (wide(x), wide(y));
+                assert_eq!(wide(x.wrapping_add(y)), wx.clone().wrapping_add(wy.clone()));
+                assert_eq!(wide(x.wrapping_sub(y)), wx.clone().wrapping_sub(wy.clone()));
+                assert_eq!(wide(x.wrapping_mul(y)), wx.clone().wrapping_mul(wy.clone()));
+                assert_eq!(wide(x.my_shl(y)), wx.clone().my_shl(wy.clone()));
+                assert_eq!(wide(x.my_shr(y)), wx.clone().my_shr(wy.clone()));
+                assert_eq!(wide(x.udiv(y)), wx.clone().udiv(wy.clone()));
+                assert_eq!(wide(x.sdiv(y)), wx.clone().sdiv(wy.clone()));
+                assert_eq!(wide(x.urem(y)), wx.clone().urem(wy.clone()));
+                assert_eq!(wide(x.srem(y)), wx.clone().srem(wy.clone()));
+                assert_eq!(wide(x.ashr(y)), wx.clone().ashr(wy.clone()));
+                assert_eq!(wide(x.rotl(y)), wx.clone().rotl(wy.clone()));
+                assert_eq!(wide(x.rotr(y)), wx.clone().rotr(wy.clone()));
+                assert_eq!(x.slt(y), wx.slt(wy));
+            }
+        }
+        assert_eq!(
+            Wide64::from_smtlib(&wide(BV64::MAX).to_smtlib()),
+            Some(wide(BV64::MAX))
+        );
+    }
+
+    #[test]
+    fn z3_literals() {
+        let ctx = z3::Context::new(&z3::Config::new());
+        let big = (BigUint::one() << 200) + BigUint::from(u64::MAX);
+        for (value, width) in [
+            (BigUint::from(5u32), 4),
+            (BigUint::from(u128::MAX), 128),
+            (big.clone(), 256),
+            (big, 201),
+        ] {
+            let literal = z3_bv_literal(&ctx, &value, width);
+            assert_eq!(literal.get_size(), width);
+            assert_eq!(z3_bv_value(&literal), Some(value));
+        }
+    }
+
+    #[test]
+    fn smtlib_literals() {
+        let five = BV4::from(5);
+        assert_eq!(five.to_smtlib(), "(_ bv5 4)");
+        assert_eq!(BV4::from_smtlib(&five.to_smtlib()), Some(five));
+        assert_eq!(BV4::from_smtlib("#b0101"), Some(five));
+        assert_eq!(BV4::from_smtlib("#x5"), Some(five));
+        assert_eq!(BV4::from_smtlib("five"), None);
     }
 }
@@ -0,0 +1,206 @@
+use std::fs::{File, OpenOptions};
+use std::io::{self, BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::Duration;
+
+use egg::{ENodeOrVar, PatternAst};
+use serde::{Deserialize, Serialize};
+
+use crate::{enumo::Rule, HashMap, Pattern, Sort, SynthLanguage, ValidationResult, Var};
+
+/// Set to a file path to cache every `Rule::validate_with` result there.
+pub const VALIDATION_CACHE_VAR: &str = "RULER_VALIDATION_CACHE";
+
+/// Validation results saved to a JSON-lines file, so rules checked once
+/// aren't re-checked by later runs. Entries are keyed by domain, the
+/// domain's `SynthLanguage::semantics_version`, validator name and the rule
+/// with its variables renamed in order of appearance, so a version bump
+/// retires every entry of that domain.
+///
+/// Only `Valid` and `Invalid` answers are saved: `Unknown` usually means a
+/// timeout, which a later run may not hit. Counterexamples are not saved,
+/// so cached `Invalid` results have none. The time the original validation
+/// took is saved with the result, so cache hits report it rather than the
+/// lookup time.
+///
+/// A cache that can't be opened or written to is reported on stderr and
+/// otherwise ignored: validation goes on without it.
+pub struct ValidationCache {
+    path: PathBuf,
+    entries: Mutex<HashMap<String, Entry>>,
+    file: Mutex<File>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct Entry {
+    domain: String,
+    version: u32,
+    validator: String,
+    rule: String,
+    valid: bool,
+    confidence: f64,
+    /// Seconds the original validation took.
+    #[serde(default)]
+    time: f64,
+}
+
+impl Entry {
+    fn key(&self) -> String {
+        format!(
+            "{}@{} [{}] {}",
+            self.domain, self.version, self.validator, self.rule
+        )
+    }
+}
+
+lazy_static::lazy_static! {
+    static ref GLOBAL: Option<ValidationCache> = std::env::var(VALIDATION_CACHE_VAR)
+        .ok()
+        .and_then(|path| match ValidationCache::open(&path) {
+            Ok(cache) => Some(cache),
+            Err(e) => {
+                eprintln!("Failed to open validation cache '{}': {}", path, e);
+                None
+            }
+        });
+}
+
+impl ValidationCache {
+    /// Load the cache at `path`, creating the file if needed. Lines that
+    /// don't parse are skipped.
+    pub fn open(path: impl AsRef<Path>) -> io::Result<Self> {
+        let path = path.as_ref().to_path_buf();
+        if let Some(dir) = path.parent().filter(|d| !d.as_os_str().is_empty()) {
+            std::fs::create_dir_all(dir)?;
+        }
+        let file = OpenOptions::new()
+            .read(true)
+            .append(true)
+            .create(true)
+            .open(&path)?;
+
+        let mut entries = HashMap::default();
+        for line in BufReader::new(&file).lines() {
+            if let Ok(entry) = serde_json::from_str::<Entry>(&line?) {
+                entries.insert(entry.key(), entry);
+            }
+        }
+
+        Ok(Self {
+            path,
+            entries: Mutex::new(entries),
+            file: Mutex::new(file),
+        })
+    }
+
+    /// The cache named by the `RULER_VALIDATION_CACHE` environment variable,
+    /// if it is set and could be opened.
+    pub fn global() -> Option<&'static Self> {
+        GLOBAL.as_ref()
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.lock().unwrap().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// The saved result of `validator` on `rule`, with its confidence and
+    /// the time the original validation took.
+    pub fn get<L: SynthLanguage>(
+        &self,
+        validator: &str,
+        rule: &Rule<L>,
+    ) -> Option<(ValidationResult<L>, f64, Duration)> {
+        let key = Self::entry::<L>(validator, rule, true, 1.0, Duration::ZERO).key();
+        let entries = self.entries.lock().unwrap();
+        entries.get(&key).map(|entry| {
+            let result = if entry.valid {
+                ValidationResult::Valid
+            } else {
+                ValidationResult::Invalid(None)
+            };
+            let time = Duration::try_from_secs_f64(entry.time).unwrap_or_default();
+            (result, entry.confidence, time)
+        })
+    }
+
+    /// Save `result`, unless it is `Unknown`.
+    pub fn insert<L: SynthLanguage>(
+        &self,
+        validator: &str,
+        rule: &Rule<L>,
+        result: &ValidationResult<L>,
+        confidence: f64,
+        time: Duration,
+    ) -> io::Result<()> {
+        let valid = match result {
+            ValidationResult::Valid => true,
+            ValidationResult::Invalid(_) => false,
+            ValidationResult::Unknown(_) => return Ok(()),
+        };
+        let entry = Self::entry(validator, rule, valid, confidence, time);
+        let line = serde_json::to_string(&entry)?;
+        writeln!(self.file.lock().unwrap(), "{}", line)?;
+        self.entries.lock().unwrap().insert(entry.key(), entry);
+        Ok(())
+    }
+
+    fn entry<L: SynthLanguage>(
+        validator: &str,
+        rule: &Rule<L>,
+        valid: bool,
+        confidence: f64,
+        time: Duration,
+    ) -> Entry {
+        Entry {
+            domain: std::any::type_name::<L>().to_string(),
+            version: L::semantics_version(),
+            validator: validator.to_string(),
+            rule: canonical_rule(rule),
+            valid,
+            confidence,
+            time: time.as_secs_f64(),
+        }
+    }
+}
+
+/// `rule` with its variables renamed `?v0`, `?v1`, ... in order of
+/// appearance (keeping sort annotations), so alpha-equivalent rules share
+/// cache entries.
+pub fn canonical_rule<L: SynthLanguage>(rule: &Rule<L>) -> String {
+    let mut renaming: Vec<(Var, Var)> = vec![];
+    let mut rename = |pattern: &Pattern<L>| {
+        let mut ast = PatternAst::default();
+        for node in pattern.ast.as_ref() {
+            ast.add(match node {
+                ENodeOrVar::Var(v) => {
+                    let i = match renaming.iter().position(|(old, _)| old == v) {
+                        Some(i) => i,
+                        None => {
+                            let name = match Sort::of_var(&v.to_string()) {
+                                Some(sort) => format!("?v{}:{}", renaming.len(), sort),
+                                None => format!("?v{}", renaming.len()),
+                            };
+                            renaming.push((*v, name.parse().unwrap()));
+                            renaming.len() - 1
+                        }
+                    };
+                    ENodeOrVar::Var(renaming[i].1)
+                }
+                ENodeOrVar::ENode(n) => ENodeOrVar::ENode(n.clone()),
+            });
+        }
+        Pattern::from(ast)
+    };
+    let lhs = rename(&rule.lhs);
+    let rhs = rename(&rule.rhs);
+    format!("{} ==> {}", lhs, rhs)
+}
@@ -0,0 +1,199 @@
+use std::sync::Arc;
+
+use egg::Searcher;
+
+use crate::{
+    enumo::Ruleset, CVec, Counterexample, EGraph, HashMap, HashSet, Id, Pattern, SynthAnalysis,
+    SynthLanguage, Validation, ValidationResult, Validator,
+};
+
+/// Counterexample-guided refinement of cvec-matched candidates.
+///
+/// Each round validates the candidates that have not been checked yet with
+/// `validator`, in parallel. The counterexamples of the invalid ones become
+/// new environments: every variable's cvec gets the new points, the rest of
+/// the e-graph is re-evaluated, and `cvec_match` proposes a fresh set of
+/// candidates.
+/// Stops after `rounds` rounds or once no new counterexamples turn up.
+///
+/// Returns the final candidates, minus those already known to be invalid.
+/// Candidates that were validated along the way keep their result in
+/// `rule.validation`, so `minimize` does not check them again.
+pub fn refine_candidates<L, F>(
+    egraph: &mut EGraph<L, SynthAnalysis>,
+    mut candidates: Ruleset<L>,
+    rounds: usize,
+    validator: &dyn Validator<L>,
+    cvec_match: F,
+) -> Ruleset<L>
+where
+    L: SynthLanguage,
+    F: Fn(&EGraph<L, SynthAnalysis>) -> Ruleset<L>,
+{
+    let vars = variables(egraph);
+    // Without cvecs on the variables there is nothing to refine
+    if vars.iter().any(|&id| egraph[id].data.cvec.is_empty()) {
+        return candidates;
+    }
+
+    let mut validations: HashMap<Arc<str>, Validation<L>> = HashMap::default();
+    for _ in 0..rounds {
+        let mut unchecked = Ruleset::default();
+        for rule in candidates.iter() {
+            if !validations.contains_key(&rule.name) {
+                unchecked.add(rule.clone());
+            }
+        }
+        let new_validations = unchecked.validate_all(validator);
+
+        let mut envs = vec![];
+        let mut seen = HashSet::default();
+        for rule in unchecked.iter() {
+            if let ValidationResult::Invalid(counterexample) = &new_validations[&rule.name].result {
+                for env in counterexample
+                    .iter()
+                    .flat_map(|cex| environments(egraph, &vars, &rule.lhs, cex))
+                {
+                    if seen.insert(env.clone()) {
+                        envs.push(env);
+                    }
+                }
+            }
+        }
+        validations.extend(new_validations);
+
+        if envs.is_empty() {
+            break;
+        }
+        add_environments(egraph, &vars, &envs);
+        candidates = cvec_match(egraph);
+    }
+
+    candidates.0.retain(|name, _| {
+        !matches!(
+            validations.get(name).map(|v| &v.result),
+            Some(ValidationResult::Invalid(_))
+        )
+    });
+    for rule in candidates.iter_mut() {
+        if let Some(validation) = validations.get(&rule.name) {
+            rule.validation = Some(validation.clone());
+        }
+    }
+    candidates
+}
+
+/// The e-classes holding the e-graph's variables.
+fn variables<L: SynthLanguage>(egraph: &EGraph<L, SynthAnalysis>) -> Vec<Id> {
+    egraph
+        .classes()
+        .filter(|class| class.nodes.iter().any(|n| n.is_var()))
+        .map(|class| class.id)
+        .collect()
+}
+
+/// Turn a counterexample over `lhs`'s pattern variables into environments
+/// over the e-graph's variables, one for each place `lhs` matches with all
+/// its pattern variables bound to variables. Variables the counterexample
+/// does not mention keep their first value.
+fn environments<L: SynthLanguage>(
+    egraph: &EGraph<L, SynthAnalysis>,
+    vars: &[Id],
+    lhs: &Pattern<L>,
+    counterexample: &Counterexample<L>,
+) -> Vec<CVec<L>> {
+    let mut envs = vec![];
+    for matches in lhs.search(egraph) {
+        'subst: for subst in &matches.substs {
+            let mut env: CVec<L> = vars
+                .iter()
+                .map(|&id| egraph[id].data.cvec[0].clone())
+                .collect();
+            let mut bound = vec![false; vars.len()];
+            for (var, value) in counterexample {
+                let id = match subst.get(*var) {
+                    Some(id) => egraph.find(*id),
+                    None => continue 'subst,
+                };
+                match vars.iter().position(|&v| v == id) {
+                    Some(i) if !bound[i] || env[i].as_ref() == Some(value) => {
+                        env[i] = Some(value.clone());
+                        bound[i] = true;
+                    }
+                    _ => continue 'subst,
+                }
+            }
+            envs.push(env);
+        }
+    }
+    envs
+}
+
+/// Append `envs` to the cvecs of the variables in `vars` (each environment
+/// holds one value per variable, in order) and re-evaluate every other
+/// e-class.
+pub fn add_environments<L: SynthLanguage>(
+    egraph: &mut EGraph<L, SynthAnalysis>,
+    vars: &[Id],
+    envs: &[CVec<L>],
+) {
+    egraph.analysis.cvec_len += envs.len();
+    let cvec_len = egraph.analysis.cvec_len;
+
+    let mut cvecs: HashMap<Id, CVec<L>> = HashMap::default();
+    for (i, &id) in vars.iter().enumerate() {
+        let mut cvec = egraph[id].data.cvec.clone();
+        cvec.extend(envs.iter().map(|env| env[i].clone()));
+        cvecs.insert(id, cvec);
+    }
+
+    // Evaluate bottom-up until nothing changes. A class may need several
+    // of its nodes to fill in all of its entries.
+    let mut changed = true;
+    while changed {
+        changed = false;
+        for class in egraph.classes() {
+            if vars.contains(&class.id) {
+                continue;
+            }
+            for node in &class.nodes {
+                if !node.all(|child| cvecs.contains_key(&child)) {
+                    continue;
+                }
+                let cvec = node.eval(cvec_len, |child| &cvecs[child]);
+                if cvec.is_empty() {
+                    continue;
+                }
+                match cvecs.get_mut(&class.id) {
+                    None => {
+                        cvecs.insert(class.id, cvec);
+                        changed = true;
+                    }
+                    Some(old) => {
+                        for (old, new) in old.iter_mut().zip(cvec) {
+                            if old.is_none() && new.is_some() {
+                                *old = new;
+                                changed = true;
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    // Classes none of whose nodes evaluate keep their old entries; pad them
+    // so every non-empty cvec has `cvec_len` entries.
+    let ids: Vec<Id> = egraph.classes().map(|class| class.id).collect();
+    for id in ids {
+        match cvecs.remove(&id) {
+            Some(cvec) => egraph[id].data.cvec = cvec,
+            None => {
+                let cvec = &mut egraph[id].data.cvec;
+                if !cvec.is_empty() {
+                    cvec.resize(cvec_len, None);
+                }
+            }
+        }
+    }
+}
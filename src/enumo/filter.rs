@@ -124,6 +124,22 @@ mod test {
         assert_eq!(actual, expected);
     }
 
+    #[test]
+    fn contains_class() {
+        let wkld = Workload::new([
+            "(+ a a)",
+            "(* a b)",
+            "(~ (* b b))",
+            "(- a (+ a b))",
+            "(f (+ a b) (+ a b))",
+        ]);
+        let actual = wkld
+            .filter(Filter::Contains("({+,-,*} ?x ?x)".parse().unwrap()))
+            .force();
+        let expected = Workload::new(["(+ a a)", "(~ (* b b))"]).force();
+        assert_eq!(actual, expected);
+    }
+
     #[test]
     fn and() {
         let wkld = Workload::new(["x", "y", "(x y)", "(y x)", "(x x x)", "(y y z)", "(x y z)"]);
@@ -0,0 +1,394 @@
+use itertools::Itertools;
+
+use super::*;
+use crate::{HashSet, IndexMap};
+
+/// A grammar with named nonterminals. Nonterminals appear as atoms in the
+/// productions, so `(+ Int Int)` refers to the nonterminal `Int`. Giving each
+/// sort its own nonterminal (e.g. `Bool` and `Int`) means only well-sorted
+/// terms are enumerated.
+#[derive(PartialEq, Eq, Clone, Debug)]
+pub struct Grammar {
+    start: String,
+    productions: IndexMap<String, Vec<Sexp>>,
+}
+
+impl Grammar {
+    pub fn new(start: impl Into<String>) -> Self {
+        Self {
+            start: start.into(),
+            productions: IndexMap::default(),
+        }
+    }
+
+    /// The grammar `iter_metric(wkld, atom, ..)` enumerates: `atom` is the
+    /// only nonterminal and the terms of `wkld` are its productions.
+    pub fn from_workload(atom: impl Into<String>, wkld: &Workload) -> Self {
+        let atom = atom.into();
+        let mut grammar = Self::new(atom.clone());
+        grammar.productions.insert(atom, wkld.force());
+        grammar
+    }
+
+    /// Add productions for `nonterminal`. Can be called more than once for
+    /// the same nonterminal.
+    pub fn production<I>(mut self, nonterminal: impl Into<String>, alternatives: I) -> Self
+    where
+        I: IntoIterator,
+        I::Item: AsRef<str>,
+    {
+        self.productions
+            .entry(nonterminal.into())
+            .or_default()
+            .extend(Workload::new(alternatives).force());
+        self
+    }
+
+    pub fn nonterminals(&self) -> Vec<String> {
+        self.productions.keys().cloned().collect()
+    }
+
+    /// All terms derivable from the start symbol whose `metric` is at most `n`,
+    /// smallest first.
+    pub fn enumerate(&self, metric: Metric, n: usize) -> Workload {
+        self.enumerate_nonterminal(&self.start, metric, n)
+    }
+
+    /// All terms derivable from `nonterminal` whose `metric` is at most `n`,
+    /// smallest first.
+    pub fn enumerate_nonterminal(&self, nonterminal: &str, metric: Metric, n: usize) -> Workload {
+        Workload::Set(
+            self.build(metric, n)
+                .into_iter()
+                .flat_map(|mut level| level.remove(nonterminal).unwrap_or_default())
+                .collect(),
+        )
+    }
+
+    /// All terms derivable from the start symbol whose `metric` is exactly `k`.
+    pub fn enumerate_exact(&self, metric: Metric, k: usize) -> Workload {
+        let mut levels = self.build(metric, k);
+        Workload::Set(levels[k].remove(&self.start).unwrap_or_default())
+    }
+
+    /// The number of terms `enumerate(metric, n)` would produce, computed
+    /// without building them. Assumes every term has a single derivation,
+    /// which holds for grammars like `base_lang`.
+    pub fn count(&self, metric: Metric, n: usize) -> usize {
+        self.counts(metric, n)
+            .iter()
+            .map(|level| level.get(&self.start).copied().unwrap_or(0))
+            .fold(0, usize::saturating_add)
+    }
+
+    // Nonterminal atoms in `template`, in pre-order, along with the depth
+    // they occur at (the root is at depth 1).
+    fn holes(&self, template: &Sexp) -> Vec<(String, usize)> {
+        fn go(g: &Grammar, sexp: &Sexp, depth: usize, holes: &mut Vec<(String, usize)>) {
+            match sexp {
+                Sexp::Atom(a) if g.productions.contains_key(a) => holes.push((a.clone(), depth)),
+                Sexp::Atom(_) => (),
+                Sexp::List(args) => args.iter().for_each(|s| go(g, s, depth + 1, holes)),
+            }
+        }
+        let mut holes = vec![];
+        go(self, template, 1, &mut holes);
+        holes
+    }
+
+    // The measure of `template` ignoring its nonterminals. For Depth, this
+    // is the depth of the deepest terminal atom.
+    fn base_measure(&self, template: &Sexp, metric: Metric) -> usize {
+        fn go(g: &Grammar, sexp: &Sexp, depth: usize, metric: Metric) -> usize {
+            match sexp {
+                Sexp::Atom(a) if g.productions.contains_key(a) => 0,
+                Sexp::Atom(_) => match metric {
+                    Metric::Atoms => 1,
+                    Metric::Lists => 0,
+                    Metric::Depth => depth,
+                },
+                Sexp::List(args) => {
+                    let children = args.iter().map(|s| go(g, s, depth + 1, metric));
+                    match metric {
+                        Metric::Atoms => children.sum(),
+                        Metric::Lists => children.sum::<usize>() + 1,
+                        Metric::Depth => children.max().unwrap_or(depth),
+                    }
+                }
+            }
+        }
+        go(self, template, 1, metric)
+    }
+
+    // Every way of picking sizes for the holes of `template` so that the
+    // filled-in term has size exactly `k`.
+    fn size_tuples(&self, template: &Sexp, metric: Metric, k: usize) -> Vec<Vec<usize>> {
+        let holes = self.holes(template);
+        let base = self.base_measure(template, metric);
+        let fits = |sizes: &[usize]| match metric {
+            Metric::Atoms | Metric::Lists => base + sizes.iter().sum::<usize>() == k,
+            Metric::Depth => {
+                let deepest = holes
+                    .iter()
+                    .zip(sizes)
+                    .map(|((_, depth), size)| depth - 1 + size)
+                    .max()
+                    .unwrap_or(0);
+                base.max(deepest) == k
+            }
+        };
+
+        if holes.is_empty() {
+            return if fits(&[]) { vec![vec![]] } else { vec![] };
+        }
+
+        // Every term has at least one atom and a depth of at least one,
+        // but atoms have no lists.
+        let min = match metric {
+            Metric::Lists => 0,
+            Metric::Atoms | Metric::Depth => 1,
+        };
+        holes
+            .iter()
+            .map(|_| min..(k + 1))
+            .multi_cartesian_product()
+            .filter(|sizes| fits(&sizes[..]))
+            .collect()
+    }
+
+    fn fill<'a>(&self, template: &Sexp, args: &mut impl Iterator<Item = &'a Sexp>) -> Sexp {
+        match template {
+            Sexp::Atom(a) if self.productions.contains_key(a) => args.next().unwrap().clone(),
+            Sexp::Atom(_) => template.clone(),
+            Sexp::List(ts) => Sexp::List(ts.iter().map(|t| self.fill(t, args)).collect()),
+        }
+    }
+
+    // levels[k][nt] holds the terms of size exactly k derivable from nt.
+    // Bottom-up: size k is built by plugging smaller terms into the holes.
+    fn build(&self, metric: Metric, n: usize) -> Vec<IndexMap<String, Vec<Sexp>>> {
+        let mut levels: Vec<IndexMap<String, Vec<Sexp>>> = vec![];
+        for k in 0..(n + 1) {
+            let mut level: IndexMap<String, Vec<Sexp>> = self
+                .productions
+                .keys()
+                .map(|nt| (nt.clone(), vec![]))
+                .collect();
+            let mut seen: IndexMap<String, HashSet<Sexp>> = self
+                .productions
+                .keys()
+                .map(|nt| (nt.clone(), HashSet::default()))
+                .collect();
+
+            // Unit productions (like `A -> B`) build terms of size k out of
+            // other terms of size k, so go around until nothing new shows up
+            loop {
+                let mut grew = false;
+                for (nt, alternatives) in &self.productions {
+                    let mut new_terms = vec![];
+                    for template in alternatives {
+                        let holes = self.holes(template);
+                        for sizes in self.size_tuples(template, metric, k) {
+                            if holes.is_empty() {
+                                new_terms.push(template.clone());
+                                continue;
+                            }
+                            let choices: Vec<&Vec<Sexp>> = holes
+                                .iter()
+                                .zip(&sizes)
+                                .map(|((hole, _), &size)| {
+                                    if size == k {
+                                        &level[hole]
+                                    } else {
+                                        &levels[size][hole]
+                                    }
+                                })
+                                .collect();
+                            for args in choices
+                                .iter()
+                                .map(|terms| terms.iter())
+                                .multi_cartesian_product()
+                            {
+                                new_terms.push(self.fill(template, &mut args.into_iter()));
+                            }
+                        }
+                    }
+                    for term in new_terms {
+                        if seen[nt].insert(term.clone()) {
+                            level[nt].push(term);
+                            grew = true;
+                        }
+                    }
+                }
+                if !grew {
+                    break;
+                }
+            }
+            levels.push(level);
+        }
+        levels
+    }
+
+    // Same recurrence as build, but only keeps track of how many terms
+    // there are.
+    fn counts(&self, metric: Metric, n: usize) -> Vec<IndexMap<String, usize>> {
+        let mut levels: Vec<IndexMap<String, usize>> = vec![];
+        for k in 0..(n + 1) {
+            let mut level: IndexMap<String, usize> =
+                self.productions.keys().map(|nt| (nt.clone(), 0)).collect();
+
+            // A chain of unit productions is at most as long as the number
+            // of nonterminals
+            for _ in 0..(self.productions.len() + 1) {
+                let mut next = IndexMap::default();
+                for (nt, alternatives) in &self.productions {
+                    let mut count = 0usize;
+                    for template in alternatives {
+                        let holes = self.holes(template);
+                        for sizes in self.size_tuples(template, metric, k) {
+                            let ways = holes
+                                .iter()
+                                .zip(&sizes)
+                                .map(|((hole, _), &size)| {
+                                    if size == k {
+                                        level[hole]
+                                    } else {
+                                        levels[size][hole]
+                                    }
+                                })
+                                .fold(1, usize::saturating_mul);
+                            count = count.saturating_add(ways);
+                        }
+                    }
+                    next.insert(nt.clone(), count);
+                }
+                if next == level {
+                    break;
+                }
+                level = next;
+            }
+            levels.push(level);
+        }
+        levels
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::recipe_utils::base_lang;
+
+    fn sorted(terms: Vec<Sexp>) -> Vec<String> {
+        let mut strs: Vec<String> = terms.iter().map(|t| t.to_string()).collect();
+        strs.sort();
+        strs
+    }
+
+    // The plug-and-filter loop `iter_metric` used before it was built on
+    // `Grammar`.
+    fn plug_iter_metric(wkld: Workload, atom: &str, met: Metric, n: usize) -> Workload {
+        let mut pegs = wkld.clone();
+        for i in 1..(n + 1) {
+            pegs = wkld
+                .clone()
+                .plug(atom, &pegs)
+                .filter(Filter::MetricLt(met, i + 1));
+        }
+        pegs
+    }
+
+    #[test]
+    fn matches_iter_metric() {
+        let grammar = Grammar::from_workload("EXPR", &base_lang(2));
+        for metric in [Metric::Atoms, Metric::Lists, Metric::Depth] {
+            for n in 1..4 {
+                assert_eq!(
+                    sorted(grammar.enumerate(metric, n).force()),
+                    sorted(plug_iter_metric(base_lang(2), "EXPR", metric, n).force())
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn exact() {
+        let grammar = Grammar::from_workload("EXPR", &base_lang(2));
+        let expected = Workload::new([
+            "(OP1 (OP1 VAR))",
+            "(OP1 (OP1 VAL))",
+            "(OP2 VAR VAR)",
+            "(OP2 VAR VAL)",
+            "(OP2 VAL VAR)",
+            "(OP2 VAL VAL)",
+        ])
+        .force();
+        assert_eq!(grammar.enumerate_exact(Metric::Atoms, 3).force(), expected);
+    }
+
+    #[test]
+    fn count() {
+        // Same numbers as iter_metric_test and iter_metric_fast
+        let two = Grammar::from_workload("EXPR", &base_lang(2));
+        let atoms = [2, 4, 10, 24, 66, 188, 570];
+        for (i, expected) in atoms.iter().enumerate() {
+            assert_eq!(two.count(Metric::Atoms, i + 1), *expected);
+        }
+        let depth = [2, 8, 74, 5552];
+        for (i, expected) in depth.iter().enumerate() {
+            assert_eq!(two.count(Metric::Depth, i + 1), *expected);
+        }
+        let lists = [8, 38, 224];
+        for (i, expected) in lists.iter().enumerate() {
+            assert_eq!(two.count(Metric::Lists, i + 1), *expected);
+        }
+
+        let three = Grammar::from_workload("EXPR", &base_lang(3));
+        assert_eq!(three.count(Metric::Atoms, 6), 388);
+        assert_eq!(three.enumerate(Metric::Atoms, 6).force().len(), 388);
+    }
+
+    #[test]
+    fn well_sorted() {
+        let grammar = Grammar::new("Int")
+            .production("Int", ["x", "0", "(+ Int Int)", "(select Bool Int Int)"])
+            .production("Bool", ["(< Int Int)", "(! Bool)"]);
+
+        let ints = grammar.enumerate(Metric::Atoms, 6).force();
+        assert_eq!(ints.len(), grammar.count(Metric::Atoms, 6));
+        assert!(ints.contains(&"(select (< x 0) x x)".parse().unwrap()));
+        for term in &ints {
+            if let Sexp::List(args) = term {
+                if args[0] == Sexp::Atom("select".into()) {
+                    let cond = args[1].to_string();
+                    assert!(cond.starts_with("(<") || cond.starts_with("(!"));
+                }
+            }
+        }
+
+        let bools = grammar
+            .enumerate_nonterminal("Bool", Metric::Atoms, 4)
+            .force();
+        let expected = Workload::new([
+            "(< x x)",
+            "(< x 0)",
+            "(< 0 x)",
+            "(< 0 0)",
+            "(! (< x x))",
+            "(! (< x 0))",
+            "(! (< 0 x))",
+            "(! (< 0 0))",
+        ])
+        .force();
+        assert_eq!(bools, expected);
+    }
+
+    #[test]
+    fn unit_productions() {
+        let grammar = Grammar::new("Expr")
+            .production("Expr", ["Atom", "(~ Expr)"])
+            .production("Atom", ["a", "b"]);
+        let expected = Workload::new(["a", "b", "(~ a)", "(~ b)"]).force();
+        assert_eq!(grammar.enumerate(Metric::Atoms, 2).force(), expected);
+        assert_eq!(grammar.count(Metric::Atoms, 2), 4);
+    }
+}
@@ -1,6 +1,7 @@
 use crate::HashMap;
 
 pub use filter::*;
+pub use grammar::*;
 pub use metric::*;
 pub use pattern::*;
 pub use rule::*;
@@ -10,6 +11,7 @@ pub use sexp::*;
 pub use workload::*;
 
 mod filter;
+mod grammar;
 mod metric;
 mod pattern;
 mod rule;
@@ -8,6 +8,15 @@ pub enum Pattern {
     Var(String),
     Lit(String),
     List(Vec<Pattern>),
+    // Matches any remaining arguments of a list. Written `...`, and only
+    // allowed as the last element of a list.
+    Rest,
+    // Matches any atom in the set. Written `{+,-,*}`.
+    Class(Vec<String>),
+    // Matches anything that is not equal to what the variable is bound to.
+    // Written `!?x`. Checked once the whole pattern has matched, so the
+    // variable may be bound before or after this point.
+    Distinct(String),
 }
 
 impl FromStr for Pattern {
@@ -16,15 +25,37 @@ impl FromStr for Pattern {
     fn from_str(s: &str) -> Result<Self, Self::Err> {
         use symbolic_expressions::parser::parse_str;
         let sexp = parse_str(s).unwrap();
-        Ok(Self::from_symbolic_expr(sexp))
+        let pat = Self::from_symbolic_expr(sexp);
+        if pat.rest_is_well_placed() {
+            Ok(pat)
+        } else {
+            Err(format!("'...' must be the last element of a list: {}", s))
+        }
     }
 }
 
 impl Pattern {
+    pub fn class<I>(ops: I) -> Self
+    where
+        I: IntoIterator,
+        I::Item: AsRef<str>,
+    {
+        Self::Class(ops.into_iter().map(|x| x.as_ref().to_string()).collect())
+    }
+
     fn from_symbolic_expr(sexp: symbolic_expressions::Sexp) -> Self {
         match sexp {
             symbolic_expressions::Sexp::String(s) if s == "*" => Self::Wild,
+            symbolic_expressions::Sexp::String(s) if s == "..." => Self::Rest,
             symbolic_expressions::Sexp::String(s) if s.starts_with('?') => Self::Var(s),
+            symbolic_expressions::Sexp::String(s) if s.starts_with("!?") => {
+                Self::Distinct(s[1..].to_string())
+            }
+            symbolic_expressions::Sexp::String(s)
+                if s.len() > 2 && s.starts_with('{') && s.ends_with('}') =>
+            {
+                Self::class(s[1..s.len() - 1].split(','))
+            }
             symbolic_expressions::Sexp::String(s) => Self::Lit(s),
             symbolic_expressions::Sexp::List(ss) => Self::List(
                 ss.iter()
@@ -35,20 +66,49 @@ impl Pattern {
         }
     }
 
+    // `...` stands for the rest of a list, so it can't be a pattern by itself
+    fn rest_is_well_placed(&self) -> bool {
+        match self {
+            Pattern::Rest => false,
+            Pattern::List(pats) => pats.iter().enumerate().all(|(i, pat)| match pat {
+                Pattern::Rest => i + 1 == pats.len(),
+                _ => pat.rest_is_well_placed(),
+            }),
+            _ => true,
+        }
+    }
+
     pub(crate) fn matches(&self, sexp: &Sexp) -> bool {
-        self.matches_with(sexp, Default::default()).is_some()
+        let mut distinct = vec![];
+        match self.matches_with(sexp, Default::default(), &mut distinct) {
+            // A distinct variable that is never bound doesn't constrain anything
+            Some(subst) => distinct
+                .iter()
+                .all(|(pvar, sexp)| subst.get(pvar).map_or(true, |x| !sexp.eq(x))),
+            None => false,
+        }
     }
 
     fn matches_with(
         &self,
         sexp: &Sexp,
         mut subst: HashMap<String, Sexp>,
+        distinct: &mut Vec<(String, Sexp)>,
     ) -> Option<HashMap<String, Sexp>> {
         match self {
-            Pattern::Wild => {
+            Pattern::Wild | Pattern::Rest => {
                 // Wild matches anything
                 Some(subst)
             }
+            Pattern::Distinct(pvar) => {
+                // Defer the check until all variables have been bound
+                distinct.push((pvar.clone(), sexp.clone()));
+                Some(subst)
+            }
+            Pattern::Class(ops) => match sexp {
+                Sexp::Atom(lit) if ops.contains(lit) => Some(subst),
+                _ => None,
+            },
             Pattern::Var(pvar) => {
                 // Variables can match anything but must match consistently
                 if let Some(x) = subst.get(pvar) {
@@ -77,12 +137,16 @@ impl Pattern {
             Pattern::List(pats) => match sexp {
                 Sexp::Atom(_) => None,
                 Sexp::List(args) => {
-                    if pats.len() == args.len() {
+                    let (pats, has_rest) = match pats.split_last() {
+                        Some((Pattern::Rest, init)) => (init, true),
+                        _ => (&pats[..], false),
+                    };
+                    if pats.len() == args.len() || (has_rest && pats.len() <= args.len()) {
                         pats.iter()
                             .zip(args.iter())
                             .fold(Some(subst), |acc, (pat, sexp)| {
                                 if let Some(subst) = acc {
-                                    pat.matches_with(sexp, subst)
+                                    pat.matches_with(sexp, subst, distinct)
                                 } else {
                                     None
                                 }
@@ -127,6 +191,17 @@ mod test {
                 ])
             ])
         );
+        assert_eq!(
+            "({+,-} ?x !?x ...)".parse::<Pattern>().unwrap(),
+            Pattern::List(vec![
+                Pattern::Class(vec!["+".into(), "-".into()]),
+                Pattern::Var("?x".into()),
+                Pattern::Distinct("?x".into()),
+                Pattern::Rest,
+            ])
+        );
+        assert!("(... a)".parse::<Pattern>().is_err());
+        assert!("...".parse::<Pattern>().is_err());
     }
 
     #[test]
@@ -150,4 +225,34 @@ mod test {
             }
         }
     }
+
+    #[test]
+    fn matches_rest() {
+        let pat: Pattern = "(+ a ...)".parse().unwrap();
+        let exprs = Workload::new(["(+)", "(+ a)", "(+ a b)", "(+ a b c)", "(+ b a)", "a"]).force();
+        let expected = vec![false, true, true, true, false, false];
+        for (expr, expected) in exprs.iter().zip(expected) {
+            assert_eq!(pat.matches(expr), expected);
+        }
+    }
+
+    #[test]
+    fn matches_class() {
+        let pat: Pattern = "({+,*} ?x ?x)".parse().unwrap();
+        let exprs = Workload::new(["(+ a a)", "(* b b)", "(- a a)", "(+ a b)", "+"]).force();
+        let expected = vec![true, true, false, false, false];
+        for (expr, expected) in exprs.iter().zip(expected) {
+            assert_eq!(pat.matches(expr), expected);
+        }
+    }
+
+    #[test]
+    fn matches_distinct() {
+        let pat: Pattern = "(+ !?y ?y)".parse().unwrap();
+        let exprs = Workload::new(["(+ a a)", "(+ a b)", "(+ (~ a) a)", "(+ a (~ a))"]).force();
+        let expected = vec![false, true, true, true];
+        for (expr, expected) in exprs.iter().zip(expected) {
+            assert_eq!(pat.matches(expr), expected);
+        }
+    }
 }
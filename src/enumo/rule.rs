@@ -1,6 +1,7 @@
 use egg::{Analysis, Applier, ENodeOrVar, Language, PatternAst, Rewrite, Subst};
 use std::fmt::{Debug, Formatter};
 use std::sync::Arc;
+use std::time::Instant;
 use serde::{Deserialize, Serialize};
 
 use crate::*;
@@ -16,6 +17,9 @@ pub struct Rule<L: SynthLanguage> {
     pub lhs: Pattern<L>,
     pub rhs: Pattern<L>,
     pub rewrite: Rewrite<L, SynthAnalysis>,
+    /// The rule's validation result, if it has been validated, e.g. by
+    /// `refine_candidates` or `Ruleset::minimize`.
+    pub validation: Option<Validation<L>>,
 }
 
 #[derive(Clone, Serialize, Deserialize)]
@@ -63,9 +67,10 @@ impl<L: SynthLanguage> Rule<L> {
                 rewrite: Rewrite::new(
                     format!("{} ==> {}", l_pat, r_pat),
                     l_pat.clone(),
-                    Rhs { rhs: r_pat.clone() },
+                    Rhs::new(&l_pat, &r_pat),
                 )
                 .unwrap(),
+                validation: None,
             };
 
             if s.contains("<=>") {
@@ -75,10 +80,11 @@ impl<L: SynthLanguage> Rule<L> {
                     rhs: l_pat.clone(),
                     rewrite: Rewrite::new(
                         format!("{} ==> {}", r_pat, l_pat),
-                        r_pat,
-                        Rhs { rhs: l_pat },
+                        r_pat.clone(),
+                        Rhs::new(&r_pat, &l_pat),
                     )
                     .unwrap(),
+                    validation: None,
                 };
                 Ok((forwards, Some(backwards)))
             } else {
@@ -92,6 +98,26 @@ impl<L: SynthLanguage> Rule<L> {
 
 struct Rhs<L: SynthLanguage> {
     rhs: Pattern<L>,
+    // Pattern variables with a sort annotation (`?a:int`), which may only
+    // bind e-classes of that sort
+    sorted: Vec<(Var, Sort)>,
+}
+
+impl<L: SynthLanguage> Rhs<L> {
+    fn new(lhs: &Pattern<L>, rhs: &Pattern<L>) -> Self {
+        let mut sorted = vec![];
+        for var in lhs.vars().into_iter().chain(rhs.vars()) {
+            if let Some(sort) = Sort::of_var(&var.to_string()) {
+                if !sorted.contains(&(var, sort)) {
+                    sorted.push((var, sort));
+                }
+            }
+        }
+        Self {
+            rhs: rhs.clone(),
+            sorted,
+        }
+    }
 }
 
 impl<L: SynthLanguage> Applier<L, SynthAnalysis> for Rhs<L> {
@@ -111,11 +137,25 @@ impl<L: SynthLanguage> Applier<L, SynthAnalysis> for Rhs<L> {
             return vec![];
         }
 
+        if self
+            .sorted
+            .iter()
+            .any(|(var, sort)| egraph[subst[*var]].data.sort != *sort)
+        {
+            return vec![];
+        }
+
         let id = apply_pat(self.rhs.ast.as_ref(), egraph, subst);
         if id == matched_id {
             return vec![];
         }
 
+        // Unsorted variables can bind anything, so the two sides may still
+        // have different sorts
+        if egraph[id].data.sort != egraph[matched_id].data.sort {
+            return vec![];
+        }
+
         if !egraph[id].data.is_defined() {
             return vec![];
         }
@@ -128,7 +168,7 @@ impl<L: SynthLanguage> Applier<L, SynthAnalysis> for Rhs<L> {
 impl<L: SynthLanguage> Rule<L> {
     pub fn new(l_pat: &Pattern<L>, r_pat: &Pattern<L>) -> Option<Self> {
         let name = format!("{} ==> {}", l_pat, r_pat);
-        let rhs = Rhs { rhs: r_pat.clone() };
+        let rhs = Rhs::new(l_pat, r_pat);
         let rewrite = Rewrite::new(name.clone(), l_pat.clone(), rhs).ok();
 
         rewrite.map(|rw| Rule {
@@ -136,6 +176,7 @@ impl<L: SynthLanguage> Rule<L> {
             lhs: l_pat.clone(),
             rhs: r_pat.clone(),
             rewrite: rw,
+            validation: None,
         })
     }
 
@@ -158,7 +199,59 @@ impl<L: SynthLanguage> Rule<L> {
     }
 
     pub fn is_valid(&self) -> bool {
-        matches!(L::validate(&self.lhs, &self.rhs), ValidationResult::Valid)
+        self.validate().is_valid()
+    }
+
+    /// Validate with the domain's own `SynthLanguage::validate`.
+    pub fn validate(&self) -> Validation<L> {
+        self.validate_with(&DomainValidator)
+    }
+
+    /// Validate with `validator`, going through `ValidationCache::global`
+    /// if it is set.
+    pub fn validate_with(&self, validator: &dyn Validator<L>) -> Validation<L> {
+        match ValidationCache::global() {
+            Some(cache) => self.validate_cached(validator, cache),
+            None => {
+                let start = Instant::now();
+                let (result, confidence) = validator.validate_with_confidence(&self.lhs, &self.rhs);
+                Validation {
+                    result,
+                    validator: validator.name(),
+                    confidence,
+                    time: start.elapsed(),
+                }
+            }
+        }
+    }
+
+    /// Validate with `validator`, reusing the result saved in `cache` if
+    /// there is one and saving it otherwise. A failed save is reported on
+    /// stderr; the validator's result is returned either way.
+    pub fn validate_cached(
+        &self,
+        validator: &dyn Validator<L>,
+        cache: &ValidationCache,
+    ) -> Validation<L> {
+        let name = validator.name();
+        let (result, confidence, time) = match cache.get(&name, self) {
+            Some(cached) => cached,
+            None => {
+                let start = Instant::now();
+                let (result, confidence) = validator.validate_with_confidence(&self.lhs, &self.rhs);
+                let time = start.elapsed();
+                if let Err(e) = cache.insert(&name, self, &result, confidence, time) {
+                    eprintln!("Failed to write to '{}': {}", cache.path().display(), e);
+                }
+                (result, confidence, time)
+            }
+        };
+        Validation {
+            result,
+            validator: name,
+            confidence,
+            time,
+        }
     }
 }
 
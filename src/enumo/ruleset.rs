@@ -1,4 +1,4 @@
-use egg::{AstSize, EClass, Extractor, RecExpr};
+use egg::{EClass, Extractor, RecExpr};
 use indexmap::map::{IntoIter, Iter, IterMut, Values, ValuesMut};
 use rayon::prelude::{IntoParallelIterator, ParallelIterator};
 use std::{io::Write, sync::Arc};
@@ -7,8 +7,8 @@ use serde_json::{json, Value};
 
 
 use crate::{
-    CVec, DeriveType, EGraph, ExtractableAstSize, HashMap, Id, IndexMap, Limits, Signature,
-    SynthAnalysis, SynthLanguage,
+    CVec, DeriveType, EGraph, ExtractableAstSize, HashMap, Id, IndexMap, Limits, Signature, Sort,
+    SynthAnalysis, SynthLanguage, Validation, Validator,
 };
 
 use super::{Rule, Scheduler};
@@ -194,6 +194,15 @@ impl<L: SynthLanguage> Ruleset<L> {
         (yes, no)
     }
 
+    /// Validate every rule with `validator` in parallel, keyed by rule name.
+    pub fn validate_all(&self, validator: &dyn Validator<L>) -> HashMap<Arc<str>, Validation<L>> {
+        let rules: Vec<&Rule<L>> = self.0.values().collect();
+        rules
+            .into_par_iter()
+            .map(|rule| (rule.name.clone(), rule.validate_with(validator)))
+            .collect()
+    }
+
     pub fn to_file(&self, filename: &str) {
         let mut file = std::fs::File::create(filename)
             .unwrap_or_else(|_| panic!("Failed to open '{}'", filename));
@@ -274,6 +283,32 @@ impl<L: SynthLanguage> Ruleset<L> {
         prior: Ruleset<L>,
         limits: Limits,
     ) -> Self {
+        Self::lift(egraph, prior, limits).0
+    }
+
+    /// Rule lifting and cvec matching on the same e-graph, for domains
+    /// where only some operators can be evaluated. Lifting runs first; the
+    /// cvec matching then runs on the e-graph lifting leaves behind, so
+    /// it only proposes equalities the prior and lifting rules did not
+    /// already prove. Classes with partial cvecs (some entries `None`) are
+    /// still matched on the entries they do have.
+    pub fn hybrid_match(
+        egraph: EGraph<L, SynthAnalysis>,
+        prior: Ruleset<L>,
+        limits: Limits,
+    ) -> Self {
+        let (lifted, eg_final) = Self::lift(egraph, prior, limits);
+        let mut candidates = Self::cvec_match(&eg_final);
+        candidates.extend(lifted);
+        candidates
+    }
+
+    /// `allow_forbid_actual`, also returning the final e-graph.
+    fn lift(
+        egraph: EGraph<L, SynthAnalysis>,
+        prior: Ruleset<L>,
+        limits: Limits,
+    ) -> (Self, EGraph<L, SynthAnalysis>) {
         /*
          * eg_init ┌─────────┐ eg_allowed ┌────────┐ eg_denote ┌────────┐  eg_final
          * ───────►│ allowed ├───────────►│ denote ├──────────►│  all   ├────────►
@@ -296,7 +331,7 @@ impl<L: SynthLanguage> Ruleset<L> {
         let eg_final = Scheduler::Compress(limits).run(&eg_denote, &all_rules);
         candidates.extend(Self::extract_candidates(&eg_denote, &eg_final));
 
-        candidates
+        (candidates, eg_final)
     }
 
     pub fn cvec_match(egraph: &EGraph<L, SynthAnalysis>) -> Self {
@@ -314,41 +349,42 @@ impl<L: SynthLanguage> Ruleset<L> {
             .filter(|x| x.data.cvec.iter().any(|v| v.is_some()))
             .collect();
 
-        let compare = |cvec1: &CVec<L>, cvec2: &CVec<L>| -> bool {
-            for tup in cvec1.iter().zip(cvec2) {
-                match tup {
-                    (Some(a), Some(b)) if a != b => return false,
-                    _ => (),
-                }
-            }
-            true
-        };
         let mut candidates = Ruleset::default();
-        let extract = Extractor::new(egraph, AstSize);
-        let mut by_first: IndexMap<Option<L::Constant>, Vec<Id>> = IndexMap::default();
+        let extract = Extractor::new(egraph, ExtractableAstSize);
+        // Classes of different sorts never match, so bucket by sort as well.
+        // See `SynthLanguage::constant_key` for when bucketing by a rounded
+        // first entry misses a match.
+        let mut by_first: IndexMap<(Sort, Option<L::Constant>), Vec<Id>> = IndexMap::default();
         for class in &not_all_none {
+            let first = class.data.cvec[0].as_ref().map(L::constant_key);
             by_first
-                .entry(class.data.cvec[0].clone())
+                .entry((class.data.sort, first))
                 .or_insert_with(Vec::new)
                 .push(class.id);
         }
 
-        let empty = vec![];
-        let first_none = by_first.get(&None).cloned().unwrap_or(empty);
-
-        for (value, classes) in by_first {
+        for ((sort, value), classes) in &by_first {
             let mut all_classes = classes.clone();
             if value.is_some() {
-                all_classes.extend(first_none.clone());
+                if let Some(first_none) = by_first.get(&(*sort, None)) {
+                    all_classes.extend(first_none);
+                }
             }
 
             for i in 0..all_classes.len() {
                 for j in i + 1..all_classes.len() {
                     let class1 = &egraph[all_classes[i]];
                     let class2 = &egraph[all_classes[j]];
-                    if compare(&class1.data.cvec, &class2.data.cvec) {
-                        let (_, e1) = extract.find_best(class1.id);
-                        let (_, e2) = extract.find_best(class2.id);
+                    if class1.data.excludes(&class2.data) {
+                        continue;
+                    }
+                    if L::cvec_eq(&class1.data.cvec, &class2.data.cvec) {
+                        let (c1, e1) = extract.find_best(class1.id);
+                        let (c2, e2) = extract.find_best(class2.id);
+                        // Only expressible with forbidden ops
+                        if c1 == usize::MAX || c2 == usize::MAX {
+                            continue;
+                        }
                         candidates.add_from_recexprs(&e1, &e2);
                     }
                 }
@@ -370,30 +406,48 @@ impl<L: SynthLanguage> Ruleset<L> {
             "starting fast cvec match with {} eclasses",
             egraph.number_of_classes()
         );
-        let mut by_cvec: IndexMap<&CVec<L>, Vec<Id>> = IndexMap::default();
+        let mut by_cvec: IndexMap<(Sort, CVec<L>), Vec<Id>> = IndexMap::default();
 
         for class in egraph.classes() {
             if class.data.is_defined() {
-                by_cvec.entry(&class.data.cvec).or_default().push(class.id);
+                by_cvec
+                    .entry((class.data.sort, L::cvec_key(&class.data.cvec)))
+                    .or_default()
+                    .push(class.id);
             }
         }
 
         let mut candidates = Ruleset::default();
-        let extract = Extractor::new(egraph, AstSize);
+        let extract = Extractor::new(egraph, ExtractableAstSize);
 
         for ids in by_cvec.values() {
-            let exprs: Vec<_> = ids.iter().map(|&id| extract.find_best(id).1).collect();
-
-            for (idx, e1) in exprs.iter().enumerate() {
-                for e2 in exprs[(idx + 1)..].iter() {
-                    candidates.add_from_recexprs(e1, e2);
+            let exprs: Vec<_> = ids
+                .iter()
+                .map(|&id| (id, extract.find_best(id).1))
+                .collect();
+
+            for (idx, (id1, e1)) in exprs.iter().enumerate() {
+                for (id2, e2) in exprs[(idx + 1)..].iter() {
+                    if !egraph[*id1].data.excludes(&egraph[*id2].data) {
+                        candidates.add_from_recexprs(e1, e2);
+                    }
                 }
             }
         }
         candidates
     }
 
-    fn select(&mut self, step_size: usize, invalid: &mut Ruleset<L>) -> Self {
+    fn select(
+        &mut self,
+        step_size: usize,
+        invalid: &mut Ruleset<L>,
+        validator: &dyn Validator<L>,
+        validations: &HashMap<Arc<str>, Validation<L>>,
+    ) -> Self {
+        let validate = |rule: &Rule<L>| match validations.get(&rule.name) {
+            Some(validation) => validation.clone(),
+            None => rule.validate_with(validator),
+        };
         let mut chosen = Self::default();
         self.0
             .sort_by(|_, rule1, _, rule2| rule1.score().cmp(&rule2.score()));
@@ -402,18 +456,26 @@ impl<L: SynthLanguage> Ruleset<L> {
         let mut selected: Ruleset<L> = Default::default();
         while selected.len() < step_size {
             let popped = self.0.pop();
-            if let Some((_, rule)) = popped {
-                if rule.is_valid() {
+            if let Some((_, mut rule)) = popped {
+                let validation = validate(&rule);
+                if validation.is_valid() {
                     selected.add(rule.clone());
                 } else {
+                    rule.validation = Some(validation);
                     invalid.add(rule.clone());
                 }
 
                 // If reverse direction is also in candidates, add it at the same time
                 let reverse = Rule::new(&rule.rhs, &rule.lhs);
-                if let Some(reverse) = reverse {
-                    if self.contains(&reverse) && reverse.is_valid() {
-                        selected.add(reverse);
+                if let Some(mut reverse) = reverse {
+                    if self.contains(&reverse) {
+                        let validation = validate(&reverse);
+                        if validation.is_valid() {
+                            selected.add(reverse);
+                        } else {
+                            reverse.validation = Some(validation);
+                            invalid.add(reverse);
+                        }
                     } else {
                         invalid.add(reverse);
                     }
@@ -457,12 +519,29 @@ impl<L: SynthLanguage> Ruleset<L> {
         }
     }
 
-    pub fn minimize(&mut self, prior: Ruleset<L>, scheduler: Scheduler) -> (Self, Self) {
+    /// Pick a subset of the candidates that, together with `prior`, derives
+    /// the rest. Candidates `validator` does not find valid are returned
+    /// separately, with their validation recorded. Candidates without a
+    /// recorded `validation` are validated up front with `validate_all`, so
+    /// the selection loop only reads the results.
+    pub fn minimize(
+        &mut self,
+        prior: Ruleset<L>,
+        scheduler: Scheduler,
+        validator: &dyn Validator<L>,
+    ) -> (Self, Self) {
         let mut invalid: Ruleset<L> = Default::default();
         let mut chosen = prior.clone();
         let step_size = 1;
+        let (unvalidated, _) = self.partition(|rule| rule.validation.is_none());
+        let mut validations = unvalidated.validate_all(validator);
+        for rule in self.iter() {
+            if let Some(validation) = &rule.validation {
+                validations.insert(rule.name.clone(), validation.clone());
+            }
+        }
         while !self.is_empty() {
-            let selected = self.select(step_size, &mut invalid);
+            let selected = self.select(step_size, &mut invalid, validator, &validations);
             chosen.extend(selected.clone());
             self.shrink(&chosen, scheduler);
         }
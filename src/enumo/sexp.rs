@@ -2,7 +2,7 @@ use std::str::FromStr;
 
 use super::*;
 
-#[derive(PartialEq, Eq, Clone, Debug)]
+#[derive(PartialEq, Eq, Hash, Clone, Debug)]
 pub enum Sexp {
     Atom(String),
     List(Vec<Self>),
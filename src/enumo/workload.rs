@@ -1,7 +1,7 @@
 use egg::{EGraph, ENodeOrVar, RecExpr};
 
 use super::*;
-use crate::{SynthAnalysis, SynthLanguage};
+use crate::{CvecSampler, SynthAnalysis, SynthLanguage};
 use std::io::Write;
 
 #[derive(PartialEq, Eq, Clone, Debug)]
@@ -54,7 +54,19 @@ impl Workload {
     }
 
     pub fn to_egraph<L: SynthLanguage>(&self) -> EGraph<L, SynthAnalysis> {
-        let mut egraph = EGraph::default();
+        self.to_egraph_with(L::default_sampler())
+    }
+
+    /// Like `to_egraph`, but `sampler` picks the environments the
+    /// variables' cvecs are built from.
+    pub fn to_egraph_with<L: SynthLanguage>(
+        &self,
+        sampler: CvecSampler,
+    ) -> EGraph<L, SynthAnalysis> {
+        let mut egraph = EGraph::new(SynthAnalysis {
+            sampler,
+            ..Default::default()
+        });
         let sexps = self.force();
 
         // Have to find all the variables first so that we can initialize
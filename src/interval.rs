@@ -0,0 +1,516 @@
+use std::{cmp::Ordering, fmt::Display};
+
+use num::{bigint::BigInt, rational::Ratio, CheckedAdd, CheckedDiv, CheckedMul, CheckedSub, Zero};
+
+use egg::Subst;
+
+use crate::{EGraph, Id, SynthAnalysis, SynthLanguage, Var, WideBV, BV};
+
+/// An interval of values. A missing bound is unbounded in that direction.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Interval<T> {
+    pub low: Option<T>,
+    pub high: Option<T>,
+}
+
+impl<T: Ord + Display> Interval<T> {
+    pub fn new(low: Option<T>, high: Option<T>) -> Self {
+        if let (Some(a), Some(b)) = (&low, &high) {
+            assert!(
+                a.le(b),
+                "Invalid interval: low must be less than or equal to high\n{} >= {}",
+                a,
+                b
+            );
+        }
+        Self { low, high }
+    }
+
+    /// The single value in this interval, if there is exactly one.
+    pub fn as_constant(&self) -> Option<&T> {
+        match (&self.low, &self.high) {
+            (Some(low), Some(high)) if low == high => Some(low),
+            _ => None,
+        }
+    }
+
+    pub fn contains(&self, c: &T) -> bool {
+        self.low.as_ref().map_or(true, |low| low <= c)
+            && self.high.as_ref().map_or(true, |high| c <= high)
+    }
+
+    /// Whether no value lies in both intervals. Two e-classes with
+    /// disjoint intervals can never be equal.
+    pub fn is_disjoint(&self, other: &Self) -> bool {
+        let below = |high: &Option<T>, low: &Option<T>| match (high, low) {
+            (Some(high), Some(low)) => high < low,
+            _ => false,
+        };
+        below(&self.high, &other.low) || below(&other.high, &self.low)
+    }
+}
+
+impl<T> Default for Interval<T> {
+    fn default() -> Self {
+        Self {
+            low: None,
+            high: None,
+        }
+    }
+}
+
+/// Arithmetic on interval bounds. Each operation returns `None` when the
+/// result isn't representable (overflow, division by zero); interval
+/// operations then give up on the whole result, which keeps them sound for
+/// domains with wrapping arithmetic.
+pub trait Bound: Clone + Ord + Display {
+    fn bound_zero() -> Self;
+    fn bound_add(&self, other: &Self) -> Option<Self>;
+    fn bound_sub(&self, other: &Self) -> Option<Self>;
+    fn bound_mul(&self, other: &Self) -> Option<Self>;
+    fn bound_div(&self, other: &Self) -> Option<Self>;
+
+    fn bound_neg(&self) -> Option<Self> {
+        Self::bound_zero().bound_sub(self)
+    }
+}
+
+macro_rules! impl_bound {
+    ($($t:ty),*) => {
+        $(impl Bound for $t {
+            fn bound_zero() -> Self {
+                Zero::zero()
+            }
+
+            fn bound_add(&self, other: &Self) -> Option<Self> {
+                CheckedAdd::checked_add(self, other)
+            }
+
+            fn bound_sub(&self, other: &Self) -> Option<Self> {
+                CheckedSub::checked_sub(self, other)
+            }
+
+            fn bound_mul(&self, other: &Self) -> Option<Self> {
+                CheckedMul::checked_mul(self, other)
+            }
+
+            fn bound_div(&self, other: &Self) -> Option<Self> {
+                CheckedDiv::checked_div(self, other)
+            }
+        })*
+    };
+}
+
+impl_bound!(i8, i16, i32, i64, i128, isize, u8, u16, u32, u64, u128, usize, BigInt);
+
+impl<T> Bound for Ratio<T>
+where
+    T: Clone + Display + num::Integer + CheckedAdd + CheckedSub + CheckedMul,
+{
+    fn bound_zero() -> Self {
+        Zero::zero()
+    }
+
+    fn bound_add(&self, other: &Self) -> Option<Self> {
+        CheckedAdd::checked_add(self, other)
+    }
+
+    fn bound_sub(&self, other: &Self) -> Option<Self> {
+        CheckedSub::checked_sub(self, other)
+    }
+
+    fn bound_mul(&self, other: &Self) -> Option<Self> {
+        CheckedMul::checked_mul(self, other)
+    }
+
+    fn bound_div(&self, other: &Self) -> Option<Self> {
+        CheckedDiv::checked_div(self, other)
+    }
+}
+
+// Bitvectors are ordered as unsigned integers, so anything that wraps
+// around is out of bounds.
+impl<const N: u128> Bound for BV<N> {
+    fn bound_zero() -> Self {
+        Self::ZERO
+    }
+
+    fn bound_add(&self, other: &Self) -> Option<Self> {
+        self.0
+            .checked_add(other.0)
+            .filter(|x| *x <= Self::ALL_ONES.0)
+            .map(Self)
+    }
+
+    fn bound_sub(&self, other: &Self) -> Option<Self> {
+        self.0.checked_sub(other.0).map(Self)
+    }
+
+    fn bound_mul(&self, other: &Self) -> Option<Self> {
+        self.0
+            .checked_mul(other.0)
+            .filter(|x| *x <= Self::ALL_ONES.0)
+            .map(Self)
+    }
+
+    fn bound_div(&self, other: &Self) -> Option<Self> {
+        self.0.checked_div(other.0).map(Self)
+    }
+}
+
+impl<const N: u128> Bound for WideBV<N> {
+    fn bound_zero() -> Self {
+        Self::zero()
+    }
+
+    fn bound_add(&self, other: &Self) -> Option<Self> {
+        Some(Self(&self.0 + &other.0)).filter(|x| *x <= Self::all_ones())
+    }
+
+    fn bound_sub(&self, other: &Self) -> Option<Self> {
+        self.0.checked_sub(&other.0).map(Self)
+    }
+
+    fn bound_mul(&self, other: &Self) -> Option<Self> {
+        Some(Self(&self.0 * &other.0)).filter(|x| *x <= Self::all_ones())
+    }
+
+    fn bound_div(&self, other: &Self) -> Option<Self> {
+        self.0.checked_div(&other.0).map(Self)
+    }
+}
+
+/// Apply `op` to two optional bounds. An unbounded input gives an unbounded
+/// output; `Err` means the operation failed on finite bounds.
+fn lift<T: Bound>(
+    a: &Option<T>,
+    b: &Option<T>,
+    op: impl Fn(&T, &T) -> Option<T>,
+) -> Result<Option<T>, ()> {
+    match (a, b) {
+        (Some(a), Some(b)) => op(a, b).map(Some).ok_or(()),
+        _ => Ok(None),
+    }
+}
+
+impl<T: Bound> Interval<T> {
+    pub fn constant(c: T) -> Self {
+        Self::new(Some(c.clone()), Some(c))
+    }
+
+    pub fn is_positive(&self) -> bool {
+        self.low
+            .as_ref()
+            .map_or(false, |low| *low > T::bound_zero())
+    }
+
+    pub fn is_negative(&self) -> bool {
+        self.high
+            .as_ref()
+            .map_or(false, |high| *high < T::bound_zero())
+    }
+
+    pub fn is_nonnegative(&self) -> bool {
+        self.low
+            .as_ref()
+            .map_or(false, |low| *low >= T::bound_zero())
+    }
+
+    pub fn is_nonpositive(&self) -> bool {
+        self.high
+            .as_ref()
+            .map_or(false, |high| *high <= T::bound_zero())
+    }
+
+    pub fn is_nonzero(&self) -> bool {
+        !self.contains(&T::bound_zero())
+    }
+
+    /// The smallest interval containing both.
+    pub fn hull(&self, other: &Self) -> Self {
+        let low = match (&self.low, &other.low) {
+            (Some(a), Some(b)) => Some(a.min(b).clone()),
+            _ => None,
+        };
+        let high = match (&self.high, &other.high) {
+            (Some(a), Some(b)) => Some(a.max(b).clone()),
+            _ => None,
+        };
+        Self::new(low, high)
+    }
+
+    pub fn add(&self, other: &Self) -> Self {
+        let low = lift(&self.low, &other.low, T::bound_add);
+        let high = lift(&self.high, &other.high, T::bound_add);
+        match (low, high) {
+            (Ok(low), Ok(high)) => Self::new(low, high),
+            _ => Self::default(),
+        }
+    }
+
+    pub fn sub(&self, other: &Self) -> Self {
+        let low = lift(&self.low, &other.high, T::bound_sub);
+        let high = lift(&self.high, &other.low, T::bound_sub);
+        match (low, high) {
+            (Ok(low), Ok(high)) => Self::new(low, high),
+            _ => Self::default(),
+        }
+    }
+
+    pub fn neg(&self) -> Self {
+        let neg = |x: &Option<T>| match x {
+            Some(x) => x.bound_neg().map(Some).ok_or(()),
+            None => Ok(None),
+        };
+        match (neg(&self.high), neg(&self.low)) {
+            (Ok(low), Ok(high)) => Self::new(low, high),
+            _ => Self::default(),
+        }
+    }
+
+    pub fn abs(&self) -> Self {
+        if self.is_nonnegative() {
+            self.clone()
+        } else if self.is_nonpositive() {
+            self.neg()
+        } else {
+            // Straddles zero: the largest magnitude comes from one of the ends
+            let high = match (&self.low, &self.high) {
+                (Some(low), Some(high)) => low.bound_neg().map(|low| low.max(high.clone())),
+                _ => None,
+            };
+            Self::new(Some(T::bound_zero()), high)
+        }
+    }
+
+    pub fn mul(&self, other: &Self) -> Self {
+        self.corners(other, |x, y| match (x, y) {
+            (Ext::Fin(x), Ext::Fin(y)) => x.bound_mul(y).map(Ext::Fin),
+            // Give up on zero times an unbounded end: in partial domains the
+            // unbounded side may be undefined, so the product isn't surely 0
+            _ => match x.sign() as i8 * y.sign() as i8 {
+                0 => None,
+                sign => Some(Ext::signed(sign)),
+            },
+        })
+    }
+
+    /// Division by an interval containing zero is unbounded.
+    pub fn div(&self, other: &Self) -> Self {
+        if !other.is_nonzero() {
+            return Self::default();
+        }
+        self.corners(other, |x, y| match (x, y) {
+            (Ext::Fin(x), Ext::Fin(y)) => x.bound_div(y).map(Ext::Fin),
+            (Ext::Fin(_), _) => Some(Ext::Fin(T::bound_zero())),
+            (_, Ext::Fin(_)) => Some(Ext::signed(x.sign() as i8 * y.sign() as i8)),
+            _ => None,
+        })
+    }
+
+    pub fn min(&self, other: &Self) -> Self {
+        let low = match (&self.low, &other.low) {
+            (Some(a), Some(b)) => Some(a.min(b).clone()),
+            _ => None,
+        };
+        let high = match (&self.high, &other.high) {
+            (Some(a), Some(b)) => Some(a.min(b).clone()),
+            (Some(x), None) | (None, Some(x)) => Some(x.clone()),
+            (None, None) => None,
+        };
+        Self::new(low, high)
+    }
+
+    pub fn max(&self, other: &Self) -> Self {
+        let low = match (&self.low, &other.low) {
+            (Some(a), Some(b)) => Some(a.max(b).clone()),
+            (Some(x), None) | (None, Some(x)) => Some(x.clone()),
+            (None, None) => None,
+        };
+        let high = match (&self.high, &other.high) {
+            (Some(a), Some(b)) => Some(a.max(b).clone()),
+            _ => None,
+        };
+        Self::new(low, high)
+    }
+
+    /// `if cond then self else other`, where any nonzero condition is true.
+    pub fn select(cond: &Self, then: &Self, other: &Self) -> Self {
+        if cond.is_nonzero() {
+            then.clone()
+        } else if cond.as_constant() == Some(&T::bound_zero()) {
+            other.clone()
+        } else {
+            then.hull(other)
+        }
+    }
+
+    /// Combine the bounds of two intervals with a monotone `op`: the result
+    /// lies between the smallest and largest of the four corners.
+    fn corners(&self, other: &Self, op: impl Fn(&Ext<T>, &Ext<T>) -> Option<Ext<T>>) -> Self {
+        let (a, b) = (Ext::low(&self.low), Ext::high(&self.high));
+        let (c, d) = (Ext::low(&other.low), Ext::high(&other.high));
+        let vals: Option<Vec<Ext<T>>> = [(&a, &c), (&a, &d), (&b, &c), (&b, &d)]
+            .iter()
+            .map(|(x, y)| op(*x, *y))
+            .collect();
+        match vals {
+            Some(vals) => Self::new(
+                vals.iter().min().and_then(Ext::finite),
+                vals.iter().max().and_then(Ext::finite),
+            ),
+            None => Self::default(),
+        }
+    }
+}
+
+/// A rewrite condition that holds when `pred` accepts the interval of the
+/// e-class bound to `var`, e.g. `interval_cond("?a", Interval::is_nonzero)`.
+pub fn interval_cond<L, P>(
+    var: &str,
+    pred: P,
+) -> impl Fn(&mut EGraph<L, SynthAnalysis>, Id, &Subst) -> bool
+where
+    L: SynthLanguage,
+    P: Fn(&Interval<L::Constant>) -> bool,
+{
+    let var: Var = var.parse().unwrap();
+    move |egraph, _, subst| pred(&egraph[subst[var]].data.interval)
+}
+
+/// A bound extended with the infinities.
+#[derive(PartialEq, Eq, PartialOrd, Ord)]
+enum Ext<T> {
+    NegInf,
+    Fin(T),
+    PosInf,
+}
+
+impl<T: Bound> Ext<T> {
+    fn low(bound: &Option<T>) -> Self {
+        bound.clone().map_or(Ext::NegInf, Ext::Fin)
+    }
+
+    fn high(bound: &Option<T>) -> Self {
+        bound.clone().map_or(Ext::PosInf, Ext::Fin)
+    }
+
+    fn finite(&self) -> Option<T> {
+        match self {
+            Ext::Fin(x) => Some(x.clone()),
+            _ => None,
+        }
+    }
+
+    fn sign(&self) -> Ordering {
+        match self {
+            Ext::NegInf => Ordering::Less,
+            Ext::Fin(x) => x.cmp(&T::bound_zero()),
+            Ext::PosInf => Ordering::Greater,
+        }
+    }
+
+    fn signed(sign: i8) -> Self {
+        match sign.cmp(&0) {
+            Ordering::Less => Ext::NegInf,
+            Ordering::Equal => Ext::Fin(T::bound_zero()),
+            Ordering::Greater => Ext::PosInf,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn iv(low: Option<i64>, high: Option<i64>) -> Interval<i64> {
+        Interval::new(low, high)
+    }
+
+    #[test]
+    fn add_sub() {
+        let a = iv(Some(1), Some(3));
+        let b = iv(Some(-2), Some(5));
+        assert_eq!(a.add(&b), iv(Some(-1), Some(8)));
+        assert_eq!(a.sub(&b), iv(Some(-4), Some(5)));
+        assert_eq!(a.add(&iv(Some(0), None)), iv(Some(1), None));
+        assert_eq!(a.sub(&iv(Some(0), None)), iv(None, Some(3)));
+
+        // Overflow makes the whole interval unbounded
+        let big = iv(Some(0), Some(i64::MAX));
+        assert_eq!(big.add(&a), Interval::default());
+    }
+
+    #[test]
+    fn mul_div() {
+        let a = iv(Some(-2), Some(3));
+        let b = iv(Some(4), Some(5));
+        assert_eq!(a.mul(&b), iv(Some(-10), Some(15)));
+        assert_eq!(a.mul(&a), iv(Some(-6), Some(9)));
+        assert_eq!(b.mul(&iv(Some(1), None)), iv(Some(4), None));
+        assert_eq!(a.mul(&iv(Some(1), None)), Interval::default());
+        assert_eq!(
+            iv(None, Some(-3)).mul(&iv(None, Some(-4))),
+            iv(Some(12), None)
+        );
+        assert_eq!(
+            iv(Some(0), Some(0)).mul(&Interval::default()),
+            Interval::default()
+        );
+
+        assert_eq!(b.div(&iv(Some(1), Some(2))), iv(Some(2), Some(5)));
+        assert_eq!(b.div(&a), Interval::default());
+        assert_eq!(b.div(&iv(Some(2), None)), iv(Some(0), Some(2)));
+        assert_eq!(
+            iv(Some(4), None).div(&iv(Some(-2), Some(-1))),
+            iv(None, Some(-2))
+        );
+    }
+
+    #[test]
+    fn neg_abs() {
+        let a = iv(Some(-2), Some(3));
+        assert_eq!(a.neg(), iv(Some(-3), Some(2)));
+        assert_eq!(a.abs(), iv(Some(0), Some(3)));
+        assert_eq!(iv(Some(-5), Some(-1)).abs(), iv(Some(1), Some(5)));
+        assert_eq!(iv(None, Some(-1)).abs(), iv(Some(1), None));
+        assert_eq!(iv(None, Some(1)).abs(), iv(Some(0), None));
+    }
+
+    #[test]
+    fn min_max_select() {
+        let a = iv(Some(-2), Some(3));
+        let b = iv(Some(1), None);
+        assert_eq!(a.min(&b), iv(Some(-2), Some(3)));
+        assert_eq!(a.max(&b), iv(Some(1), None));
+
+        let t = iv(Some(1), Some(1));
+        let f = Interval::constant(0);
+        assert_eq!(Interval::select(&t, &a, &b), a);
+        assert_eq!(Interval::select(&f, &a, &b), b);
+        assert_eq!(Interval::select(&a, &a, &b), iv(Some(-2), None));
+    }
+
+    #[test]
+    fn predicates() {
+        let a = iv(Some(1), Some(3));
+        let b = iv(Some(4), None);
+        assert!(a.is_disjoint(&b));
+        assert!(!a.is_disjoint(&iv(None, Some(1))));
+        assert!(a.is_positive() && a.is_nonzero());
+        assert!(!iv(Some(0), Some(3)).is_nonzero());
+    }
+
+    #[test]
+    fn bv_wraps() {
+        type B = BV<4>;
+        let a = Interval::new(Some(B::from(3)), Some(B::from(10)));
+        let b = Interval::constant(B::from(4));
+        assert_eq!(a.sub(&b), Interval::default());
+        assert_eq!(
+            a.add(&Interval::constant(B::from(5))),
+            Interval::new(Some(B::from(8)), Some(B::from(15)))
+        );
+        assert_eq!(a.add(&Interval::constant(B::from(6))), Interval::default());
+    }
+}
@@ -0,0 +1,451 @@
+use num::{BigUint, One, ToPrimitive, Zero};
+
+use egg::Subst;
+
+use crate::{EGraph, Id, SynthAnalysis, SynthLanguage, Var};
+
+/// What is known about the bits of a `width`-bit value: the bits set in
+/// `zeros` are surely 0 and the bits set in `ones` are surely 1. The rest
+/// may be either.
+///
+/// Bitvector domains compute these bottom-up through
+/// `SynthLanguage::mk_known_bits`, which folds operations on partially
+/// known operands (`(& ?a 0)`, `(<< (<< ?a 2) 2)` at 4 bits) and lets
+/// `cvec_match` skip classes whose known bits disagree.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct KnownBits {
+    pub width: u32,
+    pub zeros: BigUint,
+    pub ones: BigUint,
+}
+
+fn low_mask(bits: u32) -> BigUint {
+    (BigUint::one() << bits as usize) - 1u32
+}
+
+fn has_bit(x: &BigUint, i: u32) -> bool {
+    !((x >> i as usize) & BigUint::one()).is_zero()
+}
+
+impl KnownBits {
+    pub fn new(width: u32, zeros: BigUint, ones: BigUint) -> Self {
+        assert!(
+            (&zeros & &ones).is_zero(),
+            "Invalid known bits: a bit can't be both 0 and 1\n{:b} & {:b}",
+            zeros,
+            ones
+        );
+        let mask = low_mask(width);
+        Self {
+            width,
+            zeros: zeros & &mask,
+            ones: ones & mask,
+        }
+    }
+
+    /// Nothing is known.
+    pub fn unknown(width: u32) -> Self {
+        Self::new(width, BigUint::zero(), BigUint::zero())
+    }
+
+    pub fn constant(width: u32, c: &BigUint) -> Self {
+        let c = c & low_mask(width);
+        Self::new(width, low_mask(width) ^ &c, c)
+    }
+
+    /// The result of a comparison, which is 1 or 0.
+    pub fn boolean(width: u32) -> Self {
+        Self::new(width, low_mask(width) ^ BigUint::one(), BigUint::zero())
+    }
+
+    fn mask(&self) -> BigUint {
+        low_mask(self.width)
+    }
+
+    /// The bits whose value is known.
+    pub fn known(&self) -> BigUint {
+        &self.zeros | &self.ones
+    }
+
+    /// The value, if every bit is known.
+    pub fn as_constant(&self) -> Option<&BigUint> {
+        if self.known() == self.mask() {
+            Some(&self.ones)
+        } else {
+            None
+        }
+    }
+
+    /// Bit `i`, if it is known.
+    pub fn bit(&self, i: u32) -> Option<bool> {
+        if has_bit(&self.zeros, i) {
+            Some(false)
+        } else if has_bit(&self.ones, i) {
+            Some(true)
+        } else {
+            None
+        }
+    }
+
+    /// The smallest and largest values (as unsigned integers) that agree
+    /// with the known bits.
+    pub fn range(&self) -> (BigUint, BigUint) {
+        (self.ones.clone(), self.mask() ^ &self.zeros)
+    }
+
+    pub fn contains(&self, c: &BigUint) -> bool {
+        (c & &self.zeros).is_zero() && (c & &self.ones) == self.ones
+    }
+
+    /// Whether no value agrees with both. Two e-classes with disjoint known
+    /// bits can never be equal.
+    pub fn is_disjoint(&self, other: &Self) -> bool {
+        self.width != other.width
+            || !(&self.zeros & &other.ones).is_zero()
+            || !(&self.ones & &other.zeros).is_zero()
+    }
+
+    /// Everything known about either, for a value both describe.
+    pub fn meet(&self, other: &Self) -> Self {
+        Self::new(
+            self.width,
+            &self.zeros | &other.zeros,
+            &self.ones | &other.ones,
+        )
+    }
+
+    /// What is known about both, for a value that is one or the other.
+    pub fn join(&self, other: &Self) -> Self {
+        Self::new(
+            self.width,
+            &self.zeros & &other.zeros,
+            &self.ones & &other.ones,
+        )
+    }
+
+    /// How many of the lowest bits are known to be 0.
+    pub fn trailing_zeros(&self) -> u32 {
+        (0..self.width)
+            .take_while(|i| self.bit(*i) == Some(false))
+            .count() as u32
+    }
+
+    /// How many of the highest bits are known to be 0.
+    pub fn leading_zeros(&self) -> u32 {
+        (0..self.width)
+            .rev()
+            .take_while(|i| self.bit(*i) == Some(false))
+            .count() as u32
+    }
+
+    // How many of the lowest bits are known
+    fn trailing_known(&self) -> u32 {
+        (0..self.width)
+            .take_while(|i| self.bit(*i).is_some())
+            .count() as u32
+    }
+
+    pub fn not(&self) -> Self {
+        Self::new(self.width, self.ones.clone(), self.zeros.clone())
+    }
+
+    pub fn and(&self, other: &Self) -> Self {
+        Self::new(
+            self.width,
+            &self.zeros | &other.zeros,
+            &self.ones & &other.ones,
+        )
+    }
+
+    pub fn or(&self, other: &Self) -> Self {
+        Self::new(
+            self.width,
+            &self.zeros & &other.zeros,
+            &self.ones | &other.ones,
+        )
+    }
+
+    pub fn xor(&self, other: &Self) -> Self {
+        let known = self.known() & other.known();
+        let value = &self.ones ^ &other.ones;
+        Self::new(self.width, (&value ^ &known) & &known, value & known)
+    }
+
+    // Adding the smallest possible values gives the bits that may be 1, and
+    // adding the largest gives the bits that may be 0. Wherever both
+    // operands are known and the carries into the bit agree, so does the sum.
+    fn add_carry(&self, other: &Self, carry: bool) -> Self {
+        let mask = self.mask();
+        let carry = BigUint::from(carry as u32);
+        let (min_a, max_a) = self.range();
+        let (min_b, max_b) = other.range();
+        let sum_min = (min_a + min_b + &carry) & &mask;
+        let sum_max = (max_a + max_b + &carry) & &mask;
+
+        let carry_zero = &mask ^ (&sum_max ^ &self.zeros ^ &other.zeros);
+        let carry_one = &sum_min ^ &self.ones ^ &other.ones;
+        let known = self.known() & other.known() & (carry_zero | carry_one);
+        Self::new(self.width, (&mask ^ &sum_min) & &known, sum_min & known)
+    }
+
+    pub fn add(&self, other: &Self) -> Self {
+        self.add_carry(other, false)
+    }
+
+    /// `self + ~other + 1`.
+    pub fn sub(&self, other: &Self) -> Self {
+        self.add_carry(&other.not(), true)
+    }
+
+    pub fn neg(&self) -> Self {
+        Self::constant(self.width, &BigUint::zero()).sub(self)
+    }
+
+    /// The low bits of a product only depend on the low bits of its
+    /// operands, and its trailing zeros add up.
+    pub fn mul(&self, other: &Self) -> Self {
+        if let (Some(a), Some(b)) = (self.as_constant(), other.as_constant()) {
+            return Self::constant(self.width, &(a * b));
+        }
+        let known = low_mask(self.trailing_known().min(other.trailing_known()));
+        let low = ((&self.ones & &known) * (&other.ones & &known)) & &known;
+        let zeros = low_mask(
+            self.trailing_zeros()
+                .saturating_add(other.trailing_zeros())
+                .min(self.width),
+        );
+        Self::new(self.width, (&known ^ &low) | zeros, low)
+    }
+
+    // The shift amount, or `None` if it isn't known. Amounts of at least
+    // the width are `width`.
+    fn amount(&self, width: u32) -> Option<u32> {
+        self.as_constant()
+            .map(|k| k.to_u32().unwrap_or(u32::MAX).min(width))
+    }
+
+    // The smallest the shift amount can be
+    fn min_amount(&self, width: u32) -> u32 {
+        self.ones.to_u32().unwrap_or(u32::MAX).min(width)
+    }
+
+    /// Shift left, shifting in zeros. Shifting by the width or more gives 0.
+    pub fn shl(&self, amount: &Self) -> Self {
+        let w = self.width;
+        match amount.amount(w) {
+            Some(k) => Self::new(
+                w,
+                (&self.zeros << k as usize) | low_mask(k),
+                &self.ones << k as usize,
+            ),
+            None => {
+                let zeros = self
+                    .trailing_zeros()
+                    .saturating_add(amount.min_amount(w))
+                    .min(w);
+                Self::new(w, low_mask(zeros), BigUint::zero())
+            }
+        }
+    }
+
+    /// Logical shift right. Shifting by the width or more gives 0.
+    pub fn lshr(&self, amount: &Self) -> Self {
+        let w = self.width;
+        match amount.amount(w) {
+            Some(k) => Self::new(
+                w,
+                (&self.zeros >> k as usize) | (low_mask(w) ^ low_mask(w - k)),
+                &self.ones >> k as usize,
+            ),
+            None => {
+                let zeros = self
+                    .leading_zeros()
+                    .saturating_add(amount.min_amount(w))
+                    .min(w);
+                Self::new(w, low_mask(w) ^ low_mask(w - zeros), BigUint::zero())
+            }
+        }
+    }
+
+    /// Arithmetic shift right: a negative value is the complement of the
+    /// logical shift of its complement.
+    pub fn ashr(&self, amount: &Self) -> Self {
+        let positive = || self.lshr(amount);
+        let negative = || self.not().lshr(amount).not();
+        match self.bit(self.width - 1) {
+            Some(false) => positive(),
+            Some(true) => negative(),
+            None => positive().join(&negative()),
+        }
+    }
+
+    /// Rotates by `amount` modulo the width.
+    pub fn rotl(&self, amount: &Self) -> Self {
+        let w = self.width;
+        match amount.as_constant() {
+            Some(k) => {
+                let k = (k % w).to_usize().unwrap();
+                let rotate = |x: &BigUint| ((x << k) | (x >> (w as usize - k))) & low_mask(w);
+                Self::new(w, rotate(&self.zeros), rotate(&self.ones))
+            }
+            None => Self::unknown(w),
+        }
+    }
+
+    /// Rotates by `amount` modulo the width.
+    pub fn rotr(&self, amount: &Self) -> Self {
+        let w = self.width;
+        match amount.as_constant() {
+            Some(k) => {
+                let k = BigUint::from(w) - (k % w);
+                self.rotl(&Self::constant(w, &k))
+            }
+            None => Self::unknown(w),
+        }
+    }
+}
+
+/// A rewrite condition that holds when `pred` accepts the known bits of the
+/// e-class bound to `var`, e.g. `known_bits_cond("?a", |k| k.bit(0) ==
+/// Some(false))` for "the low bit of `?a` is 0". Classes without known bits
+/// never satisfy it.
+pub fn known_bits_cond<L, P>(
+    var: &str,
+    pred: P,
+) -> impl Fn(&mut EGraph<L, SynthAnalysis>, Id, &Subst) -> bool
+where
+    L: SynthLanguage,
+    P: Fn(&KnownBits) -> bool,
+{
+    let var: Var = var.parse().unwrap();
+    move |egraph, _, subst| {
+        egraph[subst[var]]
+            .data
+            .known_bits
+            .as_ref()
+            .map_or(false, &pred)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const WIDTH: u32 = 3;
+
+    fn kb(s: &str) -> KnownBits {
+        // Written high bit first, with `?` for unknown bits
+        let mask = |c: char| {
+            let bits: String = s.chars().map(|b| if b == c { '1' } else { '0' }).collect();
+            BigUint::parse_bytes(bits.as_bytes(), 2).unwrap()
+        };
+        KnownBits::new(s.len() as u32, mask('0'), mask('1'))
+    }
+
+    // Every `WIDTH`-bit pattern of known bits
+    fn all() -> Vec<KnownBits> {
+        let mut all = vec![String::new()];
+        for _ in 0..WIDTH {
+            all = all
+                .iter()
+                .flat_map(|s| ["0", "1", "?"].iter().map(move |b| format!("{}{}", s, b)))
+                .collect();
+        }
+        all.iter().map(|s| kb(s)).collect()
+    }
+
+    fn values(k: &KnownBits) -> Vec<u32> {
+        (0..1 << k.width)
+            .filter(|v| k.contains(&BigUint::from(*v)))
+            .collect()
+    }
+
+    // Whether `f` describes every result of `op` on values `a` and `b` agree
+    // with
+    fn check_sound(
+        name: &str,
+        f: impl Fn(&KnownBits, &KnownBits) -> KnownBits,
+        op: impl Fn(u32, u32) -> u32,
+    ) {
+        let mask = (1 << WIDTH) - 1;
+        for a in &all() {
+            for b in &all() {
+                let result = f(a, b);
+                for x in values(a) {
+                    for y in values(b) {
+                        let z = op(x, y) & mask;
+                        assert!(
+                            result.contains(&BigUint::from(z)),
+                            "{} {:?} {:?}: {} {} gives {}",
+                            name,
+                            a,
+                            b,
+                            x,
+                            y,
+                            z
+                        );
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn sound() {
+        let w = WIDTH;
+        let sext = |x: u32| {
+            if x >> (w - 1) == 1 {
+                x | !((1 << w) - 1)
+            } else {
+                x
+            }
+        };
+        let rotl = |x: u32, k: u32| {
+            let k = k % w;
+            (x << k) | (x >> (w - k))
+        };
+        check_sound("not", |a, _| a.not(), |x, _| !x);
+        check_sound("and", KnownBits::and, |x, y| x & y);
+        check_sound("or", KnownBits::or, |x, y| x | y);
+        check_sound("xor", KnownBits::xor, |x, y| x ^ y);
+        check_sound("add", KnownBits::add, |x, y| x + y);
+        check_sound("sub", KnownBits::sub, |x, y| x.wrapping_sub(y));
+        check_sound("neg", |a, _| a.neg(), |x, _| x.wrapping_neg());
+        check_sound("mul", KnownBits::mul, |x, y| x * y);
+        check_sound("shl", KnownBits::shl, |x, k| if k < w { x << k } else { 0 });
+        check_sound(
+            "lshr",
+            KnownBits::lshr,
+            |x, k| if k < w { x >> k } else { 0 },
+        );
+        check_sound("ashr", KnownBits::ashr, |x, k| {
+            (sext(x) as i32 >> k.min(w - 1)) as u32
+        });
+        check_sound("rotl", KnownBits::rotl, rotl);
+        check_sound("rotr", KnownBits::rotr, |x, k| rotl(x, w - k % w));
+    }
+
+    #[test]
+    fn precise() {
+        assert_eq!(kb("1?0").and(&kb("000")), kb("000"));
+        assert_eq!(kb("1?0").or(&kb("001")), kb("1?1"));
+        assert_eq!(kb("1?0").add(&kb("001")), kb("1?1"));
+        assert_eq!(kb("??0").add(&kb("??0")), kb("??0"));
+        assert_eq!(kb("0?1").sub(&kb("001")), kb("0?0"));
+        assert_eq!(kb("??10").mul(&kb("?100")), kb("?000"));
+        assert_eq!(kb("???").shl(&kb("001")), kb("??0"));
+        assert_eq!(kb("??1").shl(&kb("1??")), kb("000"));
+        assert_eq!(kb("1??").lshr(&kb("?1?")), kb("00?"));
+        assert_eq!(kb("1?0").ashr(&kb("001")), kb("11?"));
+        assert_eq!(kb("1?0").rotl(&kb("100")), kb("?01"));
+
+        let k = kb("1?0");
+        assert_eq!(k.as_constant(), None);
+        assert_eq!(kb("101").as_constant(), Some(&BigUint::from(5u32)));
+        assert_eq!(k.range(), (BigUint::from(4u32), BigUint::from(6u32)));
+        assert!(k.is_disjoint(&kb("??1")));
+        assert!(!k.is_disjoint(&kb("?1?")));
+        assert_eq!(k.meet(&kb("?1?")), kb("110"));
+        assert_eq!(k.join(&kb("0?0")), kb("??0"));
+    }
+}
@@ -1,17 +1,21 @@
 use std::{
     fmt::{Debug, Display},
     hash::Hash,
+    time::Duration,
 };
 
 use egg::{
     Analysis, AstSize, CostFunction, DidMerge, ENodeOrVar, FromOp, Language, PatternAst, RecExpr,
 };
+use rand_pcg::Pcg64;
 
 use crate::*;
 
 #[derive(Clone)]
 pub struct SynthAnalysis {
     pub cvec_len: usize,
+    /// Picks the environments domains build their variables' cvecs from.
+    pub sampler: CvecSampler,
 }
 
 #[allow(clippy::derivable_impls)]
@@ -19,21 +23,75 @@ impl Default for SynthAnalysis {
     fn default() -> Self {
         // No cvecs by default. Domains that do cvec matching are responsible
         // for setting the cvec length when they initialize variables.
-        Self { cvec_len: 0 }
+        Self {
+            cvec_len: 0,
+            sampler: CvecSampler::default(),
+        }
+    }
+}
+
+/// The sort of an e-class. Single-sorted domains never see anything but the
+/// default sort; multi-sorted domains assign sorts through
+/// `SynthLanguage::sort_of`.
+///
+/// A variable named `x:int` has sort `int`, and rules keep that annotation
+/// on their pattern variables (`?a:int`) so they only fire on e-classes of
+/// that sort.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Sort(Symbol);
+
+impl Sort {
+    pub fn new(name: &str) -> Self {
+        Self(Symbol::from(name))
+    }
+
+    pub fn name(&self) -> &str {
+        self.0.as_str()
+    }
+
+    /// The sort annotation of a variable name (`x:int`), if any.
+    /// Accepts pattern variables (`?x:int`) too.
+    pub fn of_var(name: &str) -> Option<Self> {
+        name.split_once(':').map(|(_, sort)| Self::new(sort))
+    }
+}
+
+impl Default for Sort {
+    fn default() -> Self {
+        Self::new("*")
+    }
+}
+
+impl Display for Sort {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.0)
     }
 }
 
 #[derive(Debug, Clone)]
 pub struct Signature<L: SynthLanguage> {
+    pub sort: Sort,
     pub cvec: CVec<L>,
     pub simplest: RecExpr<L>,
     pub interval: Interval<L::Constant>,
+    /// Only bitvector domains know anything about their bits.
+    pub known_bits: Option<KnownBits>,
 }
 
 impl<L: SynthLanguage> Signature<L> {
     pub fn is_defined(&self) -> bool {
         self.cvec.is_empty() || self.cvec.iter().any(|v| v.is_some())
     }
+
+    /// Whether the intervals or known bits rule out the two classes ever
+    /// being equal. Both analyses are sound, so such classes never are.
+    pub fn excludes(&self, other: &Self) -> bool {
+        let disjoint_bits = match (&self.known_bits, &other.known_bits) {
+            (Some(a), Some(b)) => a.is_disjoint(b),
+            _ => false,
+        };
+        disjoint_bits || self.interval.is_disjoint(&other.interval)
+    }
 }
 
 impl<L: SynthLanguage> Analysis<L> for SynthAnalysis {
@@ -42,7 +100,9 @@ impl<L: SynthLanguage> Analysis<L> for SynthAnalysis {
     fn make(egraph: &EGraph<L, Self>, enode: &L) -> Self::Data {
         let get_cvec = |id: &Id| &egraph[*id].data.cvec;
         let get_interval = |id: &Id| &egraph[*id].data.interval;
+        let get_known_bits = |id: &Id| &egraph[*id].data.known_bits;
         let get_simplest = |i: &Id| &egraph[*i].data.simplest;
+        let get_sort = |id: &Id| &egraph[*id].data.sort;
 
         let simplest = if enode.is_var() || enode.is_constant() {
             let mut rec = RecExpr::<L>::default();
@@ -68,8 +128,10 @@ impl<L: SynthLanguage> Analysis<L> for SynthAnalysis {
         };
 
         Signature {
+            sort: enode.sort_of(get_sort),
             cvec: enode.eval(egraph.analysis.cvec_len, get_cvec),
             interval: enode.mk_interval(get_interval),
+            known_bits: enode.mk_known_bits(get_known_bits),
             simplest,
         }
     }
@@ -79,7 +141,15 @@ impl<L: SynthLanguage> Analysis<L> for SynthAnalysis {
         let mut merge_b = false;
         let cost_fn = |x: &RecExpr<L>| ExtractableAstSize.cost_rec(x);
 
-        if !to.cvec.is_empty() && !from.cvec.is_empty() {
+        assert_eq!(to.sort, from.sort, "sorts do not match!!");
+
+        // Cvec-less classes take on the other side's cvec
+        if to.cvec.is_empty() && !from.cvec.is_empty() {
+            to.cvec = from.cvec.clone();
+            merge_a = true;
+        } else if !to.cvec.is_empty() && from.cvec.is_empty() {
+            merge_b = true;
+        } else if !to.cvec.is_empty() && !from.cvec.is_empty() {
             for i in 0..to.cvec.len() {
                 match (to.cvec[i].clone(), from.cvec[i].clone()) {
                     (None, Some(_)) => {
@@ -126,6 +196,22 @@ impl<L: SynthLanguage> Analysis<L> for SynthAnalysis {
             merge_b = true;
         }
 
+        // Known bits add up
+        let new_known_bits = match (&to.known_bits, &from.known_bits) {
+            (Some(x), Some(y)) => Some(x.meet(y)),
+            (None, y) => y.clone(),
+            (x, None) => x.clone(),
+        };
+
+        if to.known_bits != new_known_bits {
+            to.known_bits = new_known_bits;
+            merge_a = true;
+        }
+
+        if to.known_bits != from.known_bits {
+            merge_b = true;
+        }
+
         DidMerge(merge_a, merge_b)
     }
 
@@ -151,7 +237,7 @@ pub type CVec<L> = Vec<Option<<L as SynthLanguage>::Constant>>;
 pub type Constant<L> = <L as SynthLanguage>::Constant;
 
 pub trait SynthLanguage: Language + Send + Sync + Display + FromOp +  'static {
-    type Constant: Clone + Hash + Eq + Debug + Display + Ord;
+    type Constant: Clone + Hash + Eq + Debug + Display + Ord + Send + Sync;
 
     // Overrideable hook into the egraph analysis modify method
     // for language-specific purposes (such as custom constant folding)
@@ -161,6 +247,35 @@ pub trait SynthLanguage: Language + Send + Sync + Display + FromOp +  'static {
     where
         F: FnMut(&'a Id) -> &'a CVec<Self>;
 
+    /// Whether two cvecs agree, for `cvec_match`. Undefined (`None`)
+    /// entries agree with anything. Domains with inexact constants, like
+    /// floats, override this with an approximate comparison such as
+    /// `approx_eq`.
+    fn cvec_eq(cvec1: &CVec<Self>, cvec2: &CVec<Self>) -> bool {
+        cvec_eq_by(cvec1, cvec2, |a, b| a == b)
+    }
+
+    /// The key `fast_cvec_match` groups cvecs by. Defaults to
+    /// `constant_key` of every entry.
+    fn cvec_key(cvec: &CVec<Self>) -> CVec<Self> {
+        cvec.iter()
+            .map(|c| c.as_ref().map(Self::constant_key))
+            .collect()
+    }
+
+    /// The key of a single cvec entry; `cvec_match` buckets cvecs by the
+    /// key of their first entry. Constants that `cvec_eq` treats as equal
+    /// should get the same key, e.g. by rounding to a fixed precision with
+    /// `round_sig`.
+    ///
+    /// Rounding can't always manage that: two values within `cvec_eq`'s
+    /// tolerance of each other may still round to different keys if they
+    /// straddle a rounding boundary, and then the two cvecs are never
+    /// compared. Such pairs are missed rather than mismatched.
+    fn constant_key(c: &Self::Constant) -> Self::Constant {
+        c.clone()
+    }
+
     fn mk_interval<'a, F>(&'a self, _get_interval: F) -> Interval<Self::Constant>
     where
         F: FnMut(&'a Id) -> &'a Interval<Self::Constant>,
@@ -168,7 +283,86 @@ pub trait SynthLanguage: Language + Send + Sync + Display + FromOp +  'static {
         Interval::default()
     }
 
-    fn initialize_vars(egraph: &mut EGraph<Self, SynthAnalysis>, vars: &[String]);
+    /// What is known about the bits of this node's value, given what is
+    /// known about its children's. Bitvector domains use this for folding
+    /// (see `custom_modify`), pruning in `cvec_match` and `known_bits_cond`
+    /// preconditions.
+    fn mk_known_bits<'a, F>(&'a self, _get_known_bits: F) -> Option<KnownBits>
+    where
+        F: FnMut(&'a Id) -> &'a Option<KnownBits>,
+    {
+        None
+    }
+
+    /// The sort of this node, given the sorts of its children.
+    /// By default, variables take the sort they are annotated with
+    /// (`x:int`) and everything else has the default sort.
+    fn sort_of<'a, F>(&'a self, _get_sort: F) -> Sort
+    where
+        F: FnMut(&'a Id) -> &'a Sort,
+    {
+        self.to_var()
+            .and_then(|v| Sort::of_var(v.as_str()))
+            .unwrap_or_default()
+    }
+
+    /// Values a variable of `sort` ranges over when building cvecs (in the
+    /// default `initialize_vars`) and when sampling in `RandomTesting`.
+    fn sort_values(_sort: &Sort) -> Vec<Self::Constant> {
+        vec![]
+    }
+
+    /// A fresh random value of `sort`, which `RandomTesting` draws on top
+    /// of the `sort_values`. `None` if the domain can't draw one.
+    fn random_value(_sort: &Sort, _rng: &mut Pcg64) -> Option<Self::Constant> {
+        None
+    }
+
+    /// Values of `sort` that `Sampling::EdgeCases` always tries.
+    /// Only used by the default `initialize_vars`.
+    fn sort_edge_cases(_sort: &Sort) -> Vec<Self::Constant> {
+        vec![]
+    }
+
+    /// Every value of `sort`, for sorts small enough to enumerate.
+    /// Enables the `Exhaustive` validator.
+    fn all_values(_sort: &Sort) -> Option<Vec<Self::Constant>> {
+        None
+    }
+
+    /// Floating-point semantics of this node given its children's values,
+    /// which `RandomTesting` falls back on when `eval` can't handle every
+    /// op (e.g. transcendental functions). Non-finite results count as
+    /// undefined. `None` if the node has no floating-point meaning.
+    fn eval_f64(&self, _args: &[f64]) -> Option<f64> {
+        None
+    }
+
+    /// `c` as a float, for feeding variables to `eval_f64`.
+    fn constant_to_f64(_c: &Self::Constant) -> Option<f64> {
+        None
+    }
+
+    /// The sampler `Workload::to_egraph` builds cvecs with.
+    fn default_sampler() -> CvecSampler {
+        CvecSampler::default()
+    }
+
+    /// Add the variables to the egraph and give them cvecs.
+    /// The default lets the egraph's sampler pick environments from each
+    /// variable's `sort_values`, so variables of different sorts share one
+    /// set of environments.
+    fn initialize_vars(egraph: &mut EGraph<Self, SynthAnalysis>, vars: &[String]) {
+        let domains: Vec<Domain<Self::Constant>> = vars
+            .iter()
+            .map(|v| {
+                let sort = Self::mk_var(Symbol::from(v)).sort_of(|_| unreachable!());
+                Domain::new(Self::sort_values(&sort)).with_edge_cases(Self::sort_edge_cases(&sort))
+            })
+            .collect();
+        let sampler = egraph.analysis.sampler;
+        sampler.initialize(egraph, vars, &domains);
+    }
 
     fn to_var(&self) -> Option<Symbol>;
     fn mk_var(sym: Symbol) -> Self;
@@ -192,7 +386,9 @@ pub trait SynthLanguage: Language + Send + Sync + Display + FromOp +  'static {
     }
 
     // Configures whether to run rule lifting or cvec algorithm for
-    // finding candidates.
+    // finding candidates. Domains that can evaluate some but not all of
+    // their ops can use both (see Ruleset::hybrid_match), returning None
+    // from eval for the ops they cannot evaluate.
     // If rule lifting is enabled, L::get_lifting_rewrites() and L::is_allowed_op()
     // must be implemented
     fn is_rule_lifting() -> bool {
@@ -209,6 +405,17 @@ pub trait SynthLanguage: Language + Send + Sync + Display + FromOp +  'static {
         true
     }
 
+    /// The cost of this node given the costs of its children, used to pick
+    /// the representative terms that become rule sides (see
+    /// `ExtractableAstSize`). Lower is better. The default is AST size;
+    /// domains can override it to, say, penalize divisions.
+    fn node_cost<C>(&self, mut costs: C) -> usize
+    where
+        C: FnMut(Id) -> usize,
+    {
+        self.fold(1, |sum, id| sum.saturating_add(costs(id)))
+    }
+
     fn is_allowed_rewrite(lhs: &Pattern<Self>, rhs: &Pattern<Self>) -> bool {
         let pattern_is_extractable = |pat: &Pattern<Self>| {
             pat.ast.as_ref().iter().all(|n| match n {
@@ -223,9 +430,14 @@ pub trait SynthLanguage: Language + Send + Sync + Display + FromOp +  'static {
         let mut rename_node = |node: &Self| match node.to_var() {
             Some(sym) => {
                 let len = map.len();
-                let var = map
-                    .entry(sym)
-                    .or_insert_with(|| format!("?{}", letter(len)).parse().unwrap());
+                let sort = node.sort_of(|_| unreachable!());
+                let var = map.entry(sym).or_insert_with(|| {
+                    if sort == Sort::default() {
+                        format!("?{}", letter(len)).parse().unwrap()
+                    } else {
+                        format!("?{}:{}", letter(len), sort).parse().unwrap()
+                    }
+                });
                 let s = var.to_string();
                 Self::mk_var(s[1..].into())
             }
@@ -293,5 +505,46 @@ pub trait SynthLanguage: Language + Send + Sync + Display + FromOp +  'static {
         ]
     }
 
-    fn validate(lhs: &Pattern<Self>, rhs: &Pattern<Self>) -> ValidationResult;
+    fn validate(lhs: &Pattern<Self>, rhs: &Pattern<Self>) -> ValidationResult<Self>;
+
+    /// Bump when a change to the domain (its operators' meaning or its
+    /// validation) makes old validation results wrong, to retire them from
+    /// the `ValidationCache`.
+    fn semantics_version() -> u32 {
+        0
+    }
+
+    /// Check the rule with an SMT solver, giving up after `timeout`. Used by
+    /// the `Smt` validator. Domains with an SMT encoding implement this and
+    /// have `validate` call it with `SMT_TIMEOUT`; the default ignores the
+    /// timeout and falls back to `validate`.
+    fn validate_smt(
+        lhs: &Pattern<Self>,
+        rhs: &Pattern<Self>,
+        _timeout: Duration,
+    ) -> ValidationResult<Self> {
+        Self::validate(lhs, rhs)
+    }
+
+    /// The SMT-LIB2 term for this node given its children's terms, for the
+    /// `SmtLib` validator. `None` if the node has no encoding.
+    fn smtlib_term(&self, _args: &[String]) -> Option<String> {
+        None
+    }
+
+    /// The SMT-LIB2 sort of variables of `sort`.
+    fn smtlib_sort(_sort: &Sort) -> Option<String> {
+        None
+    }
+
+    /// The logic `SmtLib` queries are declared in.
+    fn smtlib_logic() -> &'static str {
+        "ALL"
+    }
+
+    /// Read back a value the solver printed in a model, e.g. `#b0101`,
+    /// for counterexamples.
+    fn constant_from_smtlib(_value: &str) -> Option<Self::Constant> {
+        None
+    }
 }
@@ -1,22 +1,40 @@
 use std::{
     fmt::{self, Display},
     hash::BuildHasherDefault,
+    time::Duration,
 };
 use serde::{Serialize};
 
 
 pub use bv::*;
+pub use cache::*;
+pub use cegis::*;
 use enumo::{Ruleset, Scheduler};
+pub use interval::*;
+pub use known_bits::*;
 pub use language::*;
+pub use mixed_bv::*;
+pub use ruler_derive::define_synth_language;
+pub use sampler::*;
+pub use smtlib::*;
 pub use util::*;
+pub use validator::*;
 
 
 mod bv;
+mod cache;
+mod cegis;
 pub mod enumo;
+mod interval;
+mod known_bits;
 mod language;
 pub mod logger;
+mod mixed_bv;
 pub mod recipe_utils;
+mod sampler;
+mod smtlib;
 mod util;
+mod validator;
 
 pub type Id = egg::Id;
 pub type Symbol = egg::Symbol;
@@ -33,13 +51,101 @@ pub type IndexMap<K, V> = indexmap::IndexMap<K, V, BuildHasherDefault<rustc_hash
 
 /// Validation result
 #[derive(Debug, Clone)]
-pub enum ValidationResult {
+pub enum ValidationResult<L: SynthLanguage> {
     Valid,
-    Invalid,
-    Unknown,
+    /// The rule does not hold. Validators that can find one also report a
+    /// counterexample, which is used to refine the cvecs.
+    Invalid(Option<Counterexample<L>>),
+    Unknown(UnknownReason),
 }
 
-// Cost function for ast size in the domain
+/// An assignment to a rule's pattern variables under which its two sides
+/// differ.
+pub type Counterexample<L> = Vec<(Var, <L as SynthLanguage>::Constant)>;
+
+/// Why a validator could not decide a rule.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum UnknownReason {
+    Timeout,
+    /// The solver gave up, e.g. on nonlinear arithmetic.
+    IncompleteTheory(String),
+    /// The rule uses an operator the validator has no encoding for.
+    UnsupportedOp(String),
+    Other(String),
+}
+
+impl UnknownReason {
+    /// Classify the reason z3 gives for its last `unknown` answer.
+    pub fn from_z3(solver: &z3::Solver) -> Self {
+        let reason = solver.get_reason_unknown().unwrap_or_default();
+        if reason.contains("timeout") || reason.contains("canceled") {
+            Self::Timeout
+        } else if reason.contains("incomplete") {
+            Self::IncompleteTheory(reason)
+        } else {
+            Self::Other(reason)
+        }
+    }
+}
+
+impl Display for UnknownReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Timeout => write!(f, "timeout"),
+            Self::IncompleteTheory(reason) => write!(f, "incomplete theory: {}", reason),
+            Self::UnsupportedOp(op) => write!(f, "unsupported operator `{}`", op),
+            Self::Other(reason) => write!(f, "{}", reason),
+        }
+    }
+}
+
+impl<L: SynthLanguage> Display for ValidationResult<L> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Valid => write!(f, "valid"),
+            Self::Invalid(None) => write!(f, "invalid"),
+            Self::Invalid(Some(counterexample)) => {
+                let assignment: Vec<String> = counterexample
+                    .iter()
+                    .map(|(var, c)| format!("{} = {}", var, c))
+                    .collect();
+                write!(f, "invalid when {}", assignment.join(", "))
+            }
+            Self::Unknown(reason) => write!(f, "unknown ({})", reason),
+        }
+    }
+}
+
+/// A `ValidationResult` together with where it came from.
+#[derive(Debug, Clone)]
+pub struct Validation<L: SynthLanguage> {
+    pub result: ValidationResult<L>,
+    /// Which validator produced the result.
+    pub validator: String,
+    /// How sure the validator is of the result, from 0 to 1. Exact
+    /// validators are always sure of `Valid` and `Invalid`.
+    pub confidence: f64,
+    pub time: Duration,
+}
+
+impl<L: SynthLanguage> Validation<L> {
+    pub fn is_valid(&self) -> bool {
+        matches!(self.result, ValidationResult::Valid)
+    }
+}
+
+impl<L: SynthLanguage> Display for Validation<L> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.result)?;
+        if self.confidence < 1.0 {
+            write!(f, " (confidence {:.3})", self.confidence)?;
+        }
+        write!(f, " [{}, {:?}]", self.validator, self.time)
+    }
+}
+
+// Cost function for picking representative terms: the domain's
+// SynthLanguage::node_cost (ast size by default)
 // Penalizes ops not in the domain
 pub struct ExtractableAstSize;
 impl<L: SynthLanguage> egg::CostFunction<L> for ExtractableAstSize {
@@ -49,7 +155,7 @@ impl<L: SynthLanguage> egg::CostFunction<L> for ExtractableAstSize {
         C: FnMut(Id) -> Self::Cost,
     {
         if enode.is_allowed_op() {
-            enode.fold(1, |sum, id| sum.saturating_add(costs(id)))
+            enode.node_cost(costs)
         } else {
             usize::max_value()
         }
@@ -98,35 +204,6 @@ impl Limits {
     }
 }
 
-#[derive(Debug, Clone, PartialEq, Eq)]
-pub struct Interval<T> {
-    pub low: Option<T>,
-    pub high: Option<T>,
-}
-
-impl<T: Ord + Display> Interval<T> {
-    pub fn new(low: Option<T>, high: Option<T>) -> Self {
-        if let (Some(a), Some(b)) = (&low, &high) {
-            assert!(
-                a.le(b),
-                "Invalid interval: low must be less than or equal to high\n{} >= {}",
-                a,
-                b
-            );
-        }
-        Self { low, high }
-    }
-}
-
-impl<T> Default for Interval<T> {
-    fn default() -> Self {
-        Self {
-            low: None,
-            high: None,
-        }
-    }
-}
-
 pub struct Phase<L: SynthLanguage> {
     pub rules: Ruleset<L>,
     pub rules_name: String,
@@ -182,7 +259,7 @@ impl SynthLanguage for egg::SymbolLang {
         }
     }
 
-    fn validate(_lhs: &Pattern<Self>, _rhs: &Pattern<Self>) -> ValidationResult {
-        ValidationResult::Invalid
+    fn validate(_lhs: &Pattern<Self>, _rhs: &Pattern<Self>) -> ValidationResult<Self> {
+        ValidationResult::Invalid(None)
     }
 }
@@ -1,44 +1,76 @@
 use std::{
-    fs::{self, OpenOptions, File},
+    fs::{self, OpenOptions},
+    io::{self, Write},
+    path::{Path, PathBuf},
     time::{Duration, Instant},
 };
 
 use serde_json::{json, Value};
 
-use crate::{count_lines, enumo::Ruleset, DeriveType, Limits, Phase, SynthLanguage};
+use crate::{
+    count_lines, enumo::Ruleset, port_ruleset, BvLanguage, DeriveType, DomainValidator, Limits,
+    Phase, SynthLanguage,
+};
 
-/**
- * Adds a JSON object to the nightly data
- * The file is an array of JSON objects, so this function
- * parses the file into an array, adds the new JSON objec to
- * the array, and writes it back to the file
- */
-fn add_json_to_file(json: Value, path: Option<&str>) {
-    let path = match path {
-        Some(p) => p,
-        None => "nightly/data/output.json"
-    };
-    std::fs::create_dir_all("nightly/data").unwrap_or_else(|e| panic!("Error creating dir: {}", e));
+/// Where the nightly data goes. Rows are appended to `output.jsonl` in
+/// `dir`, one JSON object per line, so each row costs a single write and
+/// the rows of a run that crashes halfway are kept.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Logger {
+    dir: PathBuf,
+}
 
-    OpenOptions::new()
-        .read(true)
-        .write(true)
-        .create(true)
-        .open(path)
-        .expect("Unable to open or create file");
+impl Default for Logger {
+    fn default() -> Self {
+        Self::nightly()
+    }
+}
 
-    let s = fs::read_to_string(path).expect("Unable to read file");
+impl Logger {
+    pub fn new(dir: impl AsRef<Path>) -> Self {
+        Self {
+            dir: dir.as_ref().to_path_buf(),
+        }
+    }
 
-    let mut contents: Vec<Value> = if s.is_empty() {
-        vec![]
-    } else {
-        serde_json::from_str(&s).expect("Unable to parse json")
-    };
+    /// The directory `infra/nightly.sh` publishes.
+    pub fn nightly() -> Self {
+        Self::new("nightly/data")
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
 
-    contents.push(json);
+    pub fn path(&self) -> PathBuf {
+        self.dir.join("output.jsonl")
+    }
 
-    std::fs::write(path, serde_json::to_string_pretty(&contents).unwrap())
-        .expect("Unable to write to json file");
+    /// Add `row` to the end of the output, creating it if needed.
+    pub fn append(&self, row: &Value) -> io::Result<()> {
+        fs::create_dir_all(&self.dir)?;
+        let mut file = OpenOptions::new()
+            .append(true)
+            .create(true)
+            .open(self.path())?;
+        // One write per row, so rows from parallel tests don't interleave
+        let line = serde_json::to_string(row)? + "\n";
+        file.write_all(line.as_bytes())
+    }
+
+    /// Every row written so far, oldest first.
+    pub fn rows(&self) -> io::Result<Vec<Value>> {
+        let contents = match fs::read_to_string(self.path()) {
+            Ok(contents) => contents,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(vec![]),
+            Err(e) => return Err(e),
+        };
+        contents
+            .lines()
+            .filter(|line| !line.trim().is_empty())
+            .map(|line| serde_json::from_str(line).map_err(io::Error::from))
+            .collect()
+    }
 }
 
 /**
@@ -52,14 +84,13 @@ fn add_json_to_file(json: Value, path: Option<&str>) {
  * derivability: JSON object containing dervability in both directions for both derive types
  */
 pub fn write_baseline<L: SynthLanguage>(
+    logger: &Logger,
     ruleset: &Ruleset<L>,
     spec_name: &str,
     baseline: &Ruleset<L>,
     baseline_name: &str,
     time: Duration,
-    // TODO JB: add a filepath s.t. logging can be more customizable.. tbd
-    // filepath: Option<File>
-) {
+) -> io::Result<()> {
     // Items in this list will *not* run derivability
     // Format is (a, b) where a and b are spec/baseline names
     // and a.derive(b) will *not* run.
@@ -105,7 +136,7 @@ pub fn write_baseline<L: SynthLanguage>(
       })
     });
 
-    add_json_to_file(row, None)
+    logger.append(&row)
 }
 
 /**
@@ -116,15 +147,16 @@ pub fn write_baseline<L: SynthLanguage>(
  * derivability: JSON object containing dervability for both derive types
  * (using from_bv4 rules to derive direct_gen rules)
  */
-pub fn write_bv_derivability<L: SynthLanguage>(
+pub fn write_bv_derivability<F: BvLanguage, L: BvLanguage>(
+    logger: &Logger,
     domain: &str,
     gen_rules: Ruleset<L>,
     gen_time: Duration,
-    ported_bv4_rules: Ruleset<L>,
-) {
-    // Validate bv4 rules for this domain
+    bv4_rules: &Ruleset<F>,
+) -> io::Result<()> {
+    // Port the bv4 rules to this domain and keep the ones that still hold
     let start = Instant::now();
-    let (sound_bv4, _) = ported_bv4_rules.partition(|rule| rule.is_valid());
+    let (sound_bv4, unsound_bv4) = port_ruleset(bv4_rules, &DomainValidator);
     let validate_time = start.elapsed();
 
     // Compute derivability
@@ -146,7 +178,7 @@ pub fn write_bv_derivability<L: SynthLanguage>(
         "time": derive_time.as_secs_f64()
     });
 
-    add_json_to_file(json!({
+    logger.append(&json!({
         "TYPE": "bv",
         "domain": domain,
         "direct_gen": json!({
@@ -155,13 +187,14 @@ pub fn write_bv_derivability<L: SynthLanguage>(
         }),
         "from_bv4": json!({
             "rules": sound_bv4.to_str_vec(),
+            "not_generalizing": unsound_bv4.to_str_vec(),
             "time": validate_time.as_secs_f64()
         }),
         "derivability": json!({
             "lhs": lhs,
             "lhs_rhs": lhsrhs
         })
-    }), None)
+    }))
 }
 
 /**
@@ -171,20 +204,21 @@ pub fn write_bv_derivability<L: SynthLanguage>(
  * rules: array of rules
  */
 pub fn write_lifting_phase<L: SynthLanguage>(
+    logger: &Logger,
     phase1: Phase<L>,
     phase2: Phase<L>,
     phase3: Phase<L>,
     time: Duration,
     rules: &Ruleset<L>,
-) {
-    add_json_to_file(json!({
+) -> io::Result<()> {
+    logger.append(&json!({
         "TYPE": "ff_phases",
         "phase1": format!("{}", phase1),
         "phase2": format!("{}", phase2),
         "phase3": format!("{}", phase3),
         "time": time.as_secs_f64(),
         "rules": rules.to_str_vec()
-    }), None)
+    }))
 }
 
 /**
@@ -209,19 +243,42 @@ fn get_derivability<L: SynthLanguage>(
     })
 }
 
-pub fn log_rules<L: SynthLanguage>(
-    ruleset: &Ruleset<L>,
-    filepath: Option<&str>
-) {
-
-    // for val in ruleset.to_json_vec_lhs_rhs() {
-    //     json!()
-    // }
-    // add_json_to_file(, filepath);
-    add_json_to_file(json!({
+/**
+ * Logs `ruleset` with the time it was written
+ */
+pub fn log_rules<L: SynthLanguage>(logger: &Logger, ruleset: &Ruleset<L>) -> io::Result<()> {
+    logger.append(&json!({
         "num_rules": ruleset.len(),
         "time generated": std::time::SystemTime::now(),
         "eqs": ruleset.to_json_vec_lhs_rhs(),
-    }), filepath);
+    }))
 }
 
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn append_rows() {
+        let dir = std::env::temp_dir().join(format!("ruler-logger-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        let logger = Logger::new(&dir);
+        assert_eq!(logger.rows().unwrap(), Vec::<Value>::new());
+
+        logger.append(&json!({ "TYPE": "a", "n": 1 })).unwrap();
+        logger
+            .append(&json!({ "TYPE": "b", "rules": ["x ==> y"] }))
+            .unwrap();
+        let rows = logger.rows().unwrap();
+        assert_eq!(rows.len(), 2);
+        assert_eq!(rows[0]["n"], 1);
+        assert_eq!(rows[1]["rules"][0], "x ==> y");
+
+        // Unwritable outputs are reported, not panicked on
+        let file = dir.join("file");
+        fs::write(&file, "").unwrap();
+        assert!(Logger::new(&file).append(&json!({})).is_err());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
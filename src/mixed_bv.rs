@@ -0,0 +1,673 @@
+use std::fmt;
+use std::ops::*;
+use std::str::FromStr;
+use std::time::Duration;
+
+use egg::RecExpr;
+use num::{BigUint, ToPrimitive};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    map, pattern_vars, sort_of, with_z3_solver, z3_bv_literal, z3_bv_value, CVec, EGraph,
+    Exhaustive, Id, Pattern, Sort, Symbol, SynthAnalysis, SynthLanguage, UnknownReason,
+    ValidationResult, Validator, SMT_TIMEOUT,
+};
+
+/// The widest bitvector `MixedBv` handles.
+pub const MAX_WIDTH: u32 = 128;
+
+/// A bitvector whose width is part of the value, so one domain can hold
+/// several widths. Binary operations expect both sides to have the same
+/// width; `MixedBv` only applies them when they do.
+#[derive(Copy, Clone, Hash, PartialOrd, Ord, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Bits {
+    pub width: u32,
+    pub value: u128,
+}
+
+impl Bits {
+    pub fn new(width: u32, value: u128) -> Self {
+        assert!(
+            0 < width && width <= MAX_WIDTH,
+            "unsupported bitvector width {}",
+            width
+        );
+        Self {
+            width,
+            value: value & Self::mask(width),
+        }
+    }
+
+    fn mask(width: u32) -> u128 {
+        if width >= MAX_WIDTH {
+            !0
+        } else {
+            (1 << width) - 1
+        }
+    }
+
+    pub fn zero(width: u32) -> Self {
+        Self::new(width, 0)
+    }
+
+    pub fn all_ones(width: u32) -> Self {
+        Self::new(width, !0)
+    }
+
+    pub fn min(width: u32) -> Self {
+        Self::new(width, 1 << (width - 1))
+    }
+
+    pub fn max(width: u32) -> Self {
+        Self::new(width, Self::mask(width) >> 1)
+    }
+
+    /// Every `width`-bit value, in increasing order.
+    pub fn all(width: u32) -> impl Iterator<Item = Self> {
+        (0..=Self::mask(width)).map(move |v| Self::new(width, v))
+    }
+
+    pub fn is_negative(self) -> bool {
+        self.value & Self::min(self.width).value != 0
+    }
+
+    pub fn wrapping_add(self, rhs: Self) -> Self {
+        Self::new(self.width, self.value.wrapping_add(rhs.value))
+    }
+
+    pub fn wrapping_sub(self, rhs: Self) -> Self {
+        Self::new(self.width, self.value.wrapping_sub(rhs.value))
+    }
+
+    pub fn wrapping_mul(self, rhs: Self) -> Self {
+        Self::new(self.width, self.value.wrapping_mul(rhs.value))
+    }
+
+    pub fn wrapping_neg(self) -> Self {
+        Self::new(self.width, self.value.wrapping_neg())
+    }
+
+    // Shifts follow SMT-LIB: shifting by the width or more clears every bit
+    // (or, for `ashr`, copies the sign bit into every bit).
+
+    pub fn shl(self, rhs: Self) -> Self {
+        if rhs.value >= self.width as u128 {
+            Self::zero(self.width)
+        } else {
+            Self::new(self.width, self.value << rhs.value)
+        }
+    }
+
+    pub fn lshr(self, rhs: Self) -> Self {
+        if rhs.value >= self.width as u128 {
+            Self::zero(self.width)
+        } else {
+            Self::new(self.width, self.value >> rhs.value)
+        }
+    }
+
+    pub fn ashr(self, rhs: Self) -> Self {
+        let shift = rhs.value.min(self.width as u128 - 1) as u32;
+        let extended = self.sign_extend(MAX_WIDTH - self.width);
+        Self::new(self.width, ((extended.value as i128) >> shift) as u128)
+    }
+
+    /// Adds `extra` zero bits on top.
+    pub fn zero_extend(self, extra: u32) -> Self {
+        Self::new(self.width + extra, self.value)
+    }
+
+    /// Adds `extra` copies of the sign bit on top.
+    pub fn sign_extend(self, extra: u32) -> Self {
+        let width = self.width + extra;
+        if self.is_negative() {
+            Self::new(width, self.value | !Self::mask(self.width))
+        } else {
+            Self::new(width, self.value)
+        }
+    }
+
+    /// Bits `hi` down to `lo`, inclusive.
+    pub fn extract(self, hi: u32, lo: u32) -> Self {
+        Self::new(hi - lo + 1, self.value >> lo)
+    }
+
+    /// `self` on top of `low`.
+    pub fn concat(self, low: Self) -> Self {
+        Self::new(
+            self.width + low.width,
+            (self.value << low.width) | low.value,
+        )
+    }
+
+    /// The SMT-LIB2 literal, `(_ bvX N)`.
+    pub fn to_smtlib(self) -> String {
+        format!("(_ bv{} {})", self.value, self.width)
+    }
+
+    /// Parse an SMT-LIB2 literal: `#b...` and `#x...` are as wide as their
+    /// digits, `(_ bvX N)` is `N` bits wide.
+    pub fn from_smtlib(s: &str) -> Option<Self> {
+        if s.starts_with("#b") || s.starts_with("#x") {
+            return s.parse().ok();
+        }
+        let mut parts = s.strip_prefix("(_ bv")?.strip_suffix(')')?.split(' ');
+        let value = parts.next()?.parse().ok()?;
+        let width = parts.next()?.parse().ok()?;
+        (0 < width && width <= MAX_WIDTH).then(|| Self::new(width, value))
+    }
+}
+
+impl Not for Bits {
+    type Output = Self;
+
+    fn not(self) -> Self::Output {
+        Self::new(self.width, !self.value)
+    }
+}
+
+impl BitAnd for Bits {
+    type Output = Self;
+
+    fn bitand(self, rhs: Self) -> Self::Output {
+        Self::new(self.width, self.value & rhs.value)
+    }
+}
+
+impl BitOr for Bits {
+    type Output = Self;
+
+    fn bitor(self, rhs: Self) -> Self::Output {
+        Self::new(self.width, self.value | rhs.value)
+    }
+}
+
+impl BitXor for Bits {
+    type Output = Self;
+
+    fn bitxor(self, rhs: Self) -> Self::Output {
+        Self::new(self.width, self.value ^ rhs.value)
+    }
+}
+
+// Written as SMT-LIB literals, which carry their width: hex when the width
+// is a multiple of 4, binary otherwise.
+impl fmt::Display for Bits {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.width % 4 == 0 {
+            write!(f, "#x{:0w$x}", self.value, w = (self.width / 4) as usize)
+        } else {
+            write!(f, "#b{:0w$b}", self.value, w = self.width as usize)
+        }
+    }
+}
+
+impl fmt::Debug for Bits {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Display::fmt(self, f)
+    }
+}
+
+impl FromStr for Bits {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (digits, radix, bits_per_digit) = if let Some(digits) = s.strip_prefix("#b") {
+            (digits, 2, 1)
+        } else if let Some(digits) = s.strip_prefix("#x") {
+            (digits, 16, 4)
+        } else {
+            return Err(format!("not a bitvector literal: {}", s));
+        };
+        let width = digits.len() as u32 * bits_per_digit;
+        if width == 0 || width > MAX_WIDTH {
+            return Err(format!("unsupported bitvector width {}", width));
+        }
+        let value = u128::from_str_radix(digits, radix).map_err(|e| e.to_string())?;
+        Ok(Self::new(width, value))
+    }
+}
+
+/// The width-changing operators of `MixedBv`. Like SMT-LIB's indexed
+/// operators they carry their parameters, here in the operator name:
+/// `zero_extend.4` and `sign_extend.4` add 4 bits, `extract.7.4` keeps
+/// bits 7 down to 4.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Resize {
+    ZeroExtend(u32),
+    SignExtend(u32),
+    Extract(u32, u32),
+}
+
+impl Resize {
+    /// The width of the result on a `width`-bit argument, or `None` if the
+    /// argument is too narrow or the result too wide.
+    pub fn width(&self, width: u32) -> Option<u32> {
+        let result = match *self {
+            Resize::ZeroExtend(extra) | Resize::SignExtend(extra) => width.checked_add(extra)?,
+            Resize::Extract(hi, lo) if lo <= hi && hi < width => hi - lo + 1,
+            Resize::Extract(..) => return None,
+        };
+        (result <= MAX_WIDTH).then(|| result)
+    }
+
+    pub fn apply(&self, x: Bits) -> Option<Bits> {
+        self.width(x.width)?;
+        Some(match *self {
+            Resize::ZeroExtend(extra) => x.zero_extend(extra),
+            Resize::SignExtend(extra) => x.sign_extend(extra),
+            Resize::Extract(hi, lo) => x.extract(hi, lo),
+        })
+    }
+}
+
+impl fmt::Display for Resize {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Resize::ZeroExtend(extra) => write!(f, "zero_extend.{}", extra),
+            Resize::SignExtend(extra) => write!(f, "sign_extend.{}", extra),
+            Resize::Extract(hi, lo) => write!(f, "extract.{}.{}", hi, lo),
+        }
+    }
+}
+
+impl FromStr for Resize {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let parse = |n: &str| n.parse::<u32>().map_err(|e| e.to_string());
+        let parts: Vec<&str> = s.split('.').collect();
+        match parts.as_slice() {
+            ["zero_extend", extra] => Ok(Resize::ZeroExtend(parse(extra)?)),
+            ["sign_extend", extra] => Ok(Resize::SignExtend(parse(extra)?)),
+            ["extract", hi, lo] => Ok(Resize::Extract(parse(hi)?, parse(lo)?)),
+            _ => Err(format!("not a resize operator: {}", s)),
+        }
+    }
+}
+
+egg::define_language! {
+    /// Bitvectors of several widths at once. Every e-class has the sort
+    /// `bvN` of its width, which variables are annotated with (`x:bv8`)
+    /// and literals carry (`#x0f` is 8 bits wide). Terms whose widths
+    /// don't fit together, like `(+ x:bv4 y:bv8)`, have the sort
+    /// `ill-typed` and are undefined everywhere.
+    pub enum MixedBv {
+        "+" = Add([Id; 2]),
+        "--" = Sub([Id; 2]),
+        "*" = Mul([Id; 2]),
+        "-" = Neg(Id),
+        "~" = Not(Id),
+        "<<" = Shl([Id; 2]),
+        ">>" = Shr([Id; 2]),
+        "ashr" = AShr([Id; 2]),
+        "&" = And([Id; 2]),
+        "|" = Or([Id; 2]),
+        "^" = Xor([Id; 2]),
+        // The first argument is the high half
+        "concat" = Concat([Id; 2]),
+        Resize(Resize, [Id; 1]),
+        Lit(Bits),
+        Var(egg::Symbol),
+    }
+}
+
+impl MixedBv {
+    /// The sort of `width`-bit terms, `bv<width>`.
+    pub fn sort_of_width(width: u32) -> Sort {
+        Sort::new(&format!("bv{}", width))
+    }
+
+    /// The width of terms of `sort`, if it is a bitvector sort.
+    pub fn width_of(sort: &Sort) -> Option<u32> {
+        let width = sort.name().strip_prefix("bv")?.parse().ok()?;
+        (0 < width && width <= MAX_WIDTH).then(|| width)
+    }
+
+    /// The width of `expr`, or `None` if it is ill-typed.
+    pub fn width_of_expr(expr: &RecExpr<Self>) -> Option<u32> {
+        let mut sorts: Vec<Sort> = vec![];
+        for node in expr.as_ref() {
+            let sort = node.sort_of(|id| &sorts[usize::from(*id)]);
+            sorts.push(sort);
+        }
+        Self::width_of(sorts.last()?)
+    }
+
+    fn width<'a, F>(&'a self, mut get_sort: F) -> Option<u32>
+    where
+        F: FnMut(&'a Id) -> &'a Sort,
+    {
+        let mut width = |id: &'a Id| Self::width_of(get_sort(id));
+        match self {
+            MixedBv::Lit(c) => Some(c.width),
+            MixedBv::Var(v) => Self::width_of(&Sort::of_var(v.as_str())?),
+            MixedBv::Neg(a) | MixedBv::Not(a) => width(a),
+            MixedBv::Add([a, b])
+            | MixedBv::Sub([a, b])
+            | MixedBv::Mul([a, b])
+            | MixedBv::Shl([a, b])
+            | MixedBv::Shr([a, b])
+            | MixedBv::AShr([a, b])
+            | MixedBv::And([a, b])
+            | MixedBv::Or([a, b])
+            | MixedBv::Xor([a, b]) => match (width(a)?, width(b)?) {
+                (x, y) if x == y => Some(x),
+                _ => None,
+            },
+            MixedBv::Concat([a, b]) => {
+                let total = width(a)? + width(b)?;
+                (total <= MAX_WIDTH).then(|| total)
+            }
+            MixedBv::Resize(op, [a]) => op.width(width(a)?),
+        }
+    }
+
+    /// Both sides' common width, or the answer for rules whose sides
+    /// can't be compared.
+    fn check_widths(
+        lhs: &Pattern<Self>,
+        rhs: &Pattern<Self>,
+    ) -> Result<u32, ValidationResult<Self>> {
+        let l = Self::width_of_expr(&Self::instantiate(lhs));
+        let r = Self::width_of_expr(&Self::instantiate(rhs));
+        match (l, r) {
+            (Some(l), Some(r)) if l == r => Ok(l),
+            (Some(_), Some(_)) => Err(ValidationResult::Invalid(None)),
+            _ => Err(ValidationResult::Unknown(UnknownReason::Other(
+                "ill-typed rule".into(),
+            ))),
+        }
+    }
+}
+
+impl SynthLanguage for MixedBv {
+    type Constant = Bits;
+
+    fn eval<'a, F>(&'a self, cvec_len: usize, mut get_cvec: F) -> CVec<Self>
+    where
+        F: FnMut(&'a Id) -> &'a CVec<Self>,
+    {
+        match self {
+            MixedBv::Neg(a) => map!(get_cvec, a => Some(a.wrapping_neg())),
+            MixedBv::Not(a) => map!(get_cvec, a => Some(!*a)),
+
+            MixedBv::Add([a, b]) => {
+                map!(get_cvec, a, b => (a.width == b.width).then(|| a.wrapping_add(*b)))
+            }
+            MixedBv::Sub([a, b]) => {
+                map!(get_cvec, a, b => (a.width == b.width).then(|| a.wrapping_sub(*b)))
+            }
+            MixedBv::Mul([a, b]) => {
+                map!(get_cvec, a, b => (a.width == b.width).then(|| a.wrapping_mul(*b)))
+            }
+            MixedBv::Shl([a, b]) => map!(get_cvec, a, b => (a.width == b.width).then(|| a.shl(*b))),
+            MixedBv::Shr([a, b]) => {
+                map!(get_cvec, a, b => (a.width == b.width).then(|| a.lshr(*b)))
+            }
+            MixedBv::AShr([a, b]) => {
+                map!(get_cvec, a, b => (a.width == b.width).then(|| a.ashr(*b)))
+            }
+            MixedBv::And([a, b]) => map!(get_cvec, a, b => (a.width == b.width).then(|| *a & *b)),
+            MixedBv::Or([a, b]) => map!(get_cvec, a, b => (a.width == b.width).then(|| *a | *b)),
+            MixedBv::Xor([a, b]) => map!(get_cvec, a, b => (a.width == b.width).then(|| *a ^ *b)),
+
+            MixedBv::Concat([a, b]) => {
+                map!(get_cvec, a, b => (a.width + b.width <= MAX_WIDTH).then(|| a.concat(*b)))
+            }
+            MixedBv::Resize(op, [a]) => map!(get_cvec, a => op.apply(*a)),
+
+            MixedBv::Lit(c) => vec![Some(*c); cvec_len],
+            MixedBv::Var(_) => vec![],
+        }
+    }
+
+    fn sort_of<'a, F>(&'a self, get_sort: F) -> Sort
+    where
+        F: FnMut(&'a Id) -> &'a Sort,
+    {
+        match self.width(get_sort) {
+            Some(width) => Self::sort_of_width(width),
+            None => Sort::new("ill-typed"),
+        }
+    }
+
+    fn sort_values(sort: &Sort) -> Vec<Bits> {
+        let width = match Self::width_of(sort) {
+            Some(width) => width,
+            None => return vec![],
+        };
+        let mut consts = vec![];
+        for i in 0..2 {
+            let i = Bits::new(width, i);
+            consts.push(Bits::min(width).wrapping_add(i));
+            consts.push(Bits::max(width).wrapping_sub(i));
+            consts.push(i);
+            consts.push(i.wrapping_neg());
+        }
+        consts.sort();
+        consts.dedup();
+        consts
+    }
+
+    fn sort_edge_cases(sort: &Sort) -> Vec<Bits> {
+        match Self::width_of(sort) {
+            Some(width) => vec![
+                Bits::zero(width),
+                Bits::all_ones(width),
+                Bits::min(width),
+                Bits::max(width),
+            ],
+            None => vec![],
+        }
+    }
+
+    fn all_values(sort: &Sort) -> Option<Vec<Bits>> {
+        let width = Self::width_of(sort)?;
+        (width <= 8).then(|| Bits::all(width).collect())
+    }
+
+    fn to_var(&self) -> Option<Symbol> {
+        if let MixedBv::Var(sym) = self {
+            Some(*sym)
+        } else {
+            None
+        }
+    }
+
+    fn mk_var(sym: Symbol) -> Self {
+        MixedBv::Var(sym)
+    }
+
+    fn is_constant(&self) -> bool {
+        matches!(self, MixedBv::Lit(_))
+    }
+
+    fn mk_constant(c: Self::Constant, _egraph: &mut EGraph<Self, SynthAnalysis>) -> Self {
+        MixedBv::Lit(c)
+    }
+
+    fn smtlib_term(&self, args: &[String]) -> Option<String> {
+        let op = match self {
+            MixedBv::Lit(c) => return Some(c.to_smtlib()),
+            MixedBv::Var(_) => return None,
+            MixedBv::Add(_) => "bvadd",
+            MixedBv::Sub(_) => "bvsub",
+            MixedBv::Mul(_) => "bvmul",
+            MixedBv::Neg(_) => "bvneg",
+            MixedBv::Not(_) => "bvnot",
+            MixedBv::Shl(_) => "bvshl",
+            MixedBv::Shr(_) => "bvlshr",
+            MixedBv::AShr(_) => "bvashr",
+            MixedBv::And(_) => "bvand",
+            MixedBv::Or(_) => "bvor",
+            MixedBv::Xor(_) => "bvxor",
+            MixedBv::Concat(_) => "concat",
+            MixedBv::Resize(op, _) => {
+                let op = match op {
+                    Resize::ZeroExtend(extra) => format!("(_ zero_extend {})", extra),
+                    Resize::SignExtend(extra) => format!("(_ sign_extend {})", extra),
+                    Resize::Extract(hi, lo) => format!("(_ extract {} {})", hi, lo),
+                };
+                return Some(format!("({} {})", op, args[0]));
+            }
+        };
+        Some(format!("({} {})", op, args.join(" ")))
+    }
+
+    fn smtlib_sort(sort: &Sort) -> Option<String> {
+        Self::width_of(sort).map(|width| format!("(_ BitVec {})", width))
+    }
+
+    fn smtlib_logic() -> &'static str {
+        "QF_BV"
+    }
+
+    fn constant_from_smtlib(value: &str) -> Option<Bits> {
+        Bits::from_smtlib(value)
+    }
+
+    // Rules over narrow variables are checked by enumeration, the rest by z3
+    fn validate(lhs: &Pattern<Self>, rhs: &Pattern<Self>) -> ValidationResult<Self> {
+        if let Err(result) = Self::check_widths(lhs, rhs) {
+            return result;
+        }
+        match Exhaustive::default().validate(lhs, rhs) {
+            ValidationResult::Unknown(_) => Self::validate_smt(lhs, rhs, SMT_TIMEOUT),
+            result => result,
+        }
+    }
+
+    fn validate_smt(
+        lhs: &Pattern<Self>,
+        rhs: &Pattern<Self>,
+        timeout: Duration,
+    ) -> ValidationResult<Self> {
+        use z3::{ast::Ast, SatResult};
+
+        if let Err(result) = Self::check_widths(lhs, rhs) {
+            return result;
+        }
+
+        fn egg_to_z3<'a>(ctx: &'a z3::Context, expr: &[MixedBv]) -> z3::ast::BV<'a> {
+            let mut buf: Vec<z3::ast::BV> = vec![];
+            for node in expr {
+                let arg = |id: &Id| &buf[usize::from(*id)];
+                let bv = match node {
+                    MixedBv::Var(v) => {
+                        let width = Sort::of_var(v.as_str())
+                            .and_then(|sort| MixedBv::width_of(&sort))
+                            .unwrap();
+                        z3::ast::BV::new_const(ctx, v.to_string(), width)
+                    }
+                    MixedBv::Lit(c) => z3_bv_literal(ctx, &BigUint::from(c.value), c.width),
+                    MixedBv::Add([a, b]) => arg(a).bvadd(arg(b)),
+                    MixedBv::Sub([a, b]) => arg(a).bvsub(arg(b)),
+                    MixedBv::Mul([a, b]) => arg(a).bvmul(arg(b)),
+                    MixedBv::Neg(a) => arg(a).bvneg(),
+                    MixedBv::Not(a) => arg(a).bvnot(),
+                    MixedBv::Shl([a, b]) => arg(a).bvshl(arg(b)),
+                    MixedBv::Shr([a, b]) => arg(a).bvlshr(arg(b)),
+                    MixedBv::AShr([a, b]) => arg(a).bvashr(arg(b)),
+                    MixedBv::And([a, b]) => arg(a).bvand(arg(b)),
+                    MixedBv::Or([a, b]) => arg(a).bvor(arg(b)),
+                    MixedBv::Xor([a, b]) => arg(a).bvxor(arg(b)),
+                    MixedBv::Concat([a, b]) => arg(a).concat(arg(b)),
+                    MixedBv::Resize(Resize::ZeroExtend(extra), [a]) => arg(a).zero_ext(*extra),
+                    MixedBv::Resize(Resize::SignExtend(extra), [a]) => arg(a).sign_ext(*extra),
+                    MixedBv::Resize(Resize::Extract(hi, lo), [a]) => arg(a).extract(*hi, *lo),
+                };
+                buf.push(bv);
+            }
+            buf.pop().unwrap()
+        }
+
+        with_z3_solver(timeout, |ctx, solver| {
+            let lexpr = egg_to_z3(ctx, Self::instantiate(lhs).as_ref());
+            let rexpr = egg_to_z3(ctx, Self::instantiate(rhs).as_ref());
+            solver.assert(&lexpr._eq(&rexpr).not());
+            match solver.check() {
+                SatResult::Sat => {
+                    let counterexample = solver.get_model().and_then(|model| {
+                        pattern_vars(lhs, rhs)
+                            .into_iter()
+                            .map(|var| {
+                                let width = Self::width_of(&sort_of(&var))?;
+                                let name = var.to_string();
+                                let ast = z3::ast::BV::new_const(ctx, &name[1..], width);
+                                let value = z3_bv_value(&model.eval(&ast, true)?)?;
+                                Some((var, Bits::new(width, value.to_u128()?)))
+                            })
+                            .collect()
+                    });
+                    ValidationResult::Invalid(counterexample)
+                }
+                SatResult::Unsat => ValidationResult::Valid,
+                SatResult::Unknown => ValidationResult::Unknown(UnknownReason::from_z3(solver)),
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn bits(width: u32, value: u128) -> Bits {
+        Bits::new(width, value)
+    }
+
+    #[test]
+    fn resizing() {
+        let x = bits(4, 0b1010);
+        assert_eq!(x.zero_extend(4), bits(8, 0b0000_1010));
+        assert_eq!(x.sign_extend(4), bits(8, 0b1111_1010));
+        assert_eq!(bits(4, 0b0101).sign_extend(4), bits(8, 0b0101));
+        assert_eq!(x.extract(2, 1), bits(2, 0b01));
+        assert_eq!(x.concat(bits(2, 0b11)), bits(6, 0b1010_11));
+        assert_eq!(bits(64, 1).sign_extend(64), bits(128, 1));
+        assert_eq!(bits(64, !0).sign_extend(64), Bits::all_ones(128));
+
+        assert_eq!(Resize::Extract(3, 0).width(4), Some(4));
+        assert_eq!(Resize::Extract(4, 0).width(4), None);
+        assert_eq!(Resize::Extract(0, 1).width(4), None);
+        assert_eq!(Resize::ZeroExtend(1).width(MAX_WIDTH), None);
+    }
+
+    #[test]
+    fn shifts() {
+        let x = bits(4, 0b1010);
+        assert_eq!(x.shl(bits(4, 1)), bits(4, 0b0100));
+        assert_eq!(x.lshr(bits(4, 1)), bits(4, 0b0101));
+        assert_eq!(x.ashr(bits(4, 1)), bits(4, 0b1101));
+        assert_eq!(x.shl(bits(4, 4)), Bits::zero(4));
+        assert_eq!(x.ashr(bits(4, 9)), Bits::all_ones(4));
+        assert_eq!(bits(4, 0b0110).ashr(bits(4, 9)), Bits::zero(4));
+    }
+
+    #[test]
+    fn literals() {
+        for (s, c) in [("#x0f", bits(8, 15)), ("#b101", bits(3, 5))] {
+            assert_eq!(s.parse::<Bits>().unwrap(), c);
+            assert_eq!(c.to_string(), s);
+            assert_eq!(Bits::from_smtlib(&c.to_smtlib()), Some(c));
+        }
+        assert!("15".parse::<Bits>().is_err());
+        for op in ["zero_extend.4", "sign_extend.12", "extract.7.4"] {
+            assert_eq!(op.parse::<Resize>().unwrap().to_string(), op);
+        }
+    }
+
+    #[test]
+    fn widths() {
+        let width = |s: &str| MixedBv::width_of_expr(&s.parse().unwrap());
+        assert_eq!(width("x:bv4"), Some(4));
+        assert_eq!(width("(+ x:bv4 #xf)"), Some(4));
+        assert_eq!(width("(concat x:bv4 y:bv8)"), Some(12));
+        assert_eq!(width("(zero_extend.4 x:bv4)"), Some(8));
+        assert_eq!(width("(extract.7.4 (sign_extend.4 x:bv4))"), Some(4));
+        assert_eq!(width("(+ x:bv4 y:bv8)"), None);
+        assert_eq!(width("(extract.7.0 x:bv4)"), None);
+        assert_eq!(width("x"), None);
+    }
+}
@@ -1,19 +1,14 @@
 use std::time::Instant;
 
 use crate::{
-    enumo::{Filter, Metric, Ruleset, Scheduler, Workload},
-    Limits, SynthLanguage,
+    enumo::{Filter, Grammar, Metric, Ruleset, Scheduler, Workload},
+    refine_candidates, EGraph, Limits, SynthAnalysis, SynthLanguage, Validator,
 };
 
+/// All terms built from `wkld` by plugging `atom` whose `met` is at most `n`.
+/// See `Grammar::from_workload`.
 pub fn iter_metric(wkld: Workload, atom: &str, met: Metric, n: usize) -> Workload {
-    let mut pegs = wkld.clone();
-    for i in 1..(n + 1) {
-        pegs = wkld
-            .clone()
-            .plug(atom, &pegs)
-            .filter(Filter::MetricLt(met, i + 1));
-    }
-    pegs
+    Grammar::from_workload(atom, &wkld).enumerate(met, n)
 }
 
 pub fn substitute(workload: Workload, sub: Workload, atom: &str) -> Workload {
@@ -25,27 +20,24 @@ pub fn substitute(workload: Workload, sub: Workload, atom: &str) -> Workload {
     pegs
 }
 
-fn run_workload_internal<L: SynthLanguage>(
-    workload: Workload,
+/// Minimize the candidates `generate` proposes from `prior` and report what
+/// was learned. The reported time includes generating the candidates.
+fn learn_rules<L, F>(
     prior: Ruleset<L>,
-    prior_limits: Limits,
     minimize_limits: Limits,
-    fast_match: bool,
     allow_empty: bool,
-) -> Ruleset<L> {
+    validator: &dyn Validator<L>,
+    generate: F,
+) -> Ruleset<L>
+where
+    L: SynthLanguage,
+    F: FnOnce(&Ruleset<L>) -> Ruleset<L>,
+{
     let t = Instant::now();
 
-    let egraph = workload.to_egraph::<L>();
-    let compressed = Scheduler::Compress(prior_limits).run(&egraph, &prior);
-
-    let mut candidates = if fast_match {
-        Ruleset::fast_cvec_match(&compressed)
-    } else {
-        Ruleset::cvec_match(&compressed)
-    };
-
     let num_prior = prior.len();
-    let (chosen, _) = candidates.minimize(prior, Scheduler::Compress(minimize_limits));
+    let mut candidates = generate(&prior);
+    let (chosen, _) = candidates.minimize(prior, Scheduler::Compress(minimize_limits), validator);
     let time = t.elapsed().as_secs_f64();
 
     if chosen.is_empty() && !allow_empty {
@@ -65,21 +57,100 @@ fn run_workload_internal<L: SynthLanguage>(
     chosen
 }
 
+/// How many rounds of counterexample-guided refinement `run_workload` does
+/// before minimizing. See `refine_candidates`.
+pub const CEGIS_ROUNDS: usize = 3;
+
+/// Settings for `run_workload_with`.
+pub struct RunOptions<'a, L: SynthLanguage> {
+    pub prior_limits: Limits,
+    pub minimize_limits: Limits,
+    /// Use `Ruleset::fast_cvec_match` instead of `Ruleset::cvec_match`.
+    pub fast_match: bool,
+    /// Return an empty ruleset instead of panicking when nothing is learned.
+    pub allow_empty: bool,
+    /// Rounds of counterexample-guided refinement; 0 turns it off.
+    pub cegis_rounds: usize,
+    pub validator: &'a dyn Validator<L>,
+}
+
+impl<'a, L: SynthLanguage> RunOptions<'a, L> {
+    /// The settings `run_workload` uses.
+    pub fn new(
+        prior_limits: Limits,
+        minimize_limits: Limits,
+        fast_match: bool,
+        validator: &'a dyn Validator<L>,
+    ) -> Self {
+        Self {
+            prior_limits,
+            minimize_limits,
+            fast_match,
+            allow_empty: false,
+            cegis_rounds: CEGIS_ROUNDS,
+            validator,
+        }
+    }
+
+    pub fn with_allow_empty(mut self, allow_empty: bool) -> Self {
+        self.allow_empty = allow_empty;
+        self
+    }
+
+    pub fn with_cegis_rounds(mut self, cegis_rounds: usize) -> Self {
+        self.cegis_rounds = cegis_rounds;
+        self
+    }
+}
+
+/// Learn rules from `workload`: compress it with `prior`, cvec-match,
+/// refine the candidates with counterexamples and minimize them.
+pub fn run_workload_with<L: SynthLanguage>(
+    workload: Workload,
+    prior: Ruleset<L>,
+    options: &RunOptions<L>,
+) -> Ruleset<L> {
+    let validator = options.validator;
+    learn_rules(
+        prior,
+        options.minimize_limits,
+        options.allow_empty,
+        validator,
+        |prior| {
+            let egraph = workload.to_egraph::<L>();
+            let mut compressed = Scheduler::Compress(options.prior_limits).run(&egraph, prior);
+
+            let cvec_match = |egraph: &EGraph<L, SynthAnalysis>| {
+                if options.fast_match {
+                    Ruleset::fast_cvec_match(egraph)
+                } else {
+                    Ruleset::cvec_match(egraph)
+                }
+            };
+            let candidates = cvec_match(&compressed);
+            refine_candidates(
+                &mut compressed,
+                candidates,
+                options.cegis_rounds,
+                validator,
+                cvec_match,
+            )
+        },
+    )
+}
+
+/// `run_workload_with` the default `RunOptions`, which refine candidates
+/// for `CEGIS_ROUNDS` rounds.
 pub fn run_workload<L: SynthLanguage>(
     workload: Workload,
     prior: Ruleset<L>,
     prior_limits: Limits,
     minimize_limits: Limits,
     fast_match: bool,
+    validator: &dyn Validator<L>,
 ) -> Ruleset<L> {
-    run_workload_internal(
-        workload,
-        prior,
-        prior_limits,
-        minimize_limits,
-        fast_match,
-        false,
-    )
+    let options = RunOptions::new(prior_limits, minimize_limits, fast_match, validator);
+    run_workload_with(workload, prior, &options)
 }
 
 pub fn run_rule_lifting<L: SynthLanguage>(
@@ -87,29 +158,25 @@ pub fn run_rule_lifting<L: SynthLanguage>(
     prior: Ruleset<L>,
     prior_limits: Limits,
     minimize_limits: Limits,
+    validator: &dyn Validator<L>,
 ) -> Ruleset<L> {
-    let t = Instant::now();
-
-    let egraph = workload.to_egraph::<L>();
-    let num_prior = prior.len();
-    let mut candidates = Ruleset::allow_forbid_actual(egraph, prior.clone(), prior_limits);
-
-    let chosen = candidates
-        .minimize(prior, Scheduler::Compress(minimize_limits))
-        .0;
-    let time = t.elapsed().as_secs_f64();
-
-    println!(
-        "Learned {} bidirectional rewrites ({} total rewrites) in {} using {} prior rewrites",
-        chosen.bidir_len(),
-        chosen.len(),
-        time,
-        num_prior
-    );
-
-    chosen.pretty_print();
+    learn_rules(prior, minimize_limits, true, validator, |prior| {
+        Ruleset::allow_forbid_actual(workload.to_egraph::<L>(), prior.clone(), prior_limits)
+    })
+}
 
-    chosen
+/// Like `run_rule_lifting`, but also cvec-matches the lifted e-graph and
+/// minimizes the combined candidates. See `Ruleset::hybrid_match`.
+pub fn run_hybrid<L: SynthLanguage>(
+    workload: Workload,
+    prior: Ruleset<L>,
+    prior_limits: Limits,
+    minimize_limits: Limits,
+    validator: &dyn Validator<L>,
+) -> Ruleset<L> {
+    learn_rules(prior, minimize_limits, true, validator, |prior| {
+        Ruleset::hybrid_match(workload.to_egraph::<L>(), prior.clone(), prior_limits)
+    })
 }
 
 #[derive(PartialEq, Eq, Clone, Debug)]
@@ -137,11 +204,12 @@ pub fn recursive_rules<L: SynthLanguage>(
     n: usize,
     lang: Lang,
     prior: Ruleset<L>,
+    validator: &dyn Validator<L>,
 ) -> Ruleset<L> {
     if n < 1 {
         Ruleset::default()
     } else {
-        let mut rec = recursive_rules(metric, n - 1, lang.clone(), prior.clone());
+        let mut rec = recursive_rules(metric, n - 1, lang.clone(), prior.clone(), validator);
         let base_lang = if lang.ops.len() == 2 {
             base_lang(2)
         } else {
@@ -157,14 +225,9 @@ pub fn recursive_rules<L: SynthLanguage>(
         }
         rec.extend(prior);
         let allow_empty = n < 3;
-        let new = run_workload_internal(
-            wkld,
-            rec.clone(),
-            Limits::synthesis(),
-            Limits::minimize(),
-            true,
-            allow_empty,
-        );
+        let options = RunOptions::new(Limits::synthesis(), Limits::minimize(), true, validator)
+            .with_allow_empty(allow_empty);
+        let new = run_workload_with(wkld, rec.clone(), &options);
         let mut all = new;
         all.extend(rec);
         all
@@ -183,7 +246,7 @@ pub fn base_lang(n: usize) -> Workload {
 #[cfg(test)]
 mod test {
     use crate::{
-        enumo::{Metric, Workload},
+        enumo::{Grammar, Metric, Workload},
         recipe_utils::{base_lang, iter_metric},
     };
 
@@ -233,9 +296,18 @@ mod test {
         assert_eq!(lists3.len(), 224);
     }
 
+    #[test]
+    fn iter_metric_count() {
+        let lang = base_lang(2);
+        let count = Grammar::from_workload("EXPR", &lang).count(Metric::Depth, 4);
+        let depth4 = iter_metric(lang, "EXPR", Metric::Depth, 4).force();
+        assert_eq!(count, depth4.len());
+        assert_eq!(count, 5552);
+    }
+
     #[test]
     fn iter_metric_fast() {
-        // This test will not finish if the pushing monotonic filters through plugs optimization is not working.
+        // This test will not finish if enumeration does not prune terms by the metric as it goes.
         let three = iter_metric(base_lang(3), "EXPR", Metric::Atoms, 3);
         assert_eq!(three.force().len(), 10);
 
@@ -0,0 +1,494 @@
+use std::hash::Hash;
+
+use num::{BigUint, One, Zero};
+use rand::{seq::SliceRandom, Rng, SeedableRng};
+use rand_pcg::Pcg64;
+
+use crate::{EGraph, HashSet, Symbol, SynthAnalysis, SynthLanguage};
+
+/// How a `CvecSampler` picks environments once the full cross product of
+/// the variables' values no longer fits under its cap.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Sampling {
+    /// The cross product, thinned out to evenly spaced rows.
+    Exhaustive,
+    /// Distinct environments drawn uniformly at random.
+    Random,
+    /// Every combination of edge cases first, topped up with random
+    /// environments.
+    EdgeCases,
+    /// Latin hypercube sampling: each variable's values are split into as
+    /// many strata as there are environments, and every stratum is used
+    /// exactly once.
+    Stratified,
+}
+
+/// The values a single variable ranges over.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Domain<T> {
+    pub values: Vec<T>,
+    pub edge_cases: Vec<T>,
+}
+
+impl<T: Clone + Eq + Hash> Domain<T> {
+    pub fn new(values: impl IntoIterator<Item = T>) -> Self {
+        Self {
+            values: dedup(values),
+            edge_cases: vec![],
+        }
+    }
+
+    /// Values `Sampling::EdgeCases` always tries, whether or not they
+    /// appear among the domain's ordinary values.
+    pub fn with_edge_cases(mut self, edge_cases: impl IntoIterator<Item = T>) -> Self {
+        self.edge_cases = dedup(edge_cases);
+        self
+    }
+}
+
+fn dedup<T: Clone + Eq + Hash>(values: impl IntoIterator<Item = T>) -> Vec<T> {
+    let mut seen = HashSet::default();
+    values
+        .into_iter()
+        .filter(|v| seen.insert(v.clone()))
+        .collect()
+}
+
+/// The values a bitvector variable of a given width ranges over. Each kind
+/// of value can be switched off, and a variable takes at most `max_values`
+/// of them, picked in this order:
+///
+/// 1. corner cases: 0, 1, -1, `MIN`, `MAX` and their neighbours
+/// 2. shift amounts near the width (`width - 1`, `width`, `width + 1`,
+///    `width / 2`)
+/// 3. repeating patterns (`0101...`, `0011...`, `00001111...` and their
+///    complements)
+/// 4. single-bit masks (`1 << i`), then their complements
+/// 5. `random` values drawn with `seed`
+///
+/// The default only has the corner cases. The rest tell apart expressions
+/// that agree on every corner case, which gets more common as the width
+/// grows; `impl_bv!` domains of 32 bits or more use `mixed` by default.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BvSampler {
+    pub corner_cases: bool,
+    pub shift_amounts: bool,
+    pub patterns: bool,
+    pub single_bits: bool,
+    pub random: usize,
+    pub seed: u64,
+    pub max_values: usize,
+}
+
+impl Default for BvSampler {
+    fn default() -> Self {
+        Self {
+            corner_cases: true,
+            shift_amounts: false,
+            patterns: false,
+            single_bits: false,
+            random: 0,
+            seed: 0,
+            max_values: usize::MAX,
+        }
+    }
+}
+
+impl BvSampler {
+    /// Every kind of value, with 16 random ones, up to `max_values` per
+    /// variable.
+    pub fn mixed(max_values: usize) -> Self {
+        Self {
+            corner_cases: true,
+            shift_amounts: true,
+            patterns: true,
+            single_bits: true,
+            random: 16,
+            seed: 0,
+            max_values,
+        }
+    }
+
+    pub fn with_max_values(mut self, max_values: usize) -> Self {
+        self.max_values = max_values;
+        self
+    }
+
+    pub fn with_random(mut self, random: usize) -> Self {
+        self.random = random;
+        self
+    }
+
+    pub fn with_seed(mut self, seed: u64) -> Self {
+        self.seed = seed;
+        self
+    }
+
+    /// 0, all ones, `MIN` and `MAX`.
+    pub fn edge_cases(width: u32) -> Vec<BigUint> {
+        let ones = low_mask(width);
+        let min = BigUint::one() << (width - 1) as usize;
+        vec![BigUint::zero(), ones.clone(), min, ones >> 1]
+    }
+
+    /// The values of a `width`-bit variable, as unsigned integers.
+    pub fn values(&self, width: u32) -> Vec<BigUint> {
+        let mask = low_mask(width);
+        let mut values = vec![];
+
+        if self.corner_cases {
+            // MIN + i, MAX - i, -i and i, as for the fixed-width `BV`s
+            let neg = |x: &BigUint| (&mask + 1u32 - x) & &mask;
+            let min = BigUint::one() << (width - 1) as usize;
+            let max = &mask >> 1;
+            let mut corners = vec![];
+            for i in 0..2u32 {
+                let i = BigUint::from(i);
+                corners.push((&min + &i) & &mask);
+                corners.push((&max + neg(&i)) & &mask);
+                corners.push(neg(&i));
+                corners.push(i);
+            }
+            corners.sort();
+            values.extend(corners);
+        }
+        if self.shift_amounts {
+            for k in [width - 1, width, width + 1, width / 2] {
+                values.push(BigUint::from(k) & &mask);
+            }
+        }
+        if self.patterns {
+            for block in [1, 2, 4] {
+                // `block` zeros then `block` ones, repeated
+                let mut pattern = BigUint::zero();
+                for _ in 0..(width + 2 * block - 1) / (2 * block) {
+                    pattern = (pattern << (2 * block) as usize) | low_mask(block);
+                }
+                let pattern = pattern & &mask;
+                values.push(&mask ^ &pattern);
+                values.push(pattern);
+            }
+        }
+        if self.single_bits {
+            let bit = |i: u32| BigUint::one() << i as usize;
+            values.extend((0..width).map(bit));
+            values.extend((0..width).map(|i| &mask ^ bit(i)));
+        }
+        if self.random > 0 {
+            let mut rng = Pcg64::seed_from_u64(self.seed);
+            for _ in 0..self.random {
+                let mut value = BigUint::zero();
+                for _ in 0..(width + 63) / 64 {
+                    value = (value << 64) | BigUint::from(rng.gen::<u64>());
+                }
+                values.push(value & &mask);
+            }
+        }
+
+        let mut values = dedup(values);
+        values.truncate(self.max_values);
+        values
+    }
+
+    /// The domain of a `width`-bit variable, with `edge_cases`.
+    pub fn domain(&self, width: u32) -> Domain<BigUint> {
+        Domain::new(self.values(width)).with_edge_cases(Self::edge_cases(width))
+    }
+}
+
+fn low_mask(width: u32) -> BigUint {
+    (BigUint::one() << width as usize) - 1u32
+}
+
+/// Picks the environments (one value per variable) that cvecs are built
+/// from. If every combination of values fits under `max_size` all of them
+/// are used, regardless of the sampling mode; otherwise `sampling` decides
+/// which `max_size` environments to keep.
+///
+/// Bitvector domains take their variables' values from `bv`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CvecSampler {
+    pub sampling: Sampling,
+    pub max_size: usize,
+    pub seed: u64,
+    pub bv: BvSampler,
+}
+
+impl Default for CvecSampler {
+    fn default() -> Self {
+        Self::exhaustive()
+    }
+}
+
+impl CvecSampler {
+    pub fn new(sampling: Sampling, max_size: usize) -> Self {
+        Self {
+            sampling,
+            max_size,
+            seed: 0,
+            bv: BvSampler::default(),
+        }
+    }
+
+    /// The full cross product, with no cap.
+    pub fn exhaustive() -> Self {
+        Self::new(Sampling::Exhaustive, usize::MAX)
+    }
+
+    pub fn random(max_size: usize) -> Self {
+        Self::new(Sampling::Random, max_size)
+    }
+
+    pub fn edge_cases(max_size: usize) -> Self {
+        Self::new(Sampling::EdgeCases, max_size)
+    }
+
+    pub fn stratified(max_size: usize) -> Self {
+        Self::new(Sampling::Stratified, max_size)
+    }
+
+    pub fn with_seed(mut self, seed: u64) -> Self {
+        self.seed = seed;
+        self
+    }
+
+    pub fn with_bv(mut self, bv: BvSampler) -> Self {
+        self.bv = bv;
+        self
+    }
+
+    /// Choose environments over `domains`. Each environment holds one
+    /// value per domain, in order.
+    pub fn sample<T: Clone + Eq + Hash>(&self, domains: &[Domain<T>]) -> Vec<Vec<T>> {
+        let values: Vec<&[T]> = domains.iter().map(|d| d.values.as_slice()).collect();
+        let total = values
+            .iter()
+            .fold(1usize, |n, vals| n.saturating_mul(vals.len()));
+        if total <= self.max_size {
+            return product(&values);
+        }
+
+        let mut rng = Pcg64::seed_from_u64(self.seed);
+        match self.sampling {
+            Sampling::Exhaustive => evenly_spaced(&values, total, self.max_size),
+            Sampling::Random => fill_random(vec![], &values, self.max_size, &mut rng),
+            Sampling::EdgeCases => {
+                // Variables without edge cases contribute all their values
+                let edges: Vec<&[T]> = domains
+                    .iter()
+                    .map(|d| {
+                        if d.edge_cases.is_empty() {
+                            d.values.as_slice()
+                        } else {
+                            d.edge_cases.as_slice()
+                        }
+                    })
+                    .collect();
+                let num_edges = edges
+                    .iter()
+                    .fold(1usize, |n, vals| n.saturating_mul(vals.len()));
+                let rows = if num_edges <= self.max_size {
+                    product(&edges)
+                } else {
+                    evenly_spaced(&edges, num_edges, self.max_size)
+                };
+                fill_random(rows, &values, self.max_size, &mut rng)
+            }
+            Sampling::Stratified => stratified(&values, self.max_size, &mut rng),
+        }
+    }
+
+    /// Add `vars` to the egraph with cvecs built from the chosen
+    /// environments, where `domains[i]` holds the values of `vars[i]`.
+    /// Returns the environments, in cvec order.
+    pub fn initialize<L: SynthLanguage>(
+        &self,
+        egraph: &mut EGraph<L, SynthAnalysis>,
+        vars: &[String],
+        domains: &[Domain<L::Constant>],
+    ) -> Vec<Vec<L::Constant>> {
+        assert_eq!(vars.len(), domains.len(), "one domain per variable");
+        let envs = self.sample(domains);
+
+        egraph.analysis.cvec_len = envs.len();
+
+        for (i, v) in vars.iter().enumerate() {
+            let id = egraph.add(L::mk_var(Symbol::from(v)));
+            egraph[id].data.cvec = envs.iter().map(|env| Some(env[i].clone())).collect();
+        }
+        envs
+    }
+}
+
+/// The `index`th row of the cross product, first variable varying fastest.
+fn nth_row<T: Clone>(values: &[&[T]], mut index: usize) -> Vec<T> {
+    values
+        .iter()
+        .map(|vals| {
+            let v = vals[index % vals.len()].clone();
+            index /= vals.len();
+            v
+        })
+        .collect()
+}
+
+fn product<T: Clone>(values: &[&[T]]) -> Vec<Vec<T>> {
+    let total: usize = values.iter().map(|vals| vals.len()).product();
+    (0..total).map(|i| nth_row(values, i)).collect()
+}
+
+fn evenly_spaced<T: Clone>(values: &[&[T]], total: usize, n: usize) -> Vec<Vec<T>> {
+    (0..n)
+        .map(|i| nth_row(values, (i as u128 * total as u128 / n as u128) as usize))
+        .collect()
+}
+
+/// Add distinct random rows to `rows` until there are `n` of them. Only
+/// called when the product of `values` has more than `n` rows.
+fn fill_random<T: Clone + Eq + Hash>(
+    mut rows: Vec<Vec<T>>,
+    values: &[&[T]],
+    n: usize,
+    rng: &mut Pcg64,
+) -> Vec<Vec<T>> {
+    let mut seen: HashSet<Vec<T>> = rows.iter().cloned().collect();
+    while rows.len() < n {
+        let row: Vec<T> = values
+            .iter()
+            .map(|vals| vals[rng.gen_range(0, vals.len())].clone())
+            .collect();
+        if seen.insert(row.clone()) {
+            rows.push(row);
+        }
+    }
+    rows
+}
+
+fn stratified<T: Clone>(values: &[&[T]], n: usize, rng: &mut Pcg64) -> Vec<Vec<T>> {
+    let columns: Vec<Vec<T>> = values
+        .iter()
+        .map(|vals| {
+            // Stratum k covers indices [k * len / n, (k + 1) * len / n)
+            let mut column: Vec<T> = (0..n)
+                .map(|k| {
+                    let index = (k * vals.len() + rng.gen_range(0, vals.len())) / n;
+                    vals[index].clone()
+                })
+                .collect();
+            column.shuffle(rng);
+            column
+        })
+        .collect();
+    (0..n)
+        .map(|k| columns.iter().map(|column| column[k].clone()).collect())
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn domains(n: usize) -> Vec<Domain<i64>> {
+        (0..n)
+            .map(|_| Domain::new(-2..=2).with_edge_cases(vec![0, 100]))
+            .collect()
+    }
+
+    #[test]
+    fn small_products_are_exhaustive() {
+        for sampler in [
+            CvecSampler::exhaustive(),
+            CvecSampler::random(25),
+            CvecSampler::edge_cases(25),
+            CvecSampler::stratified(100),
+        ] {
+            let envs = sampler.sample(&domains(2));
+            assert_eq!(envs.len(), 25);
+            assert_eq!(envs[0], vec![-2, -2]);
+            assert_eq!(envs[1], vec![-1, -2]);
+        }
+        assert_eq!(CvecSampler::random(1).sample::<i64>(&[]), vec![vec![]]);
+    }
+
+    #[test]
+    fn capped() {
+        let doms = domains(4);
+        for sampler in [
+            CvecSampler::new(Sampling::Exhaustive, 40),
+            CvecSampler::random(40),
+            CvecSampler::edge_cases(40),
+            CvecSampler::stratified(40),
+        ] {
+            let envs = sampler.sample(&doms);
+            assert_eq!(envs.len(), 40, "{:?}", sampler.sampling);
+            assert!(envs.iter().all(|env| env.len() == 4));
+            assert_eq!(envs, sampler.sample(&doms), "not deterministic");
+        }
+    }
+
+    #[test]
+    fn random_is_distinct_and_seeded() {
+        let doms = domains(3);
+        let envs = CvecSampler::random(50).sample(&doms);
+        let distinct: HashSet<_> = envs.iter().collect();
+        assert_eq!(distinct.len(), 50);
+        assert!(envs.iter().flatten().all(|v| (-2..=2).contains(v)));
+        assert_ne!(envs, CvecSampler::random(50).with_seed(1).sample(&doms));
+    }
+
+    #[test]
+    fn edge_cases_come_first() {
+        let envs = CvecSampler::edge_cases(10).sample(&domains(3));
+        assert_eq!(envs.len(), 10);
+        assert_eq!(envs[0], vec![0, 0, 0]);
+        assert_eq!(envs[7], vec![100, 100, 100]);
+        assert!(envs[8..].iter().flatten().all(|v| (-2..=2).contains(v)));
+    }
+
+    #[test]
+    fn bv_values() {
+        let values = |sampler: BvSampler| -> Vec<u64> {
+            let values = sampler.values(8);
+            values
+                .iter()
+                .map(|v| v.to_u64_digits().first().copied().unwrap_or(0))
+                .collect()
+        };
+        assert_eq!(
+            values(BvSampler::default()),
+            vec![0, 1, 0x7e, 0x7f, 0x80, 0x81, 0xff]
+        );
+
+        let mixed = values(BvSampler::mixed(usize::MAX));
+        assert_eq!(&mixed[..7], values(BvSampler::default()).as_slice());
+        assert_eq!(&mixed[7..10], &[7, 8, 9]);
+        for v in [4, 0x55, 0xaa, 0x33, 0x0f, 0xf0, 0x10, 0xef] {
+            assert!(mixed.contains(&v), "{:#x}", v);
+        }
+        let distinct: HashSet<_> = mixed.iter().collect();
+        assert_eq!(distinct.len(), mixed.len());
+
+        // Capped, keeping the earlier kinds
+        assert_eq!(values(BvSampler::mixed(10)), mixed[..10]);
+        assert_ne!(
+            values(BvSampler::mixed(usize::MAX).with_seed(1)),
+            mixed,
+            "random values aren't seeded"
+        );
+
+        let wide = BvSampler::mixed(usize::MAX).values(256);
+        assert!(wide.iter().all(|v| v.bits() <= 256));
+        assert!(wide.iter().any(|v| v.bits() > 192));
+    }
+
+    #[test]
+    fn stratified_covers_every_value() {
+        let doms = vec![Domain::new(0..100), Domain::new(0..100)];
+        let envs = CvecSampler::stratified(10).sample(&doms);
+        for var in 0..2 {
+            let mut strata: Vec<i64> = envs.iter().map(|env| env[var] / 10).collect();
+            strata.sort();
+            assert_eq!(strata, (0..10).collect::<Vec<_>>());
+        }
+    }
+}
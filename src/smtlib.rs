@@ -0,0 +1,219 @@
+use std::io::{Read, Write};
+use std::process::{Command, Stdio};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use egg::ENodeOrVar;
+
+use crate::{
+    pattern_vars, sort_of, Pattern, SynthLanguage, UnknownReason, ValidationResult, Validator, Var,
+    SMT_TIMEOUT,
+};
+
+/// An SMT-LIB2 script that is `sat` exactly when `lhs` and `rhs` can
+/// differ, using the domain's `smtlib_*` hooks. After `check-sat` it asks
+/// for the values of the variables returned by `pattern_vars`, in order.
+/// Fails with the offending operator or sort if the domain has no encoding
+/// for it.
+///
+/// `:produce-models` is set before `set-logic`, since solvers may reject
+/// option changes once a logic has been set.
+pub fn smtlib_query<L: SynthLanguage>(
+    lhs: &Pattern<L>,
+    rhs: &Pattern<L>,
+) -> Result<String, String> {
+    let vars = pattern_vars(lhs, rhs);
+    let mut query = format!(
+        "(set-option :produce-models true)\n(set-logic {})\n",
+        L::smtlib_logic()
+    );
+    for var in &vars {
+        let sort = sort_of(var);
+        let smt_sort = L::smtlib_sort(&sort).ok_or_else(|| format!("sort {}", sort))?;
+        query += &format!("(declare-const {} {})\n", smtlib_var(var), smt_sort);
+    }
+    query += &format!(
+        "(assert (not (= {} {})))\n(check-sat)\n",
+        smtlib_term(lhs)?,
+        smtlib_term(rhs)?
+    );
+    if !vars.is_empty() {
+        let names: Vec<String> = vars.iter().map(smtlib_var).collect();
+        query += &format!("(get-value ({}))\n", names.join(" "));
+    }
+    query += "(exit)\n";
+    Ok(query)
+}
+
+/// `pattern` as an SMT-LIB2 term.
+pub fn smtlib_term<L: SynthLanguage>(pattern: &Pattern<L>) -> Result<String, String> {
+    let mut terms: Vec<String> = vec![];
+    for node in pattern.ast.as_ref() {
+        let term = match node {
+            ENodeOrVar::Var(v) => smtlib_var(v),
+            ENodeOrVar::ENode(n) => {
+                let args: Vec<String> = n
+                    .children()
+                    .iter()
+                    .map(|id| terms[usize::from(*id)].clone())
+                    .collect();
+                n.smtlib_term(&args).ok_or_else(|| n.to_string())?
+            }
+        };
+        terms.push(term);
+    }
+    Ok(terms.pop().unwrap_or_default())
+}
+
+// Quoted, since pattern variables may carry a sort (`?x:int`)
+fn smtlib_var(var: &Var) -> String {
+    format!("|{}|", &var.to_string()[1..])
+}
+
+/// Checks rules by handing `smtlib_query` to a solver binary (z3, cvc5,
+/// bitwuzla, ...) over stdin, so any SMT-LIB2 solver on the `PATH` can
+/// validate a domain without linking against it. The solver is killed after
+/// `timeout`.
+#[derive(Debug, Clone)]
+pub struct SmtLib {
+    pub program: String,
+    pub args: Vec<String>,
+    pub timeout: Duration,
+}
+
+impl SmtLib {
+    pub fn new(program: &str, args: &[&str]) -> Self {
+        Self {
+            program: program.into(),
+            args: args.iter().map(|a| a.to_string()).collect(),
+            timeout: SMT_TIMEOUT,
+        }
+    }
+
+    pub fn z3() -> Self {
+        Self::new("z3", &["-in"])
+    }
+
+    /// The solver the `scripts/oopsla21/cvc4-eval` comparison uses.
+    pub fn cvc4() -> Self {
+        Self::new("cvc4", &["--lang=smt2"])
+    }
+
+    pub fn cvc5() -> Self {
+        Self::new("cvc5", &["--lang=smt2"])
+    }
+
+    pub fn bitwuzla() -> Self {
+        Self::new("bitwuzla", &["--lang", "smt2"])
+    }
+
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Feed `query` to the solver and collect what it prints.
+    fn run(&self, query: &str) -> Result<String, UnknownReason> {
+        let error = |e: std::io::Error| {
+            UnknownReason::Other(format!("could not run {}: {}", self.program, e))
+        };
+        let mut child = Command::new(&self.program)
+            .args(&self.args)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn()
+            .map_err(error)?;
+        child
+            .stdin
+            .take()
+            .unwrap()
+            .write_all(query.as_bytes())
+            .map_err(error)?;
+
+        let start = Instant::now();
+        while child.try_wait().map_err(error)?.is_none() {
+            if start.elapsed() > self.timeout {
+                let _ = child.kill();
+                let _ = child.wait();
+                return Err(UnknownReason::Timeout);
+            }
+            thread::sleep(Duration::from_millis(5));
+        }
+
+        let mut output = String::new();
+        child
+            .stdout
+            .take()
+            .unwrap()
+            .read_to_string(&mut output)
+            .map_err(error)?;
+        Ok(output)
+    }
+}
+
+impl<L: SynthLanguage> Validator<L> for SmtLib {
+    fn name(&self) -> String {
+        format!("smtlib({}, {:?})", self.program, self.timeout)
+    }
+
+    fn validate(&self, lhs: &Pattern<L>, rhs: &Pattern<L>) -> ValidationResult<L> {
+        let query = match smtlib_query(lhs, rhs) {
+            Ok(query) => query,
+            Err(op) => return ValidationResult::Unknown(UnknownReason::UnsupportedOp(op)),
+        };
+        let output = match self.run(&query) {
+            Ok(output) => output,
+            Err(reason) => return ValidationResult::Unknown(reason),
+        };
+        parse_answer(&pattern_vars(lhs, rhs), &output)
+    }
+}
+
+/// Read the solver's answer to a `smtlib_query` over `vars`.
+fn parse_answer<L: SynthLanguage>(vars: &[Var], output: &str) -> ValidationResult<L> {
+    let mut lines = output.lines().map(str::trim).filter(|l| !l.is_empty());
+    match lines.next() {
+        Some("unsat") => ValidationResult::Valid,
+        Some("sat") => {
+            let model: Vec<&str> = lines.collect();
+            ValidationResult::Invalid(parse_model(vars, &model.join(" ")))
+        }
+        Some("unknown") => {
+            ValidationResult::Unknown(UnknownReason::Other("solver returned unknown".into()))
+        }
+        Some(line) => ValidationResult::Unknown(UnknownReason::Other(line.into())),
+        None => ValidationResult::Unknown(UnknownReason::Other("no answer from solver".into())),
+    }
+}
+
+/// The counterexample in a `get-value` response, `((x v1) (y v2) ...)`,
+/// whose entries are in the same order as `vars`.
+fn parse_model<L: SynthLanguage>(vars: &[Var], model: &str) -> Option<Vec<(Var, L::Constant)>> {
+    use symbolic_expressions::{parser::parse_str, Sexp};
+
+    fn to_string(sexp: &Sexp) -> String {
+        match sexp {
+            Sexp::String(s) => s.clone(),
+            Sexp::List(items) => {
+                let items: Vec<String> = items.iter().map(to_string).collect();
+                format!("({})", items.join(" "))
+            }
+            Sexp::Empty => "()".into(),
+        }
+    }
+
+    let entries = match parse_str(model).ok()? {
+        Sexp::List(entries) if entries.len() == vars.len() => entries,
+        _ => return None,
+    };
+    vars.iter()
+        .zip(&entries)
+        .map(|(var, entry)| match entry {
+            Sexp::List(pair) if pair.len() == 2 => {
+                Some((*var, L::constant_from_smtlib(&to_string(&pair[1]))?))
+            }
+            _ => None,
+        })
+        .collect()
+}
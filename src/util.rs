@@ -1,3 +1,4 @@
+use float_cmp::{ApproxEq, F64Margin};
 use std::{
     fs::File,
     io::{BufRead, BufReader},
@@ -28,6 +29,49 @@ pub fn self_product<T: Clone>(ts: &[T], n: usize) -> Vec<Vec<T>> {
     res
 }
 
+/// Compare two cvecs entry by entry with `eq`, where an undefined (`None`)
+/// entry agrees with anything.
+pub fn cvec_eq_by<C>(
+    cvec1: &[Option<C>],
+    cvec2: &[Option<C>],
+    eq: impl Fn(&C, &C) -> bool,
+) -> bool {
+    cvec1.iter().zip(cvec2).all(|pair| match pair {
+        (Some(a), Some(b)) => eq(a, b),
+        _ => true,
+    })
+}
+
+/// Approximate float equality: within `epsilon` or `ulps` units in the
+/// last place of each other. NaN is equal to NaN, so that two expressions
+/// that are both undefined at a point still agree there.
+pub fn approx_eq(a: f64, b: f64, epsilon: f64, ulps: i64) -> bool {
+    if a.is_nan() || b.is_nan() {
+        a.is_nan() && b.is_nan()
+    } else {
+        a.approx_eq(b, F64Margin { epsilon, ulps })
+    }
+}
+
+/// Round `x` to `digits` significant digits, for bucketing approximately
+/// equal floats together. Values close to a rounding boundary can still
+/// land in different buckets.
+pub fn round_sig(x: f64, digits: i32) -> f64 {
+    if x == 0.0 || !x.is_finite() {
+        return x;
+    }
+    let magnitude = x.abs().log10().floor() as i32;
+    // Avoid scaling by inexact negative powers of ten
+    let shift = digits - 1 - magnitude;
+    if shift >= 0 {
+        let scale = 10f64.powi(shift);
+        (x * scale).round() / scale
+    } else {
+        let scale = 10f64.powi(-shift);
+        (x / scale).round() * scale
+    }
+}
+
 #[macro_export]
 macro_rules! map {
     ($get:ident, $a:ident => $body:expr) => {
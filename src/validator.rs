@@ -0,0 +1,687 @@
+use std::time::Duration;
+
+use egg::ENodeOrVar;
+use rand::SeedableRng;
+use rand_pcg::Pcg64;
+
+use crate::{
+    CVec, CvecSampler, Domain, Pattern, Sort, SynthLanguage, UnknownReason, ValidationResult, Var,
+};
+
+/// How long `Smt` waits for the solver by default.
+pub const SMT_TIMEOUT: Duration = Duration::from_millis(1000);
+
+/// A strategy for checking candidate rules. `Ruleset::minimize` and the
+/// `recipe_utils` drivers take one, so the strategy can change per run
+/// without touching the domain.
+pub trait Validator<L: SynthLanguage>: Send + Sync {
+    /// Identifies the validator in `Validation::validator`.
+    fn name(&self) -> String;
+
+    fn validate(&self, lhs: &Pattern<L>, rhs: &Pattern<L>) -> ValidationResult<L>;
+
+    /// `validate`, along with how sure the validator is of the answer, from
+    /// 0 to 1. By default validators are sure of anything but `Unknown`.
+    fn validate_with_confidence(
+        &self,
+        lhs: &Pattern<L>,
+        rhs: &Pattern<L>,
+    ) -> (ValidationResult<L>, f64) {
+        let result = self.validate(lhs, rhs);
+        let confidence = match result {
+            ValidationResult::Unknown(_) => 0.0,
+            _ => 1.0,
+        };
+        (result, confidence)
+    }
+}
+
+/// The domain's own `SynthLanguage::validate`.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct DomainValidator;
+
+impl<L: SynthLanguage> Validator<L> for DomainValidator {
+    fn name(&self) -> String {
+        std::any::type_name::<L>().to_string()
+    }
+
+    fn validate(&self, lhs: &Pattern<L>, rhs: &Pattern<L>) -> ValidationResult<L> {
+        L::validate(lhs, rhs)
+    }
+}
+
+/// The domain's SMT encoding (`SynthLanguage::validate_smt`) with a
+/// configurable solver timeout.
+#[derive(Debug, Clone, Copy)]
+pub struct Smt {
+    pub timeout: Duration,
+}
+
+impl Default for Smt {
+    fn default() -> Self {
+        Self {
+            timeout: SMT_TIMEOUT,
+        }
+    }
+}
+
+impl<L: SynthLanguage> Validator<L> for Smt {
+    fn name(&self) -> String {
+        format!("smt({:?})", self.timeout)
+    }
+
+    fn validate(&self, lhs: &Pattern<L>, rhs: &Pattern<L>) -> ValidationResult<L> {
+        L::validate_smt(lhs, rhs, self.timeout)
+    }
+}
+
+/// Run `f` on a fresh z3 solver with `timeout` for each check. Building a
+/// `Context` dominates the cost of the small queries rule validation makes,
+/// so `validate_smt` implementations share one context per thread, which
+/// lives as long as the thread. The solver itself is new for every query:
+/// reusing one through `push`/`pop` makes z3 fall back to its incremental
+/// core, which gives up on nonlinear arithmetic that `nlsat` would solve.
+pub fn with_z3_solver<R>(
+    timeout: Duration,
+    f: impl FnOnce(&'static z3::Context, &z3::Solver<'static>) -> R,
+) -> R {
+    thread_local! {
+        static CONTEXT: &'static z3::Context =
+            Box::leak(Box::new(z3::Context::new(&z3::Config::new())));
+    }
+
+    CONTEXT.with(|ctx| {
+        let solver = z3::Solver::new(ctx);
+        let mut params = z3::Params::new(ctx);
+        params.set_u32("timeout", timeout.as_millis() as u32);
+        solver.set_params(&params);
+        f(ctx, &solver)
+    })
+}
+
+/// Evaluates both sides on every assignment of the pattern variables, for
+/// domains that can list all the values of a sort
+/// (`SynthLanguage::all_values`). The answer is exact, and invalid rules
+/// come with a counterexample. Gives up with `Unknown` on sorts that are
+/// not enumerable and on rules with more than `max_assignments`
+/// assignments.
+#[derive(Debug, Clone, Copy)]
+pub struct Exhaustive {
+    pub max_assignments: usize,
+}
+
+impl Default for Exhaustive {
+    fn default() -> Self {
+        Self {
+            max_assignments: 1 << 16,
+        }
+    }
+}
+
+impl<L: SynthLanguage> Validator<L> for Exhaustive {
+    fn name(&self) -> String {
+        "exhaustive".to_string()
+    }
+
+    fn validate(&self, lhs: &Pattern<L>, rhs: &Pattern<L>) -> ValidationResult<L> {
+        let vars = pattern_vars(lhs, rhs);
+        let mut domains = vec![];
+        let mut assignments = 1usize;
+        for var in &vars {
+            let sort = sort_of(var);
+            match L::all_values(&sort) {
+                Some(values) => {
+                    assignments = assignments.saturating_mul(values.len());
+                    domains.push(Domain::new(values));
+                }
+                None => {
+                    return ValidationResult::Unknown(UnknownReason::Other(format!(
+                        "sort {} is not enumerable",
+                        sort
+                    )))
+                }
+            }
+        }
+        if assignments > self.max_assignments {
+            return ValidationResult::Unknown(UnknownReason::Other(format!(
+                "{} assignments is too many",
+                assignments
+            )));
+        }
+
+        let envs = CvecSampler::exhaustive().sample(&domains);
+        test_on(lhs, rhs, &vars, &envs)
+    }
+}
+
+/// Evaluates both sides on `samples` seeded random assignments, for domains
+/// without an exact validator. Each variable ranges over its sort's
+/// `SynthLanguage::sort_values` plus `samples` values drawn with
+/// `SynthLanguage::random_value`. Uses `eval` (and so the domain's own, possibly exact,
+/// constants) when it covers every op, and `eval_f64` otherwise.
+///
+/// Points where either side is undefined are skipped. If no sample tells
+/// the sides apart the rule is `Valid`, with a confidence that grows with
+/// the number of points where both sides were defined: the chance that
+/// those points would have caught a rule that is wrong on at least 1% of
+/// inputs. Rules without variables are evaluated once, with confidence 1.
+#[derive(Debug, Clone, Copy)]
+pub struct RandomTesting {
+    pub samples: usize,
+    pub seed: u64,
+}
+
+impl RandomTesting {
+    pub fn new(samples: usize) -> Self {
+        Self { samples, seed: 0 }
+    }
+
+    pub fn with_seed(mut self, seed: u64) -> Self {
+        self.seed = seed;
+        self
+    }
+}
+
+impl<L: SynthLanguage> Validator<L> for RandomTesting {
+    fn name(&self) -> String {
+        format!("random({}, seed {})", self.samples, self.seed)
+    }
+
+    fn validate(&self, lhs: &Pattern<L>, rhs: &Pattern<L>) -> ValidationResult<L> {
+        self.validate_with_confidence(lhs, rhs).0
+    }
+
+    fn validate_with_confidence(
+        &self,
+        lhs: &Pattern<L>,
+        rhs: &Pattern<L>,
+    ) -> (ValidationResult<L>, f64) {
+        let vars = pattern_vars(lhs, rhs);
+        let mut rng = Pcg64::seed_from_u64(self.seed);
+        let mut domains = vec![];
+        for var in &vars {
+            let sort = sort_of(var);
+            let mut values = L::sort_values(&sort);
+            values.extend((0..self.samples).filter_map(|_| L::random_value(&sort, &mut rng)));
+            if values.is_empty() {
+                let reason = UnknownReason::Other(format!("no values for sort {}", sort));
+                return (ValidationResult::Unknown(reason), 0.0);
+            }
+            domains.push(Domain::new(values));
+        }
+
+        let sampler = CvecSampler::random(self.samples).with_seed(self.seed);
+        let envs = sampler.sample(&domains);
+        let comparison = match eval_sides(lhs, rhs, &vars, &envs) {
+            Ok((l, r)) => compare(&l, &r, |a, b| a == b, true),
+            Err(_) => match eval_sides_f64(lhs, rhs, &vars, &envs) {
+                Ok((l, r)) => compare(&l, &r, |a, b| close(*a, *b), true),
+                Err(op) => {
+                    let reason = UnknownReason::UnsupportedOp(op);
+                    return (ValidationResult::Unknown(reason), 0.0);
+                }
+            },
+        };
+
+        match comparison {
+            Comparison::Differ(i) => (counterexample(&vars, &envs[i]), 1.0),
+            Comparison::Agree { defined: 0 } => {
+                let reason = UnknownReason::Other("undefined at every sample".into());
+                (ValidationResult::Unknown(reason), 0.0)
+            }
+            Comparison::Agree { .. } if vars.is_empty() => (ValidationResult::Valid, 1.0),
+            Comparison::Agree { defined } => {
+                (ValidationResult::Valid, 1.0 - 0.99f64.powi(defined as i32))
+            }
+        }
+    }
+}
+
+/// Asks each validator in turn and returns the first answer that is not
+/// `Unknown`.
+pub struct FirstDefinitive<L: SynthLanguage>(pub Vec<Box<dyn Validator<L>>>);
+
+impl<L: SynthLanguage> Validator<L> for FirstDefinitive<L> {
+    fn name(&self) -> String {
+        combined_name("first", &self.0)
+    }
+
+    fn validate(&self, lhs: &Pattern<L>, rhs: &Pattern<L>) -> ValidationResult<L> {
+        self.validate_with_confidence(lhs, rhs).0
+    }
+
+    fn validate_with_confidence(
+        &self,
+        lhs: &Pattern<L>,
+        rhs: &Pattern<L>,
+    ) -> (ValidationResult<L>, f64) {
+        let mut result = ValidationResult::Unknown(UnknownReason::Other("no validators".into()));
+        let mut confidence = 0.0;
+        for validator in &self.0 {
+            (result, confidence) = validator.validate_with_confidence(lhs, rhs);
+            if !matches!(result, ValidationResult::Unknown(_)) {
+                break;
+            }
+        }
+        (result, confidence)
+    }
+}
+
+/// Returns whichever of `Valid` and `Invalid` more than half of the
+/// validators answered, and `Unknown` if neither has a majority. The
+/// confidence is the lowest among the validators in the majority.
+pub struct Majority<L: SynthLanguage>(pub Vec<Box<dyn Validator<L>>>);
+
+impl<L: SynthLanguage> Validator<L> for Majority<L> {
+    fn name(&self) -> String {
+        combined_name("majority", &self.0)
+    }
+
+    fn validate(&self, lhs: &Pattern<L>, rhs: &Pattern<L>) -> ValidationResult<L> {
+        self.validate_with_confidence(lhs, rhs).0
+    }
+
+    fn validate_with_confidence(
+        &self,
+        lhs: &Pattern<L>,
+        rhs: &Pattern<L>,
+    ) -> (ValidationResult<L>, f64) {
+        let results = validate_all(&self.0, lhs, rhs);
+        let valid = results.iter().filter(|(r, _)| is_valid(r)).count();
+        let valid_confidence = min_confidence(&results, is_valid);
+        let invalid_confidence = min_confidence(&results, is_invalid);
+        let (invalid, first_invalid) = invalid_results(results);
+        if 2 * valid > self.0.len() {
+            (ValidationResult::Valid, valid_confidence)
+        } else if 2 * invalid > self.0.len() {
+            (first_invalid.unwrap(), invalid_confidence)
+        } else {
+            let reason = UnknownReason::Other("no majority".into());
+            (ValidationResult::Unknown(reason), 0.0)
+        }
+    }
+}
+
+/// Returns `Valid` or `Invalid` only if every validator gave that answer,
+/// and `Unknown` otherwise. The confidence is the lowest of the
+/// validators'.
+pub struct AllAgree<L: SynthLanguage>(pub Vec<Box<dyn Validator<L>>>);
+
+impl<L: SynthLanguage> Validator<L> for AllAgree<L> {
+    fn name(&self) -> String {
+        combined_name("all", &self.0)
+    }
+
+    fn validate(&self, lhs: &Pattern<L>, rhs: &Pattern<L>) -> ValidationResult<L> {
+        self.validate_with_confidence(lhs, rhs).0
+    }
+
+    fn validate_with_confidence(
+        &self,
+        lhs: &Pattern<L>,
+        rhs: &Pattern<L>,
+    ) -> (ValidationResult<L>, f64) {
+        let results = validate_all(&self.0, lhs, rhs);
+        if let Some((ValidationResult::Unknown(reason), _)) = results
+            .iter()
+            .find(|(r, _)| matches!(r, ValidationResult::Unknown(_)))
+        {
+            return (ValidationResult::Unknown(reason.clone()), 0.0);
+        }
+        let confidence = min_confidence(&results, |_| true);
+        let valid = results.iter().filter(|(r, _)| is_valid(r)).count();
+        let (invalid, first_invalid) = invalid_results(results);
+        if valid == self.0.len() {
+            (ValidationResult::Valid, confidence)
+        } else if invalid == self.0.len() {
+            (first_invalid.unwrap(), confidence)
+        } else {
+            let reason = UnknownReason::Other("validators disagree".into());
+            (ValidationResult::Unknown(reason), 0.0)
+        }
+    }
+}
+
+fn combined_name<L: SynthLanguage>(kind: &str, validators: &[Box<dyn Validator<L>>]) -> String {
+    let names: Vec<String> = validators.iter().map(|v| v.name()).collect();
+    format!("{}({})", kind, names.join(", "))
+}
+
+fn validate_all<L: SynthLanguage>(
+    validators: &[Box<dyn Validator<L>>],
+    lhs: &Pattern<L>,
+    rhs: &Pattern<L>,
+) -> Vec<(ValidationResult<L>, f64)> {
+    validators
+        .iter()
+        .map(|v| v.validate_with_confidence(lhs, rhs))
+        .collect()
+}
+
+fn is_valid<L: SynthLanguage>(result: &ValidationResult<L>) -> bool {
+    matches!(result, ValidationResult::Valid)
+}
+
+fn is_invalid<L: SynthLanguage>(result: &ValidationResult<L>) -> bool {
+    matches!(result, ValidationResult::Invalid(_))
+}
+
+/// The lowest confidence among the results `pick` accepts.
+fn min_confidence<L: SynthLanguage>(
+    results: &[(ValidationResult<L>, f64)],
+    pick: impl Fn(&ValidationResult<L>) -> bool,
+) -> f64 {
+    results
+        .iter()
+        .filter(|(r, _)| pick(r))
+        .map(|(_, confidence)| *confidence)
+        .fold(1.0, f64::min)
+}
+
+/// How many results are `Invalid`, and the first one, preferring one
+/// with a counterexample.
+fn invalid_results<L: SynthLanguage>(
+    results: Vec<(ValidationResult<L>, f64)>,
+) -> (usize, Option<ValidationResult<L>>) {
+    let mut invalid: Vec<_> = results
+        .into_iter()
+        .map(|(r, _)| r)
+        .filter(is_invalid)
+        .collect();
+    let count = invalid.len();
+    let first = invalid
+        .iter()
+        .position(|r| matches!(r, ValidationResult::Invalid(Some(_))))
+        .unwrap_or(0);
+    (count, (count > 0).then(|| invalid.swap_remove(first)))
+}
+
+/// The variables of both sides, `lhs`'s first.
+pub(crate) fn pattern_vars<L: SynthLanguage>(lhs: &Pattern<L>, rhs: &Pattern<L>) -> Vec<Var> {
+    let mut vars = lhs.vars();
+    for var in rhs.vars() {
+        if !vars.contains(&var) {
+            vars.push(var);
+        }
+    }
+    vars
+}
+
+pub(crate) fn sort_of(var: &Var) -> Sort {
+    Sort::of_var(&var.to_string()).unwrap_or_default()
+}
+
+/// Evaluate both sides on `envs` (one value per variable in `vars`, in
+/// order) and report the first environment they disagree on. A side that
+/// is undefined (`None`) only agrees with an undefined side.
+fn test_on<L: SynthLanguage>(
+    lhs: &Pattern<L>,
+    rhs: &Pattern<L>,
+    vars: &[Var],
+    envs: &[Vec<L::Constant>],
+) -> ValidationResult<L> {
+    match eval_sides(lhs, rhs, vars, envs) {
+        Ok((l, r)) => match compare(&l, &r, |a, b| a == b, false) {
+            Comparison::Differ(i) => counterexample(vars, &envs[i]),
+            Comparison::Agree { .. } => ValidationResult::Valid,
+        },
+        Err(op) => ValidationResult::Unknown(UnknownReason::UnsupportedOp(op)),
+    }
+}
+
+fn counterexample<L: SynthLanguage>(vars: &[Var], env: &[L::Constant]) -> ValidationResult<L> {
+    ValidationResult::Invalid(Some(
+        vars.iter()
+            .zip(env)
+            .map(|(var, value)| (*var, value.clone()))
+            .collect(),
+    ))
+}
+
+enum Comparison {
+    /// The sides differ at this point.
+    Differ(usize),
+    /// The sides agree everywhere; both are defined at `defined` points.
+    Agree { defined: usize },
+}
+
+/// Compare two sides point by point. With `skip_undefined`, points where
+/// either side is undefined are ignored; otherwise an undefined side only
+/// agrees with an undefined side.
+fn compare<T>(
+    l: &[Option<T>],
+    r: &[Option<T>],
+    eq: impl Fn(&T, &T) -> bool,
+    skip_undefined: bool,
+) -> Comparison {
+    let mut defined = 0;
+    for (i, (a, b)) in l.iter().zip(r).enumerate() {
+        match (a, b) {
+            (Some(a), Some(b)) if eq(a, b) => defined += 1,
+            (Some(_), Some(_)) => return Comparison::Differ(i),
+            (None, None) => (),
+            _ if skip_undefined => (),
+            _ => return Comparison::Differ(i),
+        }
+    }
+    Comparison::Agree { defined }
+}
+
+/// Both sides evaluated with `SynthLanguage::eval` on `envs`.
+fn eval_sides<L: SynthLanguage>(
+    lhs: &Pattern<L>,
+    rhs: &Pattern<L>,
+    vars: &[Var],
+    envs: &[Vec<L::Constant>],
+) -> Result<(CVec<L>, CVec<L>), String> {
+    let columns: Vec<(Var, CVec<L>)> = vars
+        .iter()
+        .enumerate()
+        .map(|(i, var)| (*var, envs.iter().map(|env| Some(env[i].clone())).collect()))
+        .collect();
+    Ok((
+        eval_pattern(lhs, &columns, envs.len())?,
+        eval_pattern(rhs, &columns, envs.len())?,
+    ))
+}
+
+/// Both sides evaluated with `SynthLanguage::eval_f64` on `envs`.
+#[allow(clippy::type_complexity)]
+fn eval_sides_f64<L: SynthLanguage>(
+    lhs: &Pattern<L>,
+    rhs: &Pattern<L>,
+    vars: &[Var],
+    envs: &[Vec<L::Constant>],
+) -> Result<(Vec<Option<f64>>, Vec<Option<f64>>), String> {
+    let mut l = vec![];
+    let mut r = vec![];
+    for env in envs {
+        let mut values = vec![];
+        for (var, c) in vars.iter().zip(env) {
+            let value = L::constant_to_f64(c).ok_or_else(|| c.to_string())?;
+            values.push((*var, value));
+        }
+        l.push(eval_pattern_f64(lhs, &values)?);
+        r.push(eval_pattern_f64(rhs, &values)?);
+    }
+    Ok((l, r))
+}
+
+/// Evaluate `pattern` with `SynthLanguage::eval_f64`. `None` if it is
+/// undefined (not finite) at this point; fails with the offending operator
+/// if `eval_f64` does not support it.
+fn eval_pattern_f64<L: SynthLanguage>(
+    pattern: &Pattern<L>,
+    values: &[(Var, f64)],
+) -> Result<Option<f64>, String> {
+    let mut results: Vec<f64> = vec![];
+    for node in pattern.ast.as_ref() {
+        let value = match node {
+            ENodeOrVar::Var(v) => values
+                .iter()
+                .find(|(var, _)| var == v)
+                .map(|(_, value)| *value)
+                .ok_or_else(|| v.to_string())?,
+            ENodeOrVar::ENode(n) => {
+                let args: Vec<f64> = n
+                    .children()
+                    .iter()
+                    .map(|id| results[usize::from(*id)])
+                    .collect();
+                n.eval_f64(&args).ok_or_else(|| n.to_string())?
+            }
+        };
+        results.push(value);
+    }
+    Ok(results.pop().filter(|v| v.is_finite()))
+}
+
+/// Equal up to floating-point error: a relative tolerance for large
+/// values and an absolute one near zero.
+fn close(a: f64, b: f64) -> bool {
+    (a - b).abs() <= 1e-6 * a.abs().max(b.abs()).max(1.0)
+}
+
+/// Evaluate `pattern` with its variables bound to the cvecs in `columns`.
+/// Fails with the offending operator if `eval` does not support it.
+pub fn eval_pattern<L: SynthLanguage>(
+    pattern: &Pattern<L>,
+    columns: &[(Var, CVec<L>)],
+    cvec_len: usize,
+) -> Result<CVec<L>, String> {
+    let mut cvecs: Vec<CVec<L>> = vec![];
+    for node in pattern.ast.as_ref() {
+        let cvec = match node {
+            ENodeOrVar::Var(v) => columns
+                .iter()
+                .find(|(var, _)| var == v)
+                .map(|(_, cvec)| cvec.clone())
+                .ok_or_else(|| v.to_string())?,
+            ENodeOrVar::ENode(n) => {
+                let cvec = n.eval(cvec_len, |id| &cvecs[usize::from(*id)]);
+                if cvec.len() != cvec_len {
+                    return Err(n.to_string());
+                }
+                cvec
+            }
+        };
+        cvecs.push(cvec);
+    }
+    Ok(cvecs.pop().unwrap_or_default())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    struct Always(ValidationResult<egg::SymbolLang>);
+
+    impl Validator<egg::SymbolLang> for Always {
+        fn name(&self) -> String {
+            format!("{}", self.0)
+        }
+
+        fn validate(
+            &self,
+            _lhs: &Pattern<egg::SymbolLang>,
+            _rhs: &Pattern<egg::SymbolLang>,
+        ) -> ValidationResult<egg::SymbolLang> {
+            self.0.clone()
+        }
+    }
+
+    fn always(result: ValidationResult<egg::SymbolLang>) -> Box<dyn Validator<egg::SymbolLang>> {
+        Box::new(Always(result))
+    }
+
+    // Valid, with the given confidence
+    struct Unsure(f64);
+
+    impl Validator<egg::SymbolLang> for Unsure {
+        fn name(&self) -> String {
+            format!("unsure({})", self.0)
+        }
+
+        fn validate(
+            &self,
+            lhs: &Pattern<egg::SymbolLang>,
+            rhs: &Pattern<egg::SymbolLang>,
+        ) -> ValidationResult<egg::SymbolLang> {
+            self.validate_with_confidence(lhs, rhs).0
+        }
+
+        fn validate_with_confidence(
+            &self,
+            _lhs: &Pattern<egg::SymbolLang>,
+            _rhs: &Pattern<egg::SymbolLang>,
+        ) -> (ValidationResult<egg::SymbolLang>, f64) {
+            (ValidationResult::Valid, self.0)
+        }
+    }
+
+    fn unsure(confidence: f64) -> Box<dyn Validator<egg::SymbolLang>> {
+        Box::new(Unsure(confidence))
+    }
+
+    fn check(validator: &dyn Validator<egg::SymbolLang>) -> ValidationResult<egg::SymbolLang> {
+        let pat: Pattern<egg::SymbolLang> = "?a".parse().unwrap();
+        validator.validate(&pat, &pat)
+    }
+
+    fn unknown_because(result: ValidationResult<egg::SymbolLang>, why: &str) -> bool {
+        matches!(result, ValidationResult::Unknown(UnknownReason::Other(reason)) if reason == why)
+    }
+
+    #[test]
+    fn combinators() {
+        use ValidationResult::*;
+        let unknown = || Unknown(UnknownReason::Timeout);
+
+        let first = FirstDefinitive(vec![
+            always(unknown()),
+            always(Invalid(None)),
+            always(Valid),
+        ]);
+        assert!(matches!(check(&first), Invalid(None)));
+        assert!(unknown_because(
+            check(&FirstDefinitive(vec![])),
+            "no validators"
+        ));
+        assert_eq!(first.name(), "first(unknown (timeout), invalid, valid)");
+
+        let majority = Majority(vec![always(Valid), always(unknown()), always(Valid)]);
+        assert!(matches!(check(&majority), Valid));
+        let tie = Majority(vec![always(Valid), always(Invalid(None))]);
+        assert!(unknown_because(check(&tie), "no majority"));
+
+        let all = AllAgree(vec![always(Valid), always(Valid)]);
+        assert!(matches!(check(&all), Valid));
+        let disagree = AllAgree(vec![always(Valid), always(Invalid(None))]);
+        assert!(unknown_because(check(&disagree), "validators disagree"));
+        let undecided = AllAgree(vec![always(Valid), always(unknown())]);
+        assert!(matches!(check(&undecided), Unknown(UnknownReason::Timeout)));
+    }
+
+    #[test]
+    fn combined_confidence() {
+        use ValidationResult::*;
+        let pat: Pattern<egg::SymbolLang> = "?a".parse().unwrap();
+        let majority = Majority(vec![
+            unsure(0.9),
+            always(Invalid(None)),
+            unsure(0.5),
+            always(Valid),
+        ]);
+        let (result, confidence) = majority.validate_with_confidence(&pat, &pat);
+        assert!(matches!(result, Valid));
+        assert_eq!(confidence, 0.5);
+
+        let all = AllAgree(vec![unsure(0.9), always(Valid)]);
+        let (result, confidence) = all.validate_with_confidence(&pat, &pat);
+        assert!(matches!(result, Valid));
+        assert_eq!(confidence, 0.9);
+
+        let tie = Majority(vec![unsure(0.9), always(Invalid(None))]);
+        assert_eq!(tie.validate_with_confidence(&pat, &pat).1, 0.0);
+    }
+}
@@ -5,121 +5,106 @@ pub mod bool;
 use serde::{Deserialize, Serialize};
 
 
-egg::define_language! {
+define_synth_language! {
     #[derive(Serialize, Deserialize)]
-  pub enum Bool {
-    "~" = Not(Id),
-    "&" = And([Id; 2]),
-    "|" = Or([Id; 2]),
-    "^" = Xor([Id; 2]),
-    "->" = Implies([Id; 2]),
-    Lit(bool),
-    Var(egg::Symbol),
-  }
-}
+    pub enum Bool {
+        "~" = Not(Id) {
+            eval: |x| Some(x.not()),
+            z3: |_ctx, x| x.not(),
+        },
+        "&" = And([Id; 2]) {
+            eval: |x, y| Some(*x & *y),
+            z3: |ctx, x, y| z3::ast::Bool::and(ctx, &[x, y]),
+        },
+        "|" = Or([Id; 2]) {
+            eval: |x, y| Some(*x | *y),
+            z3: |ctx, x, y| z3::ast::Bool::or(ctx, &[x, y]),
+        },
+        "^" = Xor([Id; 2]) {
+            eval: |x, y| Some(*x ^ *y),
+            z3: |_ctx, x, y| x.xor(y),
+        },
+        "->" = Implies([Id; 2]) {
+            eval: |x, y| Some(!(*x) | *y),
+            z3: |_ctx, x, y| x.implies(y),
+        },
+        #[lit]
+        Lit(bool),
+        #[var]
+        Var(egg::Symbol),
+    }
 
-impl SynthLanguage for Bool {
-    type Constant = bool;
-
-    fn eval<'a, F>(&'a self, cvec_len: usize, mut get_cvec: F) -> CVec<Self>
-    where
-        F: FnMut(&'a Id) -> &'a CVec<Self>,
-    {
-        match self {
-            Bool::Not(x) => map!(get_cvec, x => Some(x.not())),
-            Bool::And([x, y]) => map!(get_cvec, x, y => Some(*x & *y)),
-            Bool::Or([x, y]) => map!(get_cvec, x, y => Some(*x | *y)),
-            Bool::Xor([x, y]) => map!(get_cvec, x, y => Some(*x ^ *y)),
-            Bool::Implies([x, y]) => map!(get_cvec, x, y => Some(!(*x) | *y)),
-            Bool::Lit(c) => vec![Some(*c); cvec_len],
-            Bool::Var(_) => vec![],
-        }
+    z3 {
+        sort: z3::ast::Bool,
+        var: |ctx, name| z3::ast::Bool::new_const(ctx, name),
+        lit: |ctx, c| z3::ast::Bool::from_bool(ctx, *c),
+        value: |b| b.as_bool(),
     }
 
-    fn mk_interval<'a, F>(&'a self, mut get_interval: F) -> Interval<Self::Constant>
-    where
-        F: FnMut(&'a Id) -> &'a Interval<Self::Constant>,
-    {
-        let unwrap_interval = |interval: &Interval<Self::Constant>| {
-            (
-                interval
-                    .low
-                    .expect("Bool shouldn't have infinite intervals"),
-                interval
-                    .high
-                    .expect("Bool shouldn't have infinite intervals"),
-            )
-        };
-        match self {
-            Bool::Lit(c) => Interval::new(Some(*c), Some(*c)),
-            Bool::Var(_) => Interval::new(Some(false), Some(true)),
-            Bool::Not(x) => {
-                let (low, high) = unwrap_interval(get_interval(x));
-                Interval::new(Some(!high), Some(!low))
-            }
-            Bool::And([x, y]) => {
-                let (x_low, x_high) = unwrap_interval(get_interval(x));
-                let (y_low, y_high) = unwrap_interval(get_interval(y));
-                Interval::new(Some(x_low && y_low), Some(x_high && y_high))
-            }
-            Bool::Or([x, y]) => {
-                let (x_low, x_high) = unwrap_interval(get_interval(x));
-                let (y_low, y_high) = unwrap_interval(get_interval(y));
-                Interval::new(Some(x_low || y_low), Some(x_high || y_high))
-            }
-            Bool::Xor([x, y]) => {
-                let (x_low, x_high) = unwrap_interval(get_interval(x));
-                let (y_low, y_high) = unwrap_interval(get_interval(y));
-                if x_low == x_high && y_low == y_high {
-                    Interval::new(Some(x_low != y_low), Some(x_low != y_low))
-                } else {
-                    Interval::new(Some(false), Some(true))
+    impl {
+        fn mk_interval<'a, F>(&'a self, mut get_interval: F) -> Interval<Self::Constant>
+        where
+            F: FnMut(&'a Id) -> &'a Interval<Self::Constant>,
+        {
+            let unwrap_interval = |interval: &Interval<Self::Constant>| {
+                (
+                    interval
+                        .low
+                        .expect("Bool shouldn't have infinite intervals"),
+                    interval
+                        .high
+                        .expect("Bool shouldn't have infinite intervals"),
+                )
+            };
+            match self {
+                Bool::Lit(c) => Interval::new(Some(*c), Some(*c)),
+                Bool::Var(_) => Interval::new(Some(false), Some(true)),
+                Bool::Not(x) => {
+                    let (low, high) = unwrap_interval(get_interval(x));
+                    Interval::new(Some(!high), Some(!low))
+                }
+                Bool::And([x, y]) => {
+                    let (x_low, x_high) = unwrap_interval(get_interval(x));
+                    let (y_low, y_high) = unwrap_interval(get_interval(y));
+                    Interval::new(Some(x_low && y_low), Some(x_high && y_high))
+                }
+                Bool::Or([x, y]) => {
+                    let (x_low, x_high) = unwrap_interval(get_interval(x));
+                    let (y_low, y_high) = unwrap_interval(get_interval(y));
+                    Interval::new(Some(x_low || y_low), Some(x_high || y_high))
+                }
+                Bool::Xor([x, y]) => {
+                    let (x_low, x_high) = unwrap_interval(get_interval(x));
+                    let (y_low, y_high) = unwrap_interval(get_interval(y));
+                    if x_low == x_high && y_low == y_high {
+                        Interval::new(Some(x_low != y_low), Some(x_low != y_low))
+                    } else {
+                        Interval::new(Some(false), Some(true))
+                    }
+                }
+                Bool::Implies([x, y]) => {
+                    let (x_low, x_high) = unwrap_interval(get_interval(x));
+                    let (y_low, y_high) = unwrap_interval(get_interval(y));
+                    Interval::new(Some(!x_high || y_low), Some(!x_low || y_high))
                 }
-            }
-            Bool::Implies([x, y]) => {
-                let (x_low, x_high) = unwrap_interval(get_interval(x));
-                let (y_low, y_high) = unwrap_interval(get_interval(y));
-                Interval::new(Some(!x_high || y_low), Some(!x_low || y_high))
             }
         }
-    }
-
-    fn initialize_vars(egraph: &mut EGraph<Self, SynthAnalysis>, vars: &[String]) {
-        let consts = vec![Some(true), Some(false)];
-        let cvecs = self_product(&consts, vars.len());
-
-        println!("varssss {:?}", vars);
 
-        egraph.analysis.cvec_len = cvecs[0].len();
-
-        for (i, v) in vars.iter().enumerate() {
-            let id = egraph.add(Bool::Var(Symbol::from(v.clone())));
-            let cvec = cvecs[i].clone();
-            egraph[id].data.cvec = cvec;
+        fn sort_values(_sort: &Sort) -> Vec<bool> {
+            vec![true, false]
         }
-    }
-
-    fn mk_var(sym: egg::Symbol) -> Self {
-        Bool::Var(sym)
-    }
 
-    fn to_var(&self) -> Option<Symbol> {
-        match self {
-            Bool::Var(v) => Some(*v),
-            _ => None,
+        fn all_values(_sort: &Sort) -> Option<Vec<bool>> {
+            Some(vec![false, true])
         }
-    }
-
-    fn validate(_lhs: &Pattern<Self>, _rhs: &Pattern<Self>) -> ValidationResult {
-        ValidationResult::Valid
-    }
-
-    fn is_constant(&self) -> bool {
-        matches!(self, Bool::Lit(_))
-    }
 
-    fn mk_constant(c: Self::Constant, _egraph: &mut EGraph<Self, SynthAnalysis>) -> Self {
-        Bool::Lit(c)
+        // Truth tables are cheap; only rules with many variables need z3
+        fn validate(lhs: &Pattern<Self>, rhs: &Pattern<Self>) -> ValidationResult<Self> {
+            match Exhaustive::default().validate(lhs, rhs) {
+                ValidationResult::Unknown(_) => Self::validate_smt(lhs, rhs, SMT_TIMEOUT),
+                result => result,
+            }
+        }
     }
 }
 
@@ -153,7 +138,9 @@ mod test {
 
         let egraph = scheduler.run(&atoms3.to_egraph(), &all_rules);
         let mut candidates = Ruleset::cvec_match(&egraph);
-        let rules3 = candidates.minimize(all_rules.clone(), scheduler).0;
+        let rules3 = candidates
+            .minimize(all_rules.clone(), scheduler, &DomainValidator)
+            .0;
         all_rules.extend(rules3);
 
         let atoms4 = iter_bool(4);
@@ -161,7 +148,9 @@ mod test {
 
         let egraph = scheduler.run(&atoms4.to_egraph(), &all_rules);
         candidates = Ruleset::cvec_match(&egraph);
-        let rules4 = candidates.minimize(all_rules.clone(), scheduler).0;
+        let rules4 = candidates
+            .minimize(all_rules.clone(), scheduler, &DomainValidator)
+            .0;
         all_rules.extend(rules4);
 
         let atoms5 = iter_bool(5);
@@ -169,7 +158,9 @@ mod test {
 
         let egraph = scheduler.run(&atoms5.to_egraph(), &all_rules);
         candidates = Ruleset::cvec_match(&egraph);
-        let rules5 = candidates.minimize(all_rules.clone(), scheduler).0;
+        let rules5 = candidates
+            .minimize(all_rules.clone(), scheduler, &DomainValidator)
+            .0;
         all_rules.extend(rules5);
 
         let expected: Ruleset<Bool> = Ruleset::new(&[
@@ -221,6 +212,7 @@ mod test {
             Limits::synthesis(),
             Limits::minimize(),
             false,
+            &DomainValidator,
         );
         all_rules.extend(rules3);
 
@@ -233,6 +225,7 @@ mod test {
             Limits::synthesis(),
             Limits::minimize(),
             false,
+            &DomainValidator,
         );
         all_rules.extend(rules4);
 
@@ -245,6 +238,7 @@ mod test {
             Limits::synthesis(),
             Limits::minimize(),
             false,
+            &DomainValidator,
         );
         all_rules.extend(rules5);
 
@@ -297,7 +291,15 @@ mod test {
         let duration = start.elapsed();
         let baseline = Ruleset::<_>::from_file("baseline/bool.rules");
 
-        logger::write_baseline(&rules, "bool", &baseline, "oopsla", duration);
+        logger::write_baseline(
+            &logger::Logger::nightly(),
+            &rules,
+            "bool",
+            &baseline,
+            "oopsla",
+            duration,
+        )
+        .unwrap();
     }
 
     #[test]
@@ -324,11 +326,24 @@ mod test {
             node: 1000000,
             match_: 200_000,
         };
-        let three: Ruleset<Bool> =
-            run_workload(iter_bool(3), Ruleset::default(), limits, limits, false);
+        let three: Ruleset<Bool> = run_workload(
+            iter_bool(3),
+            Ruleset::default(),
+            limits,
+            limits,
+            false,
+            &DomainValidator,
+        );
         three.to_file("three.txt");
 
-        let four = run_workload(iter_bool(4), Ruleset::default(), limits, limits, false);
+        let four = run_workload(
+            iter_bool(4),
+            Ruleset::default(),
+            limits,
+            limits,
+            false,
+            &DomainValidator,
+        );
         four.to_file("four.txt");
 
         let (can, cannot) = three.derive(
@@ -343,4 +358,71 @@ mod test {
         assert!(can.len() > 0);
         assert!(cannot.len() > 0);
     }
+
+    #[test]
+    fn exhaustive_validation() {
+        let check = |l: &str, r: &str| Bool::validate(&l.parse().unwrap(), &r.parse().unwrap());
+        assert!(matches!(
+            check("(-> ?a ?b)", "(| (~ ?a) ?b)"),
+            ValidationResult::Valid
+        ));
+        match check("(-> ?a ?b)", "(-> ?b ?a)") {
+            ValidationResult::Invalid(Some(cex)) => assert_ne!(cex[0].1, cex[1].1),
+            result => panic!("{}", result),
+        }
+    }
+
+    #[test]
+    fn validation_cache() {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+
+        // Exhaustive validation that counts how often it is asked
+        struct Counting(AtomicUsize);
+
+        impl Validator<Bool> for Counting {
+            fn name(&self) -> String {
+                "counting".into()
+            }
+
+            fn validate(&self, lhs: &Pattern<Bool>, rhs: &Pattern<Bool>) -> ValidationResult<Bool> {
+                self.0.fetch_add(1, Ordering::SeqCst);
+                Exhaustive::default().validate(lhs, rhs)
+            }
+        }
+
+        let path = std::env::temp_dir().join(format!("ruler-cache-{}.jsonl", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let counting = Counting(AtomicUsize::new(0));
+        let rules: Ruleset<Bool> = Ruleset::new([
+            "(& ?a ?b) ==> (& ?b ?a)",
+            "(& ?x ?y) ==> (& ?y ?x)",
+            "(-> ?a ?b) ==> (-> ?b ?a)",
+        ]);
+
+        let cache = ValidationCache::open(&path).unwrap();
+        let results: Vec<bool> = rules
+            .0
+            .values()
+            .map(|rule| rule.validate_cached(&counting, &cache).is_valid())
+            .collect();
+        assert_eq!(results, [true, true, false]);
+        // The second rule is the first with its variables renamed
+        assert_eq!(counting.0.load(Ordering::SeqCst), 2);
+        assert_eq!(cache.len(), 2);
+
+        // Results survive reopening the file, and keep the original time
+        let times: Vec<f64> = rules
+            .0
+            .values()
+            .map(|rule| rule.validate_cached(&counting, &cache).time.as_secs_f64())
+            .collect();
+        let cache = ValidationCache::open(&path).unwrap();
+        for (rule, time) in rules.0.values().zip(times) {
+            let validation = rule.validate_cached(&counting, &cache);
+            assert!((validation.time.as_secs_f64() - time).abs() < 1e-6);
+        }
+        assert_eq!(counting.0.load(Ordering::SeqCst), 2);
+
+        let _ = std::fs::remove_file(&path);
+    }
 }
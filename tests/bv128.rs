@@ -17,9 +17,10 @@ pub mod test {
         enumo::{self, Ruleset},
         logger,
         recipe_utils::{recursive_rules, Lang},
+        DomainValidator, SynthLanguage, ValidationResult, SMT_TIMEOUT,
     };
 
-    use crate::Bv;
+    use crate::{Bv, BV};
 
     fn gen() -> (Ruleset<Bv>, Duration) {
         let start = Instant::now();
@@ -34,6 +35,7 @@ pub mod test {
             5,
             lang.clone(),
             Ruleset::default(),
+            &DomainValidator,
         ));
 
         // too slow for 128
@@ -60,16 +62,41 @@ pub mod test {
         (rules, duration)
     }
 
+    #[test]
+    fn wide_literals() {
+        let check = |l: &str, r: &str| {
+            Bv::validate_smt(&l.parse().unwrap(), &r.parse().unwrap(), SMT_TIMEOUT)
+        };
+        // 2^64, which doesn't survive a trip through a u64
+        assert!(matches!(
+            check("(<< 1 64)", "18446744073709551616"),
+            ValidationResult::Valid
+        ));
+        match check("(& ?a 18446744073709551616)", "0") {
+            ValidationResult::Invalid(Some(cex)) => {
+                let bit = BV::from(1 << 64);
+                assert_eq!(cex[0].1 & bit, bit);
+            }
+            result => panic!("{}", result),
+        }
+    }
+
     #[test]
     fn compare() {
         let domain = "BV128";
-        // Port the bv4 rules into domain
-        let actual_bv4_rules: Ruleset<_> = bv4_fancy_rules();
-        let ported_bv4_rules: Ruleset<Bv> = Ruleset::new(actual_bv4_rules.to_str_vec());
+        // The bv4 rules to port into domain
+        let bv4_rules = bv4_fancy_rules();
 
         // Generate the rules directly
         let (gen, gen_time): (Ruleset<Bv>, Duration) = gen();
 
-        logger::write_bv_derivability(domain, gen, gen_time, ported_bv4_rules)
+        logger::write_bv_derivability(
+            &logger::Logger::nightly(),
+            domain,
+            gen,
+            gen_time,
+            &bv4_rules,
+        )
+        .unwrap();
     }
 }
@@ -16,8 +16,11 @@ pub mod test {
     use ruler::{
         enumo::{self, Filter, Ruleset, Workload},
         logger,
-        recipe_utils::{base_lang, iter_metric, recursive_rules, run_workload, Lang},
-        Limits,
+        recipe_utils::{
+            base_lang, iter_metric, recursive_rules, run_workload, run_workload_with, Lang,
+            RunOptions,
+        },
+        DomainValidator, Limits,
     };
 
     use crate::Bv;
@@ -35,6 +38,7 @@ pub mod test {
             5,
             lang.clone(),
             Ruleset::default(),
+            &DomainValidator,
         ));
 
         let a6_canon = iter_metric(base_lang(2), "EXPR", enumo::Metric::Atoms, 6)
@@ -55,22 +59,60 @@ pub mod test {
             Limits::synthesis(),
             Limits::minimize(),
             true,
+            &DomainValidator,
         ));
 
         let duration = start.elapsed();
         (rules, duration)
     }
 
+    #[test]
+    fn run_workload_refines_candidates() {
+        // The corner cases can't tell the first two apart, so the invalid
+        // candidate between them is refined away before minimizing
+        let workload = Workload::new(["(& x 2)", "(& (>> x 1) 2)", "(& x (& x 2))"]);
+        let rules: Ruleset<Bv> = run_workload(
+            workload.clone(),
+            Ruleset::default(),
+            Limits::synthesis(),
+            Limits::minimize(),
+            false,
+            &DomainValidator,
+        );
+        assert!(!rules.is_empty());
+        assert!(rules.0.values().all(|rule| rule.is_valid()));
+        assert!(!rules.to_str_vec().iter().any(|r| r.contains(">>")));
+
+        // Without refinement `minimize` still weeds it out
+        let options = RunOptions::new(
+            Limits::synthesis(),
+            Limits::minimize(),
+            false,
+            &DomainValidator,
+        )
+        .with_cegis_rounds(0);
+        assert_eq!(
+            run_workload_with(workload, Ruleset::default(), &options),
+            rules
+        );
+    }
+
     #[test]
     fn compare() {
         let domain = "BV16";
-        // Port the bv4 rules into domain
-        let actual_bv4_rules: Ruleset<_> = bv4_fancy_rules();
-        let ported_bv4_rules: Ruleset<Bv> = Ruleset::new(actual_bv4_rules.to_str_vec());
+        // The bv4 rules to port into domain
+        let bv4_rules = bv4_fancy_rules();
 
         // Generate the rules directly
         let (gen, gen_time): (Ruleset<Bv>, Duration) = gen();
 
-        logger::write_bv_derivability(domain, gen, gen_time, ported_bv4_rules)
+        logger::write_bv_derivability(
+            &logger::Logger::nightly(),
+            domain,
+            gen,
+            gen_time,
+            &bv4_rules,
+        )
+        .unwrap();
     }
 }
@@ -0,0 +1,95 @@
+/*!
+256 bit implementation of Bitvectors, on big-integer-backed `WideBV`s.
+!*/
+
+ruler::impl_bv!(256, WideBV);
+
+#[path = "./recipes/bv4_fancy.rs"]
+pub mod bv4_fancy;
+
+#[cfg(test)]
+pub mod test {
+    use ruler::{
+        enumo::Ruleset, port_ruleset, DomainValidator, Pattern, RandomTesting, SynthLanguage,
+        ValidationResult, Validator, SMT_TIMEOUT,
+    };
+
+    use crate::{Bv, BV};
+
+    const ALL_ONES: &str =
+        "115792089237316195423570985008687907853269984665640564039457584007913129639935";
+    const MIN: &str =
+        "57896044618658097711785492504343953926634992332820282019728792003956564819968";
+    const MAX: &str =
+        "57896044618658097711785492504343953926634992332820282019728792003956564819967";
+
+    #[test]
+    fn wide_literals() {
+        let check = |l: &str, r: &str| {
+            Bv::validate_smt(&l.parse().unwrap(), &r.parse().unwrap(), SMT_TIMEOUT)
+        };
+        assert!(matches!(check("(~ 0)", ALL_ONES), ValidationResult::Valid));
+        assert!(matches!(check("(<< 1 255)", MIN), ValidationResult::Valid));
+        assert!(matches!(
+            check(
+                "(<< 1 200)",
+                "1606938044258990275541962092341162602522202993782792835301376"
+            ),
+            ValidationResult::Valid
+        ));
+
+        // Counterexamples come back at full width
+        let min: BV = MIN.parse().unwrap();
+        match check(&format!("(& ?a {})", MIN), "0") {
+            ValidationResult::Invalid(Some(cex)) => {
+                assert_eq!(cex[0].1.clone() & min.clone(), min)
+            }
+            result => panic!("{}", result),
+        }
+    }
+
+    #[test]
+    fn eval_agrees_with_z3() {
+        let rules = [
+            ("(sdiv ?a (- 1))", "(- ?a)"),
+            ("(ashr ?a 255)", "(- (slt ?a 0))"),
+            ("(rotl ?a 256)", "?a"),
+            ("(udiv ?a 0)", "(~ 0)"),
+            ("(* ?a 2)", "(<< ?a 1)"),
+            ("(<< ?a 128)", "0"),
+            ("(slt ?a ?b)", "(ult ?a ?b)"),
+        ];
+        for (l, r) in rules {
+            let (l, r): (Pattern<Bv>, Pattern<Bv>) = (l.parse().unwrap(), r.parse().unwrap());
+            let sampled = RandomTesting::new(64).validate(&l, &r);
+            let smt = Bv::validate_smt(&l, &r, SMT_TIMEOUT);
+            assert!(!matches!(smt, ValidationResult::Unknown(_)));
+            assert_eq!(
+                matches!(sampled, ValidationResult::Valid),
+                matches!(smt, ValidationResult::Valid),
+                "{} ==> {}",
+                l,
+                r
+            );
+        }
+    }
+
+    #[test]
+    fn port_from_bv4() {
+        let bv4_rules: Ruleset<crate::bv4_fancy::Bv> = Ruleset::new([
+            "(-- ?a 15) ==> (+ ?a 1)",
+            "(+ ?a 8) ==> (-- ?a 8)",
+            "(& ?a 7) ==> (& ?a (~ 8))",
+            "(<< ?a 4) ==> 0",
+        ]);
+
+        let (sound, unsound): (Ruleset<Bv>, _) = port_ruleset(&bv4_rules, &DomainValidator);
+        let expected = Ruleset::new([
+            format!("(-- ?a {}) ==> (+ ?a 1)", ALL_ONES),
+            format!("(+ ?a {}) ==> (-- ?a {})", MIN, MIN),
+            format!("(& ?a {}) ==> (& ?a (~ {}))", MAX, MIN),
+        ]);
+        assert_eq!(sound, expected);
+        assert_eq!(unsound, Ruleset::new(["(<< ?a 4) ==> 0"]));
+    }
+}
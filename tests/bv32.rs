@@ -24,7 +24,9 @@ impl Bv {
         let mut candidates = Ruleset::cvec_match(&compressed);
 
         let num_prior = prior.len();
-        let chosen = candidates.minimize(prior, Scheduler::Compress(limits)).0;
+        let chosen = candidates
+            .minimize(prior, Scheduler::Compress(limits), &DomainValidator)
+            .0;
         let time = t.elapsed().as_secs_f64();
 
         println!(
@@ -50,9 +52,9 @@ pub mod test {
 
     use ruler::{
         enumo::{self, Filter, Ruleset, Workload},
-        logger,
+        logger, port_ruleset,
         recipe_utils::{base_lang, iter_metric, recursive_rules, run_workload, Lang},
-        Limits,
+        BvSampler, CvecSampler, DomainValidator, Limits,
     };
 
     use crate::Bv;
@@ -70,6 +72,7 @@ pub mod test {
             5,
             lang.clone(),
             Ruleset::default(),
+            &DomainValidator,
         ));
 
         let a6_canon = iter_metric(base_lang(2), "EXPR", enumo::Metric::Atoms, 6)
@@ -90,6 +93,7 @@ pub mod test {
             Limits::synthesis(),
             Limits::minimize(),
             true,
+            &DomainValidator,
         ));
 
         let duration = start.elapsed();
@@ -108,19 +112,76 @@ pub mod test {
         let duration = start.elapsed();
         let baseline = Ruleset::<_>::from_file("baseline/bv32.rules");
 
-        logger::write_baseline(&rules, "bv32", &baseline, "oopsla", duration);
+        logger::write_baseline(
+            &logger::Logger::nightly(),
+            &rules,
+            "bv32",
+            &baseline,
+            "oopsla",
+            duration,
+        )
+        .unwrap();
+    }
+
+    #[test]
+    fn port_from_bv4() {
+        let bv4_rules: Ruleset<crate::bv4_fancy::Bv> = Ruleset::new([
+            "(-- ?a 15) ==> (+ ?a 1)",
+            "(+ ?a 8) ==> (-- ?a 8)",
+            "(& ?a 7) ==> (& ?a (~ 8))",
+            "(<< ?a 4) ==> 0",
+        ]);
+        assert!(bv4_rules.0.values().all(|rule| rule.is_valid()));
+
+        let (sound, unsound): (Ruleset<Bv>, _) = port_ruleset(&bv4_rules, &DomainValidator);
+        let expected = Ruleset::new([
+            "(-- ?a 4294967295) ==> (+ ?a 1)",
+            "(+ ?a 2147483648) ==> (-- ?a 2147483648)",
+            "(& ?a 2147483647) ==> (& ?a (~ 2147483648))",
+        ]);
+        assert_eq!(sound, expected);
+        assert_eq!(unsound, Ruleset::new(["(<< ?a 4) ==> 0"]));
+        assert!(unsound.0.values().all(|rule| rule.validation.is_some()));
+    }
+
+    #[test]
+    fn bit_patterns_split_classes() {
+        // Equal on 0, 1, -1, MIN, MAX and their neighbours, but not on 2
+        let workload = Workload::new(["(& x 2)", "(& (>> x 1) 2)"]);
+        let candidates = |sampler: CvecSampler| {
+            let egraph = workload.to_egraph_with::<Bv>(sampler);
+            Ruleset::cvec_match(&egraph)
+        };
+
+        assert!(!candidates(CvecSampler::default()).is_empty());
+        let mixed = CvecSampler::default().with_bv(BvSampler::mixed(32));
+        assert!(candidates(mixed).is_empty());
+
+        let egraph = workload.to_egraph_with::<Bv>(mixed);
+        assert_eq!(egraph.analysis.cvec_len, 32);
+
+        // 32-bit domains mix in the other values by default
+        let egraph = workload.to_egraph::<Bv>();
+        assert_eq!(egraph.analysis.sampler.bv, BvSampler::mixed(32));
+        assert!(Ruleset::cvec_match(&egraph).is_empty());
     }
 
     #[test]
     fn compare() {
         let domain = "BV32";
-        // Port the bv4 rules into domain
-        let actual_bv4_rules: Ruleset<_> = bv4_fancy_rules();
-        let ported_bv4_rules: Ruleset<Bv> = Ruleset::new(actual_bv4_rules.to_str_vec());
+        // The bv4 rules to port into domain
+        let bv4_rules = bv4_fancy_rules();
 
         // Generate the rules directly
         let (gen, gen_time): (Ruleset<Bv>, Duration) = gen();
 
-        logger::write_bv_derivability(domain, gen, gen_time, ported_bv4_rules)
+        logger::write_bv_derivability(
+            &logger::Logger::nightly(),
+            domain,
+            gen,
+            gen_time,
+            &bv4_rules,
+        )
+        .unwrap();
     }
 }
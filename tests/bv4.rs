@@ -15,7 +15,7 @@ pub mod test {
     use super::*;
     use crate::bv4_base::bv4_rules;
     use crate::bv4_fancy::bv4_fancy_rules;
-    use ruler::enumo::Ruleset;
+    use ruler::enumo::{Rule, Ruleset, Scheduler, Workload};
     use std::time::Instant;
 
     #[test]
@@ -30,13 +30,168 @@ pub mod test {
         let duration = start.elapsed();
         let baseline = Ruleset::<bv4_fancy::Bv>::from_file("baseline/bv4.rules");
 
-        logger::write_baseline(&rules, "bv4_fancy", &baseline, "oopsla", duration);
+        logger::write_baseline(
+            &logger::Logger::nightly(),
+            &rules,
+            "bv4_fancy",
+            &baseline,
+            "oopsla",
+            duration,
+        )
+        .unwrap();
 
         let start = Instant::now();
         let rules = bv4_rules();
         let duration = start.elapsed();
         let baseline = Ruleset::<bv4_base::Bv>::from_file("baseline/bv4.rules");
 
-        logger::write_baseline(&rules, "bv4_base", &baseline, "oopsla", duration);
+        logger::write_baseline(
+            &logger::Logger::nightly(),
+            &rules,
+            "bv4_base",
+            &baseline,
+            "oopsla",
+            duration,
+        )
+        .unwrap();
+    }
+
+    #[test]
+    fn exhaustive_validation() {
+        let check = |l: &str, r: &str| Bv::validate(&l.parse().unwrap(), &r.parse().unwrap());
+        assert!(matches!(
+            check("(- ?a)", "(+ (~ ?a) 1)"),
+            ValidationResult::Valid
+        ));
+        match check("(<< ?a ?b)", "(>> ?a ?b)") {
+            ValidationResult::Invalid(Some(cex)) => {
+                let (a, b) = (cex[0].1, cex[1].1);
+                assert_ne!(a.my_shl(b), a.my_shr(b));
+            }
+            result => panic!("{}", result),
+        }
+    }
+
+    #[test]
+    fn known_bits() {
+        let egraph = Workload::new([
+            "(& (<< x 1) 1)",
+            "(<< (<< x 2) 2)",
+            "(| (<< x 1) 1)",
+            "(+ (<< x 1) 1)",
+        ])
+        .to_egraph::<Bv>();
+        let id = |s: &str| egraph.lookup_expr(&s.parse().unwrap()).unwrap();
+        let bits = |s: &str| egraph[id(s)].data.known_bits.clone().unwrap();
+
+        // Folded, though x is unknown
+        assert_eq!(id("(& (<< x 1) 1)"), id("0"));
+        assert_eq!(id("(<< (<< x 2) 2)"), id("0"));
+        assert_eq!(bits("(+ (<< x 1) 1)"), bits("(| (<< x 1) 1)"));
+        assert_eq!(bits("(+ (<< x 1) 1)").bit(0), Some(true));
+        assert!(bits("(<< x 1)").is_disjoint(&bits("(| (<< x 1) 1)")));
+    }
+
+    #[test]
+    fn known_bits_precondition() {
+        let mut rules: Ruleset<Bv> = Ruleset::default();
+        rules.add(Rule {
+            name: "shl-shr-even".into(),
+            lhs: "(<< (>> ?a 1) 1)".parse().unwrap(),
+            rhs: "?a".parse().unwrap(),
+            rewrite: egg::rewrite!("shl-shr-even"; "(<< (>> ?a 1) 1)" => "?a"
+                if known_bits_cond::<Bv, _>("?a", |k| k.bit(0) == Some(false))),
+            validation: None,
+        });
+
+        let egraph = Workload::new(["(<< (>> (<< x 1) 1) 1)", "(<< (>> x 1) 1)"]).to_egraph::<Bv>();
+        let egraph = Scheduler::Simple(Limits::deriving()).run(&egraph, &rules);
+        let id = |s: &str| egraph.lookup_expr(&s.parse().unwrap()).unwrap();
+
+        assert_eq!(id("(<< (>> (<< x 1) 1) 1)"), id("(<< x 1)"));
+        assert_ne!(id("(<< (>> x 1) 1)"), id("x"));
+    }
+
+    #[test]
+    fn smtlib_ops_agree_with_z3() {
+        let rules = [
+            ("(udiv ?a 0)", "(~ 0)"),
+            ("(urem ?a 0)", "?a"),
+            ("(sdiv ?a 0)", "(-- (<< (slt ?a 0) 1) 1)"),
+            ("(srem ?a 0)", "?a"),
+            ("(sdiv ?a (- 1))", "(- ?a)"),
+            ("(srem (- ?a) ?b)", "(- (srem ?a ?b))"),
+            ("(sdiv ?a ?b)", "(udiv ?a ?b)"),
+            ("(ashr ?a 3)", "(- (slt ?a 0))"),
+            ("(rotl ?a ?b)", "(rotr ?a (- ?b))"),
+            ("(rotl ?a 4)", "?a"),
+            ("(rotl ?a ?b)", "(<< ?a ?b)"),
+            ("(ugt ?a ?b)", "(ult ?b ?a)"),
+            ("(sge ?a ?b)", "(sle ?b ?a)"),
+            ("(slt ?a ?b)", "(ult ?a ?b)"),
+            ("(ule ?a ?b)", "(-- 1 (ugt ?a ?b))"),
+        ];
+        for (l, r) in rules {
+            let (l, r): (Pattern<Bv>, Pattern<Bv>) = (l.parse().unwrap(), r.parse().unwrap());
+            let exhaustive = Exhaustive::default().validate(&l, &r);
+            let smt = Bv::validate_smt(&l, &r, SMT_TIMEOUT);
+            assert!(!matches!(exhaustive, ValidationResult::Unknown(_)));
+            assert_eq!(
+                matches!(exhaustive, ValidationResult::Valid),
+                matches!(smt, ValidationResult::Valid),
+                "{} ==> {}",
+                l,
+                r
+            );
+        }
+    }
+
+    #[test]
+    fn validate_all() {
+        let rules: Ruleset<Bv> = Ruleset::new([
+            "(+ ?a ?b) ==> (+ ?b ?a)",
+            "(- ?a) ==> (+ (~ ?a) 1)",
+            "(<< ?a ?b) ==> (>> ?a ?b)",
+        ]);
+        let validations = rules.validate_all(&Smt::default());
+        assert_eq!(validations.len(), rules.len());
+        for (name, rule) in &rules {
+            assert_eq!(validations[name].is_valid(), rule.is_valid());
+        }
+    }
+
+    #[test]
+    fn smtlib_validation() {
+        let lhs: Pattern<Bv> = "(- ?a)".parse().unwrap();
+        let rhs: Pattern<Bv> = "(+ (~ ?a) 1)".parse().unwrap();
+        assert_eq!(
+            smtlib_query(&lhs, &rhs).unwrap(),
+            "(set-option :produce-models true)\n\
+             (set-logic QF_BV)\n\
+             (declare-const |a| (_ BitVec 4))\n\
+             (assert (not (= (bvneg |a|) (bvadd (bvnot |a|) (_ bv1 4)))))\n\
+             (check-sat)\n\
+             (get-value (|a|))\n\
+             (exit)\n"
+        );
+
+        // Only run the solver if it is installed
+        if std::process::Command::new("z3")
+            .arg("-version")
+            .output()
+            .is_err()
+        {
+            return;
+        }
+        let z3 = SmtLib::z3();
+        assert!(matches!(z3.validate(&lhs, &rhs), ValidationResult::Valid));
+        let rhs: Pattern<Bv> = "(~ ?a)".parse().unwrap();
+        match z3.validate(&lhs, &rhs) {
+            ValidationResult::Invalid(Some(cex)) => {
+                let a = cex[0].1;
+                assert_ne!(a.wrapping_neg(), !a);
+            }
+            result => panic!("{}", result),
+        }
     }
 }
@@ -17,7 +17,7 @@ pub mod test {
         enumo::{self, Filter, Ruleset, Workload},
         logger,
         recipe_utils::{base_lang, iter_metric, recursive_rules, run_workload, Lang},
-        Limits,
+        DomainValidator, Limits,
     };
 
     use crate::Bv;
@@ -35,6 +35,7 @@ pub mod test {
             5,
             lang.clone(),
             Ruleset::default(),
+            &DomainValidator,
         ));
 
         let a6_canon = iter_metric(base_lang(2), "EXPR", enumo::Metric::Atoms, 6)
@@ -55,6 +56,7 @@ pub mod test {
             Limits::synthesis(),
             Limits::minimize(),
             true,
+            &DomainValidator,
         ));
 
         let duration = start.elapsed();
@@ -64,13 +66,19 @@ pub mod test {
     #[test]
     fn compare() {
         let domain = "BV64";
-        // Port the bv4 rules into domain
-        let actual_bv4_rules: Ruleset<_> = bv4_fancy_rules();
-        let ported_bv4_rules: Ruleset<Bv> = Ruleset::new(actual_bv4_rules.to_str_vec());
+        // The bv4 rules to port into domain
+        let bv4_rules = bv4_fancy_rules();
 
         // Generate the rules directly
         let (gen, gen_time): (Ruleset<Bv>, Duration) = gen();
 
-        logger::write_bv_derivability(domain, gen, gen_time, ported_bv4_rules)
+        logger::write_bv_derivability(
+            &logger::Logger::nightly(),
+            domain,
+            gen,
+            gen_time,
+            &bv4_rules,
+        )
+        .unwrap();
     }
 }
@@ -5,7 +5,9 @@
 use std::time::Instant;
 
 use num::rational::Ratio;
-use num::BigInt;
+use num::{BigInt, ToPrimitive};
+use rand::Rng;
+use rand_pcg::Pcg64;
 use ruler::*;
 #[path = "./recipes/exponential.rs"]
 pub mod exponential;
@@ -85,9 +87,52 @@ impl SynthLanguage for Exponential {
         Exponential::Num(c)
     }
 
-    // no validation possible
-    fn validate(_lhs: &Pattern<Self>, _rhs: &Pattern<Self>) -> ValidationResult {
-        ValidationResult::Valid
+    fn sort_values(_sort: &Sort) -> Vec<Self::Constant> {
+        ["-2", "-1", "-1/2", "0", "1/3", "1/2", "1", "2", "3", "10"]
+            .iter()
+            .map(|s| s.parse().unwrap())
+            .collect()
+    }
+
+    // Fractions in [-10, 10] with denominators up to 16
+    fn random_value(_sort: &Sort, rng: &mut Pcg64) -> Option<Self::Constant> {
+        let denom: i64 = rng.gen_range(1, 17);
+        let numer: i64 = rng.gen_range(-10 * denom, 10 * denom + 1);
+        Some(Rational::new(numer.into(), denom.into()))
+    }
+
+    fn eval_f64(&self, args: &[f64]) -> Option<f64> {
+        Some(match (self, args) {
+            (Exponential::Exp(_), [x]) => x.exp(),
+            (Exponential::Log(_), [x]) => x.ln(),
+            (Exponential::Pow(_), [x, y]) => x.powf(*y),
+            (Exponential::Sqrt(_), [x]) => x.sqrt(),
+            (Exponential::Cbrt(_), [x]) => x.cbrt(),
+            (Exponential::Neg(_), [x]) => -x,
+            (Exponential::Add(_), [x, y]) => x + y,
+            (Exponential::Sub(_), [x, y]) => x - y,
+            (Exponential::Mul(_), [x, y]) => x * y,
+            (Exponential::Div(_), [x, y]) => x / y,
+            (Exponential::If(_), [c, t, e]) => {
+                if *c != 0.0 {
+                    *t
+                } else {
+                    *e
+                }
+            }
+            (Exponential::Abs(_), [x]) => x.abs(),
+            (Exponential::Num(c), []) => c.to_f64()?,
+            _ => return None,
+        })
+    }
+
+    fn constant_to_f64(c: &Self::Constant) -> Option<f64> {
+        c.to_f64()
+    }
+
+    // no exact validation possible, so test on random points
+    fn validate(lhs: &Pattern<Self>, rhs: &Pattern<Self>) -> ValidationResult<Self> {
+        RandomTesting::new(64).validate(lhs, rhs)
     }
 
     fn is_rule_lifting() -> bool {
@@ -281,6 +326,32 @@ mod test {
         ])
     }
 
+    #[test]
+    fn random_testing() {
+        let validator = RandomTesting::new(64);
+        let check = |rule: &str| {
+            let rule: enumo::Rule<Exponential> = enumo::Rule::from_string(rule).unwrap().0;
+            validator.validate_with_confidence(&rule.lhs, &rule.rhs)
+        };
+
+        // Undefined at non-positive points, which are skipped
+        let (result, confidence) = check("(exp (log ?a)) ==> ?a");
+        assert!(matches!(result, ValidationResult::Valid));
+        assert!(0.0 < confidence && confidence < 1.0);
+        // More defined points than the ten fixed `sort_values` could give
+        assert!(confidence > 1.0 - 0.99f64.powi(10));
+
+        let (result, _) = check("(exp (+ ?a ?b)) ==> (* (exp ?a) (exp ?b))");
+        assert!(matches!(result, ValidationResult::Valid));
+
+        let (result, confidence) = check("(exp (+ ?a ?b)) ==> (+ (exp ?a) (exp ?b))");
+        assert!(matches!(result, ValidationResult::Invalid(Some(_))));
+        assert_eq!(confidence, 1.0);
+
+        let (result, _) = check("(log (~ (fabs ?a))) ==> ?a");
+        assert!(matches!(result, ValidationResult::Unknown(_)));
+    }
+
     #[test]
     fn run() {
         // Skip this test in github actions
@@ -294,6 +365,14 @@ mod test {
         let rules = make_rules();
         let duration = start.elapsed();
 
-        logger::write_baseline(&rules, "exponential", &herbie, "herbie", duration);
+        logger::write_baseline(
+            &logger::Logger::nightly(),
+            &rules,
+            "exponential",
+            &herbie,
+            "herbie",
+            duration,
+        )
+        .unwrap();
     }
 }
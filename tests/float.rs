@@ -0,0 +1,91 @@
+use egg::{Extractor, Language};
+use ordered_float::OrderedFloat;
+use ruler::*;
+
+pub type Constant = OrderedFloat<f64>;
+
+define_synth_language! {
+    pub enum Float {
+        "+" = Add([Id; 2]) {
+            eval: |x, y| Some(*x + *y),
+        },
+        "*" = Mul([Id; 2]) {
+            eval: |x, y| Some(*x * *y),
+        },
+        #[lit]
+        Lit(Constant),
+        #[var]
+        Var(egg::Symbol),
+    }
+
+    impl {
+        fn sort_values(_sort: &Sort) -> Vec<Constant> {
+            vec![-1.0, 0.0, 1.0, 2.5].into_iter().map(OrderedFloat).collect()
+        }
+
+        fn cvec_eq(cvec1: &CVec<Self>, cvec2: &CVec<Self>) -> bool {
+            cvec_eq_by(cvec1, cvec2, |a, b| approx_eq(a.0, b.0, 1e-9, 4))
+        }
+
+        fn constant_key(c: &Constant) -> Constant {
+            OrderedFloat(round_sig(c.0, 9))
+        }
+
+        // Multiplications are expensive
+        fn node_cost<C>(&self, mut costs: C) -> usize
+        where
+            C: FnMut(Id) -> usize,
+        {
+            let own = if let Float::Mul(_) = self { 10 } else { 1 };
+            self.fold(own, |sum, id| sum.saturating_add(costs(id)))
+        }
+
+        fn validate(_lhs: &Pattern<Self>, _rhs: &Pattern<Self>) -> ValidationResult<Self> {
+            ValidationResult::Unknown(UnknownReason::Other("no float validator".into()))
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use ruler::enumo::{Ruleset, Workload};
+
+    #[test]
+    fn approx_eq_is_nan_aware() {
+        assert!(approx_eq(0.1 + 0.2, 0.3, 0.0, 4));
+        assert!(!approx_eq(1.0, 1.1, 1e-9, 4));
+        assert!(approx_eq(f64::NAN, f64::NAN, 0.0, 0));
+        assert!(!approx_eq(f64::NAN, 0.0, f64::INFINITY, 0));
+        assert_eq!(round_sig(0.1 + 0.2, 9), 0.3);
+        assert_eq!(round_sig(-1234.5678, 3), -1230.0);
+    }
+
+    #[test]
+    fn rounding_error_still_matches() {
+        let egraph =
+            Workload::new(["(+ (+ x 0.1) 0.2)", "(+ x 0.3)", "(* x 0.3)"]).to_egraph::<Float>();
+        let expected = "(+ (+ ?a 0.1) 0.2) ==> (+ ?a 0.3)";
+
+        let candidates: Ruleset<Float> = Ruleset::cvec_match(&egraph);
+        assert!(candidates.0.contains_key(expected));
+        assert!(!candidates.to_str_vec().iter().any(|r| r.contains('*')));
+
+        let candidates: Ruleset<Float> = Ruleset::fast_cvec_match(&egraph);
+        assert!(candidates.0.contains_key(expected));
+    }
+
+    #[test]
+    fn cost_hook_picks_representatives() {
+        let mut egraph = Workload::new(["(* x 2)", "(+ x x)"]).to_egraph::<Float>();
+        let mul = egraph.add_expr(&"(* x 2)".parse().unwrap());
+        let add = egraph.add_expr(&"(+ x x)".parse().unwrap());
+        egraph.union(mul, add);
+        egraph.rebuild();
+
+        let id = egraph.find(mul);
+        assert_eq!(egraph[id].data.simplest.to_string(), "(+ x x)");
+        let (cost, best) = Extractor::new(&egraph, ExtractableAstSize).find_best(id);
+        assert_eq!((cost, best.to_string()), (3, "(+ x x)".to_string()));
+    }
+}
@@ -1,5 +1,6 @@
 use num::{ToPrimitive, Zero};
 use ruler::*;
+use std::time::Duration;
 use z3::ast::Ast;
 
 type Constant = i64;
@@ -103,25 +104,36 @@ impl SynthLanguage for Pred {
         }
     }
 
-    fn initialize_vars(egraph: &mut EGraph<Self, SynthAnalysis>, vars: &[String]) {
-        let consts = vec![
-            Some((-10).to_i64().unwrap()),
-            Some((-1).to_i64().unwrap()),
-            Some(0.to_i64().unwrap()),
-            Some(1.to_i64().unwrap()),
-            Some(2.to_i64().unwrap()),
-            Some(5.to_i64().unwrap()),
-            Some(100.to_i64().unwrap()),
-        ];
-        let cvecs = self_product(&consts, vars.len());
+    fn mk_interval<'a, F>(&'a self, mut get_interval: F) -> Interval<Self::Constant>
+    where
+        F: FnMut(&'a Id) -> &'a Interval<Self::Constant>,
+    {
+        match self {
+            Pred::Lit(c) => Interval::constant(*c),
+            Pred::Var(_) => Interval::default(),
+            Pred::Neg(x) => get_interval(x).neg(),
+            Pred::Add([x, y]) => get_interval(x).add(get_interval(y)),
+            Pred::Sub([x, y]) => get_interval(x).sub(get_interval(y)),
+            Pred::Mul([x, y]) => get_interval(x).mul(get_interval(y)),
+            // Division by zero is 0, which `div` covers by giving up on
+            // divisors that may be zero
+            Pred::Div([x, y]) => get_interval(x).div(get_interval(y)),
+            Pred::Min([x, y]) => get_interval(x).min(get_interval(y)),
+            Pred::Max([x, y]) => get_interval(x).max(get_interval(y)),
+            Pred::Select([x, y, z]) => {
+                Interval::select(get_interval(x), get_interval(y), get_interval(z))
+            }
+            // Comparisons and logical operators give 0 or 1
+            _ => Interval::new(Some(0), Some(1)),
+        }
+    }
 
-        egraph.analysis.cvec_len = cvecs[0].len();
+    fn sort_values(_sort: &Sort) -> Vec<Self::Constant> {
+        vec![-10, -1, 0, 1, 2, 5, 100]
+    }
 
-        for (i, v) in vars.iter().enumerate() {
-            let id = egraph.add(Pred::Var(Symbol::from(v.clone())));
-            let cvec = cvecs[i].clone();
-            egraph[id].data.cvec = cvec;
-        }
+    fn sort_edge_cases(_sort: &Sort) -> Vec<Self::Constant> {
+        vec![-1, 0, 1]
     }
 
     fn to_var(&self) -> Option<Symbol> {
@@ -144,19 +156,37 @@ impl SynthLanguage for Pred {
         Pred::Lit(c)
     }
 
-    fn validate(lhs: &Pattern<Self>, rhs: &Pattern<Self>) -> ValidationResult {
-        let mut cfg = z3::Config::new();
-        cfg.set_timeout_msec(1000);
-        let ctx = z3::Context::new(&cfg);
-        let solver = z3::Solver::new(&ctx);
-        let lexpr = egg_to_z3(&ctx, Self::instantiate(lhs).as_ref());
-        let rexpr = egg_to_z3(&ctx, Self::instantiate(rhs).as_ref());
-        solver.assert(&lexpr._eq(&rexpr).not());
-        match solver.check() {
-            z3::SatResult::Unsat => ValidationResult::Valid,
-            z3::SatResult::Unknown => ValidationResult::Unknown,
-            z3::SatResult::Sat => ValidationResult::Invalid,
-        }
+    fn validate(lhs: &Pattern<Self>, rhs: &Pattern<Self>) -> ValidationResult<Self> {
+        Self::validate_smt(lhs, rhs, SMT_TIMEOUT)
+    }
+
+    fn validate_smt(
+        lhs: &Pattern<Self>,
+        rhs: &Pattern<Self>,
+        timeout: Duration,
+    ) -> ValidationResult<Self> {
+        with_z3_solver(timeout, |ctx, solver| {
+            let lexpr = egg_to_z3(ctx, Self::instantiate(lhs).as_ref());
+            let rexpr = egg_to_z3(ctx, Self::instantiate(rhs).as_ref());
+            solver.assert(&lexpr._eq(&rexpr).not());
+            match solver.check() {
+                z3::SatResult::Unsat => ValidationResult::Valid,
+                z3::SatResult::Unknown => ValidationResult::Unknown(UnknownReason::from_z3(solver)),
+                z3::SatResult::Sat => {
+                    let counterexample = solver.get_model().and_then(|model| {
+                        lhs.vars()
+                            .into_iter()
+                            .map(|var| {
+                                let name = var.to_string();
+                                let ast = z3::ast::Int::new_const(ctx, &name[1..]);
+                                Some((var, model.eval(&ast, true)?.as_i64()?))
+                            })
+                            .collect()
+                    });
+                    ValidationResult::Invalid(counterexample)
+                }
+            }
+        })
     }
 }
 
@@ -294,9 +324,23 @@ mod test {
         enumo::{Filter, Metric, Ruleset, Workload},
         logger,
         recipe_utils::{recursive_rules, run_workload, Lang},
-        Limits,
+        Interval, Limits,
     };
 
+    #[test]
+    fn intervals() {
+        let egraph = Workload::new(["(max a 1)", "(select b 2 3)", "(< a b)", "(/ a 0)"])
+            .to_egraph::<Pred>();
+        let interval = |expr: &str| {
+            let id = egraph.lookup_expr(&expr.parse().unwrap()).unwrap();
+            egraph[id].data.interval.clone()
+        };
+        assert_eq!(interval("(max a 1)"), Interval::new(Some(1), None));
+        assert_eq!(interval("(select b 2 3)"), Interval::new(Some(2), Some(3)));
+        assert_eq!(interval("(< a b)"), Interval::new(Some(0), Some(1)));
+        assert_eq!(interval("(/ a 0)"), Interval::default());
+    }
+
     #[test]
     fn run() {
         // Skip this test in github actions
@@ -318,14 +362,24 @@ mod test {
         let oopsla_halide: Ruleset<Pred> = Ruleset::from_file("baseline/oopsla-halide.rules");
         let oopsla_duration = Duration::from_secs_f32(3.354);
 
-        logger::write_baseline(&all_rules, "halide", &baseline, "halide", duration);
+        logger::write_baseline(
+            &logger::Logger::nightly(),
+            &all_rules,
+            "halide",
+            &baseline,
+            "halide",
+            duration,
+        )
+        .unwrap();
 
         logger::write_baseline(
+            &logger::Logger::nightly(),
             &oopsla_halide,
             "oopsla halide (1 iter)",
             &baseline,
             "halide",
             oopsla_duration,
-        );
+        )
+        .unwrap();
     }
 }
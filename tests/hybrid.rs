@@ -0,0 +1,84 @@
+use ruler::*;
+
+// Integer arithmetic where squaring is treated as an op we cannot
+// evaluate: it is only related to the rest of the domain by lifting rules.
+define_synth_language! {
+    pub enum Arith {
+        "+" = Add([Id; 2]) {
+            eval: |x, y| Some(x + y),
+        },
+        "*" = Mul([Id; 2]) {
+            eval: |x, y| Some(x * y),
+        },
+        "/" = Div([Id; 2]) {
+            eval: |x, y| if *y == 0 { None } else { Some(x / y) },
+        },
+        "sq" = Sq(Id) {
+            eval: |_x| None,
+        },
+        #[lit]
+        Lit(i64),
+        #[var]
+        Var(egg::Symbol),
+    }
+
+    impl {
+        fn sort_values(_sort: &Sort) -> Vec<i64> {
+            vec![-2, -1, 0, 1, 2]
+        }
+
+        fn is_rule_lifting() -> bool {
+            true
+        }
+
+        fn get_lifting_rules() -> enumo::Ruleset<Self> {
+            enumo::Ruleset::new(&["(sq ?a) ==> (* ?a ?a)", "(* ?a ?a) ==> (sq ?a)"])
+        }
+
+        fn validate(_lhs: &Pattern<Self>, _rhs: &Pattern<Self>) -> ValidationResult<Self> {
+            ValidationResult::Valid
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use ruler::enumo::{Ruleset, Workload};
+
+    fn has(rules: &Ruleset<Arith>, lhs: &str, rhs: &str) -> bool {
+        [(lhs, rhs), (rhs, lhs)]
+            .iter()
+            .any(|(l, r)| rules.0.contains_key(format!("{} ==> {}", l, r).as_str()))
+    }
+
+    #[test]
+    fn hybrid_merges_lifted_and_matched_candidates() {
+        let workload = Workload::new([
+            "(sq x)",
+            "(* x x)",
+            "(+ x x)",
+            "(* x 2)",
+            "(/ (* x 2) x)",
+            "2",
+        ]);
+
+        let lifted: Ruleset<Arith> = Ruleset::allow_forbid_actual(
+            workload.to_egraph(),
+            Ruleset::default(),
+            Limits::synthesis(),
+        );
+        assert!(has(&lifted, "(sq ?a)", "(* ?a ?a)"));
+        assert!(!has(&lifted, "(+ ?a ?a)", "(* ?a 2)"));
+
+        let hybrid: Ruleset<Arith> = Ruleset::hybrid_match(
+            workload.to_egraph(),
+            Ruleset::default(),
+            Limits::synthesis(),
+        );
+        assert!(has(&hybrid, "(sq ?a)", "(* ?a ?a)"));
+        assert!(has(&hybrid, "(+ ?a ?a)", "(* ?a 2)"));
+        // Undefined at x = 0, but agrees everywhere else
+        assert!(has(&hybrid, "(/ (* ?a 2) ?a)", "2"));
+    }
+}
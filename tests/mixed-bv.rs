@@ -0,0 +1,146 @@
+/*!
+Bitvectors of several widths, with the width-changing operators
+(extensions, extracts and concatenation) instruction selection needs.
+!*/
+
+use ruler::{
+    enumo::{Rule, Ruleset, Scheduler, Workload},
+    *,
+};
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn workload() -> Workload {
+        let vars = Workload::new(["x:bv4", "y:bv4"]);
+        let wide = Workload::new([
+            "(zero_extend.4 V)",
+            "(sign_extend.4 V)",
+            "(concat V V)",
+            "(concat #x0 V)",
+        ])
+        .plug("V", &vars);
+        let narrow = Workload::new(["(extract.3.0 W)", "(extract.7.4 W)"]).plug("W", &wide);
+        vars.append(wide)
+            .append(narrow)
+            .append(Workload::new(["#x0", "#x00"]))
+    }
+
+    #[test]
+    fn widths_are_sorts() {
+        let egraph = workload().to_egraph::<MixedBv>();
+        let sort_of = |s: &str| {
+            let id = egraph.lookup_expr(&s.parse().unwrap()).unwrap();
+            egraph[id].data.sort
+        };
+        assert_eq!(sort_of("x:bv4"), MixedBv::sort_of_width(4));
+        assert_eq!(sort_of("(concat x:bv4 y:bv4)"), MixedBv::sort_of_width(8));
+        assert_eq!(
+            sort_of("(extract.7.4 (sign_extend.4 x:bv4))"),
+            MixedBv::sort_of_width(4)
+        );
+        // #x0 and #x00 have the same value but not the same width
+        assert_ne!(
+            egraph.lookup_expr(&"#x0".parse().unwrap()),
+            egraph.lookup_expr(&"#x00".parse().unwrap())
+        );
+    }
+
+    #[test]
+    fn width_changing_rules() {
+        let egraph = workload().to_egraph::<MixedBv>();
+        let mut candidates: Ruleset<MixedBv> = Ruleset::cvec_match(&egraph);
+        let (rules, _) = candidates.minimize(
+            Ruleset::default(),
+            Scheduler::Compress(Limits::minimize()),
+            &DomainValidator,
+        );
+        assert!(!rules.is_empty());
+
+        for rule in rules.iter() {
+            let l = MixedBv::width_of_expr(&MixedBv::instantiate(&rule.lhs));
+            let r = MixedBv::width_of_expr(&MixedBv::instantiate(&rule.rhs));
+            assert!(l.is_some() && l == r, "{}", rule.name);
+        }
+
+        for expected in [
+            "(extract.3.0 (zero_extend.4 ?a:bv4)) ==> ?a:bv4",
+            "(extract.3.0 (sign_extend.4 ?a:bv4)) ==> ?a:bv4",
+            "(extract.7.4 (zero_extend.4 ?a:bv4)) ==> #x0",
+            "(extract.7.4 (concat ?a:bv4 ?b:bv4)) ==> ?a:bv4",
+            "(extract.3.0 (concat ?a:bv4 ?b:bv4)) ==> ?b:bv4",
+            "(concat #x0 ?a:bv4) ==> (zero_extend.4 ?a:bv4)",
+        ] {
+            let rule = Rule::from_string(expected).unwrap().0;
+            assert!(
+                rules.can_derive(DeriveType::Lhs, &rule, Limits::deriving()),
+                "{}",
+                expected
+            );
+        }
+    }
+
+    #[test]
+    fn z3_agrees_with_enumeration() {
+        let rules = [
+            ("(extract.3.0 (sign_extend.4 ?a:bv4))", "?a:bv4"),
+            ("(extract.7.4 (sign_extend.4 ?a:bv4))", "(ashr ?a:bv4 #x3)"),
+            ("(sign_extend.4 ?a:bv4)", "(zero_extend.4 ?a:bv4)"),
+            ("(concat ?a:bv4 ?b:bv4)", "(concat ?b:bv4 ?a:bv4)"),
+            (
+                "(+ (zero_extend.4 ?a:bv4) (zero_extend.4 ?b:bv4))",
+                "(zero_extend.4 (+ ?a:bv4 ?b:bv4))",
+            ),
+            (
+                "(extract.3.0 (* (sign_extend.4 ?a:bv4) (zero_extend.4 ?b:bv4)))",
+                "(* ?a:bv4 ?b:bv4)",
+            ),
+        ];
+        for (l, r) in rules {
+            let (l, r): (Pattern<MixedBv>, Pattern<MixedBv>) =
+                (l.parse().unwrap(), r.parse().unwrap());
+            let exhaustive = Exhaustive::default().validate(&l, &r);
+            let smt = MixedBv::validate_smt(&l, &r, SMT_TIMEOUT);
+            assert!(!matches!(exhaustive, ValidationResult::Unknown(_)));
+            assert_eq!(
+                matches!(exhaustive, ValidationResult::Valid),
+                matches!(smt, ValidationResult::Valid),
+                "{} ==> {}",
+                l,
+                r
+            );
+        }
+    }
+
+    #[test]
+    fn wide_validation() {
+        let check = |l: &str, r: &str| MixedBv::validate(&l.parse().unwrap(), &r.parse().unwrap());
+        assert!(matches!(
+            check("(extract.63.0 (concat ?a:bv64 ?b:bv64))", "?b:bv64"),
+            ValidationResult::Valid
+        ));
+        assert!(matches!(
+            check(
+                "(concat #xffffffffffffffff ?a:bv64)",
+                "(| (zero_extend.64 ?a:bv64) #xffffffffffffffff0000000000000000)"
+            ),
+            ValidationResult::Valid
+        ));
+        match check("(sign_extend.64 ?a:bv64)", "(zero_extend.64 ?a:bv64)") {
+            ValidationResult::Invalid(Some(cex)) => assert!(cex[0].1.is_negative()),
+            result => panic!("{}", result),
+        }
+
+        // Sides of different widths can't be equal, and ill-typed sides
+        // can't be checked at all
+        assert!(matches!(
+            check("(zero_extend.4 ?a:bv4)", "?a:bv4"),
+            ValidationResult::Invalid(None)
+        ));
+        assert!(matches!(
+            check("(+ ?a:bv4 ?b:bv8)", "(+ ?b:bv8 ?a:bv4)"),
+            ValidationResult::Unknown(_)
+        ));
+    }
+}
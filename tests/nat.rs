@@ -3,6 +3,7 @@ use num_bigint::ToBigInt;
 use rand::{Rng, SeedableRng};
 use rand_pcg::Pcg64;
 use ruler::*;
+use std::time::Duration;
 use z3::ast::Ast;
 
 egg::define_language! {
@@ -133,19 +134,25 @@ impl SynthLanguage for Nat {
         }
     }
 
-    fn validate(lhs: &Pattern<Self>, rhs: &Pattern<Self>) -> ValidationResult {
-        let mut cfg = z3::Config::new();
-        cfg.set_timeout_msec(1000);
-        let ctx = z3::Context::new(&cfg);
-        let solver = z3::Solver::new(&ctx);
-        let lexpr = egg_to_z3(&ctx, Self::instantiate(lhs).as_ref());
-        let rexpr = egg_to_z3(&ctx, Self::instantiate(rhs).as_ref());
-        solver.assert(&lexpr._eq(&rexpr).not());
-        match solver.check() {
-            z3::SatResult::Unsat => ValidationResult::Valid,
-            z3::SatResult::Unknown => ValidationResult::Unknown,
-            z3::SatResult::Sat => ValidationResult::Invalid,
-        }
+    fn validate(lhs: &Pattern<Self>, rhs: &Pattern<Self>) -> ValidationResult<Self> {
+        Self::validate_smt(lhs, rhs, SMT_TIMEOUT)
+    }
+
+    fn validate_smt(
+        lhs: &Pattern<Self>,
+        rhs: &Pattern<Self>,
+        timeout: Duration,
+    ) -> ValidationResult<Self> {
+        with_z3_solver(timeout, |ctx, solver| {
+            let lexpr = egg_to_z3(ctx, Self::instantiate(lhs).as_ref());
+            let rexpr = egg_to_z3(ctx, Self::instantiate(rhs).as_ref());
+            solver.assert(&lexpr._eq(&rexpr).not());
+            match solver.check() {
+                z3::SatResult::Unsat => ValidationResult::Valid,
+                z3::SatResult::Unknown => ValidationResult::Unknown(UnknownReason::from_z3(solver)),
+                z3::SatResult::Sat => ValidationResult::Invalid(None),
+            }
+        })
     }
 }
 
@@ -201,19 +208,40 @@ mod test {
         let atoms3 = iter_nat(3);
         assert_eq!(atoms3.force().len(), 39);
 
-        let rules3 = run_workload(atoms3, all_rules.clone(), limits, limits, false);
+        let rules3 = run_workload(
+            atoms3,
+            all_rules.clone(),
+            limits,
+            limits,
+            false,
+            &DomainValidator,
+        );
         all_rules.extend(rules3);
 
         let atoms4 = iter_nat(4);
         assert_eq!(atoms4.force().len(), 132);
 
-        let rules4 = run_workload(atoms4, all_rules.clone(), limits, limits, false);
+        let rules4 = run_workload(
+            atoms4,
+            all_rules.clone(),
+            limits,
+            limits,
+            false,
+            &DomainValidator,
+        );
         all_rules.extend(rules4);
 
         let atoms5 = iter_nat(5);
         assert_eq!(atoms5.force().len(), 819);
 
-        let rules5 = run_workload(atoms5, all_rules.clone(), limits, limits, false);
+        let rules5 = run_workload(
+            atoms5,
+            all_rules.clone(),
+            limits,
+            limits,
+            false,
+            &DomainValidator,
+        );
         all_rules.extend(rules5);
 
         let expected: Ruleset<Nat> = Ruleset::new(&[
@@ -104,7 +104,7 @@ impl SynthLanguage for Pos {
         }
     }
 
-    fn validate(_lhs: &Pattern<Self>, _rhs: &Pattern<Self>) -> ValidationResult {
+    fn validate(_lhs: &Pattern<Self>, _rhs: &Pattern<Self>) -> ValidationResult<Self> {
         ValidationResult::Valid
     }
 }
@@ -205,19 +205,19 @@ mod tests {
         let atoms3 = iter_pos(3);
         assert_eq!(atoms3.force().len(), 51);
 
-        let rules3 = run_rule_lifting(atoms3, all_rules.clone(), limits, limits);
+        let rules3 = run_rule_lifting(atoms3, all_rules.clone(), limits, limits, &DomainValidator);
         all_rules.extend(rules3);
 
         let atoms4 = iter_pos(4);
         assert_eq!(atoms4.force().len(), 255);
 
-        let rules4 = run_rule_lifting(atoms4, all_rules.clone(), limits, limits);
+        let rules4 = run_rule_lifting(atoms4, all_rules.clone(), limits, limits, &DomainValidator);
         all_rules.extend(rules4);
 
         let atoms5 = iter_pos(5);
         assert_eq!(atoms5.force().len(), 1527);
 
-        let rules4 = run_rule_lifting(atoms5, all_rules.clone(), limits, limits);
+        let rules4 = run_rule_lifting(atoms5, all_rules.clone(), limits, limits, &DomainValidator);
         all_rules.extend(rules4);
 
         let expected: Ruleset<Pos> = Ruleset::new(&[
@@ -6,7 +6,10 @@ use ruler::{
     enumo::{Rule, Ruleset, Scheduler, Workload},
     *,
 };
-use std::{ops::*, time::Instant};
+use std::{
+    ops::*,
+    time::{Duration, Instant},
+};
 use symbolic_expressions::parser::parse_str;
 use symbolic_expressions::Sexp;
 use z3::ast::Ast;
@@ -81,68 +84,33 @@ impl SynthLanguage for Math {
     where
         F: FnMut(&'a Id) -> &'a Interval<Self::Constant>,
     {
-        let mut get_const = |x: &'a Id| {
-            let ival = get_interval(x);
-            if ival.low == ival.high {
-                ival.low.clone()
-            } else {
-                None
-            }
-        };
         match self {
-            Math::Lit(n) => Some(n.clone()),
-            Math::Var(_) => None,
-            Math::Neg(x) => get_const(x).map(|c| -c),
-            Math::Abs(a) => get_const(a).map(|c| c.abs()),
-            Math::Add([x, y]) => match (get_const(x), get_const(y)) {
-                (Some(x), Some(y)) => x.checked_add(&y),
-                _ => None,
-            },
-            Math::Sub([x, y]) => match (get_const(x), get_const(y)) {
-                (Some(x), Some(y)) => x.checked_sub(&y),
-                _ => None,
-            },
-            Math::Mul([x, y]) => match (get_const(x), get_const(y)) {
-                (Some(x), Some(y)) => x.checked_mul(&y),
-                _ => None,
-            },
-            Math::Div([x, y]) => match (get_const(x), get_const(y)) {
-                (Some(x), Some(y)) => x.checked_div(&y),
-                _ => None,
-            },
+            Math::Lit(n) => Interval::constant(n.clone()),
+            Math::Var(_) => Interval::default(),
+            Math::Neg(x) => get_interval(x).neg(),
+            Math::Abs(a) => get_interval(a).abs(),
+            Math::Add([x, y]) => get_interval(x).add(get_interval(y)),
+            Math::Sub([x, y]) => get_interval(x).sub(get_interval(y)),
+            Math::Mul([x, y]) => get_interval(x).mul(get_interval(y)),
+            Math::Div([x, y]) => get_interval(x).div(get_interval(y)),
             Math::If([x, y, z]) => {
-                if let Some(x) = get_const(x) {
-                    if !x.is_zero() {
-                        get_const(y)
-                    } else {
-                        get_const(z)
-                    }
-                } else {
-                    None
-                }
+                Interval::select(get_interval(x), get_interval(y), get_interval(z))
             }
         }
-        .map(|c| Interval::new(Some(c.clone()), Some(c)))
-        .unwrap_or_default()
-    }
-
-    fn initialize_vars(egraph: &mut EGraph<Self, SynthAnalysis>, vars: &[String]) {
-        let consts = vec![
-            Some(mk_rat(-1, 1)),
-            Some(mk_rat(0, 1)),
-            Some(mk_rat(1, 1)),
-            Some(mk_rat(2, 1)),
-            Some(mk_rat(-3, 1)),
-        ];
-        let cvecs = self_product(&consts, vars.len());
-
-        egraph.analysis.cvec_len = cvecs[0].len();
-
-        for (i, v) in vars.iter().enumerate() {
-            let id = egraph.add(Math::Var(Symbol::from(v.clone())));
-            let cvec = cvecs[i].clone();
-            egraph[id].data.cvec = cvec;
-        }
+    }
+
+    fn sort_values(_sort: &Sort) -> Vec<Self::Constant> {
+        vec![
+            mk_rat(-1, 1),
+            mk_rat(0, 1),
+            mk_rat(1, 1),
+            mk_rat(2, 1),
+            mk_rat(-3, 1),
+        ]
+    }
+
+    fn sort_edge_cases(_sort: &Sort) -> Vec<Self::Constant> {
+        vec![mk_rat(-1, 1), mk_rat(0, 1), mk_rat(1, 1)]
     }
 
     fn mk_var(sym: egg::Symbol) -> Self {
@@ -156,52 +124,74 @@ impl SynthLanguage for Math {
         }
     }
 
-    fn validate(lhs: &Pattern<Self>, rhs: &Pattern<Self>) -> ValidationResult {
+    fn validate(lhs: &Pattern<Self>, rhs: &Pattern<Self>) -> ValidationResult<Self> {
+        Self::validate_smt(lhs, rhs, SMT_TIMEOUT)
+    }
+
+    fn validate_smt(
+        lhs: &Pattern<Self>,
+        rhs: &Pattern<Self>,
+        timeout: Duration,
+    ) -> ValidationResult<Self> {
         // TODO if you drop variables, it's unsound because
         // we may have lost an error
         /*if lhs.vars().into_iter().collect::<HashSet<Var>>()
             != rhs.vars().into_iter().collect::<HashSet<Var>>()
         {
-            return ValidationResult::Invalid;
+            return ValidationResult::Invalid(None);
         }*/
 
-        let mut cfg = z3::Config::new();
-        cfg.set_timeout_msec(1000);
-        let ctx = z3::Context::new(&cfg);
-        let solver = z3::Solver::new(&ctx);
-        let lexpr = egg_to_z3(&ctx, Self::instantiate(lhs).as_ref());
-        let rexpr = egg_to_z3(&ctx, Self::instantiate(rhs).as_ref());
-        let lhs_denom = Self::error_conditions(
-            &ctx,
-            Self::pat_to_sexp(lhs),
-            z3::ast::Bool::from_bool(&ctx, true),
-        );
-        let rhs_denom = Self::error_conditions(
-            &ctx,
-            Self::pat_to_sexp(rhs),
-            z3::ast::Bool::from_bool(&ctx, true),
-        );
+        with_z3_solver(timeout, |ctx, solver| {
+            let lexpr = egg_to_z3(ctx, Self::instantiate(lhs).as_ref());
+            let rexpr = egg_to_z3(ctx, Self::instantiate(rhs).as_ref());
+            let lhs_denom = Self::error_conditions(
+                ctx,
+                Self::pat_to_sexp(lhs),
+                z3::ast::Bool::from_bool(ctx, true),
+            );
+            let rhs_denom = Self::error_conditions(
+                ctx,
+                Self::pat_to_sexp(rhs),
+                z3::ast::Bool::from_bool(ctx, true),
+            );
 
-        let mut assert_equal = lexpr._eq(&rexpr);
+            let mut assert_equal = lexpr._eq(&rexpr);
 
-        for condition in lhs_denom.iter().chain(rhs_denom.iter()) {
-            assert_equal = condition.not().implies(&assert_equal);
-        }
+            for condition in lhs_denom.iter().chain(rhs_denom.iter()) {
+                assert_equal = condition.not().implies(&assert_equal);
+            }
 
-        let rhs_errors =
-            z3::ast::Bool::or(&ctx, &rhs_denom.iter().collect::<Vec<&z3::ast::Bool>>());
-        let lhs_errors =
-            z3::ast::Bool::or(&ctx, &lhs_denom.iter().collect::<Vec<&z3::ast::Bool>>());
-        let error_preserved = rhs_errors.iff(&lhs_errors);
-        let assertion = z3::ast::Bool::and(&ctx, &[&assert_equal, &error_preserved]);
-
-        solver.assert(&assertion.clone().not());
-        let res = Self::z3_res_to_validationresult(solver.check());
-        /*if let ValidationResult::Valid = res {
-            eprintln!("verifying {} => {}", lhs, rhs);
-        eprintln!("assertion: {}", assertion);
-        }*/
-        res
+            let rhs_errors =
+                z3::ast::Bool::or(ctx, &rhs_denom.iter().collect::<Vec<&z3::ast::Bool>>());
+            let lhs_errors =
+                z3::ast::Bool::or(ctx, &lhs_denom.iter().collect::<Vec<&z3::ast::Bool>>());
+            let error_preserved = rhs_errors.iff(&lhs_errors);
+            let assertion = z3::ast::Bool::and(ctx, &[&assert_equal, &error_preserved]);
+
+            solver.assert(&assertion.clone().not());
+            let res = Self::z3_res_to_validationresult(solver, solver.check());
+            /*if let ValidationResult::Valid = res {
+                eprintln!("verifying {} => {}", lhs, rhs);
+            eprintln!("assertion: {}", assertion);
+            }*/
+            match res {
+                ValidationResult::Invalid(_) => {
+                    let counterexample = solver.get_model().and_then(|model| {
+                        lhs.vars()
+                            .into_iter()
+                            .map(|var| {
+                                let name = var.to_string();
+                                let ast = z3::ast::Real::new_const(ctx, &name[1..]);
+                                let (num, denom) = model.eval(&ast, true)?.as_real()?;
+                                Some((var, mk_rat(num, denom)))
+                            })
+                            .collect()
+                    });
+                    ValidationResult::Invalid(counterexample)
+                }
+                res => res,
+            }
+        })
     }
 
     fn is_constant(&self) -> bool {
@@ -228,11 +218,14 @@ impl Math {
         one_of_rhs_errors
     }
 
-    fn z3_res_to_validationresult(res: z3::SatResult) -> ValidationResult {
+    fn z3_res_to_validationresult(
+        solver: &z3::Solver,
+        res: z3::SatResult,
+    ) -> ValidationResult<Self> {
         match res {
             z3::SatResult::Unsat => ValidationResult::Valid,
-            z3::SatResult::Sat => ValidationResult::Invalid,
-            z3::SatResult::Unknown => ValidationResult::Unknown,
+            z3::SatResult::Sat => ValidationResult::Invalid(None),
+            z3::SatResult::Unknown => ValidationResult::Unknown(UnknownReason::from_z3(solver)),
         }
     }
 
@@ -307,6 +300,7 @@ impl Math {
                 rhs,
                 name: name.into(),
                 rewrite,
+                validation: None,
             })
         }
     }
@@ -389,7 +383,8 @@ impl Math {
         };
 
         let num_prior = prior.len();
-        let (chosen, invalid) = candidates.minimize(prior.clone(), Scheduler::Compress(limits));
+        let (chosen, invalid) =
+            candidates.minimize(prior.clone(), Scheduler::Compress(limits), &DomainValidator);
 
         println!(
             "Found {} valid and {} invalid rules",
@@ -417,7 +412,11 @@ impl Math {
         );
 
         let chosen_conditional = with_condition
-            .minimize(prior.union(&chosen), Scheduler::Compress(limits))
+            .minimize(
+                prior.union(&chosen),
+                Scheduler::Compress(limits),
+                &DomainValidator,
+            )
             .0;
 
         let result = chosen.union(&chosen_conditional);
@@ -524,103 +523,6 @@ fn sign(interval: &Interval<Constant>) -> Sign {
     }
 }
 
-fn neg(interval: &Interval<Constant>) -> Interval<Constant> {
-    let low = interval.low.clone();
-    let high = interval.high.clone();
-    Interval::new(high.map(|x| -x), low.map(|x| -x))
-}
-
-fn _abs(interval: &Interval<Constant>) -> Interval<Constant> {
-    let low = interval.low.clone();
-    let high = interval.high.clone();
-
-    match (low, high) {
-        (None, None) => Interval::new(None, None),
-        (Some(x), None) => {
-            if x.is_negative() {
-                Interval::new(Some(-x), None)
-            } else {
-                Interval::new(Some(x), None)
-            }
-        }
-        (None, Some(y)) => {
-            if y.is_negative() {
-                Interval::new(None, Some(-y))
-            } else {
-                Interval::new(None, Some(y))
-            }
-        }
-        (Some(x), Some(y)) => {
-            if x.is_negative() {
-                if y.is_negative() {
-                    Interval::new(Some(-x), Some(-y))
-                } else {
-                    Interval::new(Some(-x), Some(y))
-                }
-            } else {
-                if y.is_negative() {
-                    Interval::new(Some(x), Some(-y))
-                } else {
-                    Interval::new(Some(x), Some(y))
-                }
-            }
-        }
-    }
-}
-
-fn add(a: &Interval<Constant>, b: &Interval<Constant>) -> Interval<Constant> {
-    let add_opts = |x: Option<Constant>, y: Option<Constant>| x.zip(y).map(|(x, y)| x + y);
-    let a = a.clone();
-    let b = b.clone();
-    Interval::new(add_opts(a.low, b.low), add_opts(a.high, b.high))
-}
-
-fn mul(a: &Interval<Constant>, b: &Interval<Constant>) -> Interval<Constant> {
-    let mul_opts = |x: Option<Constant>, y: Option<Constant>| x.zip(y).map(|(x, y)| x * y);
-    let (sign_a, sign_b) = (sign(a), sign(b));
-    let a = a.clone();
-    let b = b.clone();
-    match (sign_a, sign_b) {
-        (Sign::Negative, Sign::Negative) => {
-            Interval::new(mul_opts(a.high, b.high), mul_opts(a.low, b.low))
-        }
-        (Sign::Positive, Sign::Positive) => {
-            Interval::new(mul_opts(a.low, b.low), mul_opts(a.high, b.high))
-        }
-        (Sign::Positive, Sign::Negative) => {
-            Interval::new(mul_opts(a.high, b.low), mul_opts(a.low, b.high))
-        }
-        (Sign::Negative, Sign::Positive) => {
-            Interval::new(mul_opts(a.low, b.high), mul_opts(a.high, b.low))
-        }
-
-        (Sign::Positive, Sign::ContainsZero) => {
-            Interval::new(mul_opts(a.high.clone(), b.low), mul_opts(a.high, b.high))
-        }
-        (Sign::ContainsZero, Sign::Positive) => {
-            Interval::new(mul_opts(a.low, b.high.clone()), mul_opts(a.high, b.high))
-        }
-
-        (Sign::Negative, Sign::ContainsZero) => {
-            Interval::new(mul_opts(a.low.clone(), b.high), mul_opts(a.low, b.low))
-        }
-        (Sign::ContainsZero, Sign::Negative) => {
-            Interval::new(mul_opts(a.high, b.low.clone()), mul_opts(a.low, b.low))
-        }
-
-        (Sign::ContainsZero, Sign::ContainsZero) => {
-            let al_bh = mul_opts(a.low.clone(), b.high.clone());
-            let ah_bl = mul_opts(a.high.clone(), b.low.clone());
-            let min = al_bh.zip(ah_bl).map(|(x, y)| x.min(y));
-
-            let ah_bh = mul_opts(a.high, b.high);
-            let al_bl = mul_opts(a.low, b.low);
-            let max = ah_bh.zip(al_bl).map(|(x, y)| x.max(y));
-            Interval::new(min, max)
-        }
-    }
-}
-
 fn recip(interval: &Interval<Constant>) -> Interval<Constant> {
     let interval = interval.clone();
     let sign = sign(&interval);
@@ -664,13 +566,13 @@ pub mod test {
 
     #[test]
     fn neg_interval_test() {
-        assert_eq!(neg(&interval(None, None)), interval(None, None));
-        assert_eq!(neg(&interval(Some(10), None)), interval(None, Some(-10)));
-        assert_eq!(neg(&interval(Some(-10), None)), interval(None, Some(10)));
-        assert_eq!(neg(&interval(None, Some(10))), interval(Some(-10), None));
-        assert_eq!(neg(&interval(None, Some(-10))), interval(Some(10), None));
+        assert_eq!(interval(None, None).neg(), interval(None, None));
+        assert_eq!(interval(Some(10), None).neg(), interval(None, Some(-10)));
+        assert_eq!(interval(Some(-10), None).neg(), interval(None, Some(10)));
+        assert_eq!(interval(None, Some(10)).neg(), interval(Some(-10), None));
+        assert_eq!(interval(None, Some(-10)).neg(), interval(Some(10), None));
         assert_eq!(
-            neg(&interval(Some(5), Some(10))),
+            interval(Some(5), Some(10)).neg(),
             interval(Some(-10), Some(-5))
         );
     }
@@ -678,22 +580,19 @@ pub mod test {
     #[test]
     fn add_interval_test() {
         assert_eq!(
-            add(&interval(None, None), &interval(None, None)),
+            interval(None, None).add(&interval(None, None)),
             interval(None, None)
         );
         assert_eq!(
-            add(&interval(None, None), &interval(Some(-10), Some(10))),
+            interval(None, None).add(&interval(Some(-10), Some(10))),
             interval(None, None)
         );
         assert_eq!(
-            add(&interval(Some(-10), Some(10)), &interval(None, None)),
+            interval(Some(-10), Some(10)).add(&interval(None, None)),
             interval(None, None)
         );
         assert_eq!(
-            add(
-                &interval(Some(-20), Some(5)),
-                &interval(Some(-10), Some(10))
-            ),
+            interval(Some(-20), Some(5)).add(&interval(Some(-10), Some(10))),
             interval(Some(-30), Some(15))
         );
     }
@@ -701,76 +600,67 @@ pub mod test {
     #[test]
     fn mul_interval_test() {
         assert_eq!(
-            mul(&interval(None, Some(-3)), &interval(None, Some(-4))),
+            interval(None, Some(-3)).mul(&interval(None, Some(-4))),
             interval(Some(12), None)
         );
         assert_eq!(
-            mul(
-                &interval(Some(-100), Some(-2)),
-                &interval(Some(-50), Some(-20))
-            ),
+            interval(Some(-100), Some(-2)).mul(&interval(Some(-50), Some(-20))),
             interval(Some(40), Some(5000))
         );
         assert_eq!(
-            mul(&interval(Some(2), None), &interval(Some(50), None)),
+            interval(Some(2), None).mul(&interval(Some(50), None)),
             interval(Some(100), None)
         );
         assert_eq!(
-            mul(&interval(Some(30), Some(50)), &interval(Some(2), Some(3))),
+            interval(Some(30), Some(50)).mul(&interval(Some(2), Some(3))),
             interval(Some(60), Some(150))
         );
         assert_eq!(
-            mul(
-                &interval(Some(-10), Some(-5)),
-                &interval(Some(6), Some(100))
-            ),
+            interval(Some(-10), Some(-5)).mul(&interval(Some(6), Some(100))),
             interval(Some(-1000), Some(-30))
         );
         assert_eq!(
-            mul(&interval(Some(3), Some(10)), &interval(None, Some(-1))),
+            interval(Some(3), Some(10)).mul(&interval(None, Some(-1))),
             interval(None, Some(-3))
         );
         assert_eq!(
-            mul(&interval(Some(2), Some(5)), &interval(Some(-3), Some(4))),
+            interval(Some(2), Some(5)).mul(&interval(Some(-3), Some(4))),
             interval(Some(-15), Some(20))
         );
         assert_eq!(
-            mul(&interval(Some(-2), None), &interval(Some(3), Some(4))),
+            interval(Some(-2), None).mul(&interval(Some(3), Some(4))),
             interval(Some(-8), None)
         );
         assert_eq!(
-            mul(&interval(None, None), &interval(Some(-10), Some(-4))),
+            interval(None, None).mul(&interval(Some(-10), Some(-4))),
             interval(None, None)
         );
         assert_eq!(
-            mul(&interval(Some(-8), Some(6)), &interval(Some(-3), Some(-2))),
+            interval(Some(-8), Some(6)).mul(&interval(Some(-3), Some(-2))),
             interval(Some(-18), Some(24))
         );
         assert_eq!(
-            mul(&interval(Some(-4), Some(6)), &interval(Some(-8), Some(10))),
+            interval(Some(-4), Some(6)).mul(&interval(Some(-8), Some(10))),
             interval(Some(-48), Some(60))
         );
         assert_eq!(
-            mul(
-                &interval(Some(-100), Some(50)),
-                &interval(Some(-5), Some(7))
-            ),
+            interval(Some(-100), Some(50)).mul(&interval(Some(-5), Some(7))),
             interval(Some(-700), Some(500))
         );
         assert_eq!(
-            mul(&interval(Some(-5), Some(6)), &interval(Some(-4), Some(8))),
+            interval(Some(-5), Some(6)).mul(&interval(Some(-4), Some(8))),
             interval(Some(-40), Some(48))
         );
         assert_eq!(
-            mul(&interval(Some(-4), Some(10)), &interval(Some(-8), Some(6))),
+            interval(Some(-4), Some(10)).mul(&interval(Some(-8), Some(6))),
             interval(Some(-80), Some(60))
         );
         assert_eq!(
-            mul(&interval(None, Some(10)), &interval(Some(-5), Some(15))),
+            interval(None, Some(10)).mul(&interval(Some(-5), Some(15))),
             interval(None, None)
         );
         assert_eq!(
-            mul(&interval(Some(-4), Some(10)), &interval(Some(-8), None)),
+            interval(Some(-4), Some(10)).mul(&interval(Some(-8), None)),
             interval(None, None)
         );
     }
@@ -808,19 +698,23 @@ pub mod test {
         let duration = start.elapsed();
 
         logger::write_baseline(
+            &logger::Logger::nightly(),
             &replicate_rules,
             "rational_replicate",
             &ruler1,
             "oopsla",
             duration,
-        );
+        )
+        .unwrap();
         logger::write_baseline(
+            &logger::Logger::nightly(),
             &replicate_rules,
             "rational_replicate",
             &herbie,
             "herbie",
             duration,
-        );
+        )
+        .unwrap();
 
         let start = Instant::now();
         let best_rules = best_enumo_recipe();
@@ -841,15 +735,33 @@ pub mod test {
                 .collect::<Vec<_>>(),
         );
 
-        logger::write_baseline(&without_if, "rational_best", &ruler1, "oopsla", duration);
-        logger::write_baseline(&without_if, "rational_best", &herbie, "herbie", duration);
         logger::write_baseline(
+            &logger::Logger::nightly(),
+            &without_if,
+            "rational_best",
+            &ruler1,
+            "oopsla",
+            duration,
+        )
+        .unwrap();
+        logger::write_baseline(
+            &logger::Logger::nightly(),
+            &without_if,
+            "rational_best",
+            &herbie,
+            "herbie",
+            duration,
+        )
+        .unwrap();
+        logger::write_baseline(
+            &logger::Logger::nightly(),
             &best_rules,
             "rational_best",
             &best_rules,
             "rational_best",
             duration,
-        );
+        )
+        .unwrap();
     }
 
     #[test]
@@ -866,6 +778,7 @@ pub mod test {
             Limits::synthesis(),
             Limits::minimize(),
             false,
+            &DomainValidator,
         );
         all_rules.extend(starting_rules);
 
@@ -876,6 +789,7 @@ pub mod test {
             Limits::synthesis(),
             Limits::minimize(),
             false,
+            &DomainValidator,
         );
         all_rules.extend(basic_if_rules);
 
@@ -891,6 +805,7 @@ pub mod test {
             Limits::synthesis(),
             Limits::minimize(),
             false,
+            &DomainValidator,
         );
         guarded_rules.to_file("guard.rules");
         assert!(guarded_rules
@@ -901,6 +816,123 @@ pub mod test {
             .contains_key("(/ 0 ?a) ==> (if ?a 0 (/ ?a ?a))"));
     }
 
+    #[test]
+    fn interval_precondition() {
+        let mut rules: Ruleset<Math> = Ruleset::default();
+        rules.add(Rule {
+            name: "fabs-nonneg".into(),
+            lhs: "(fabs ?a)".parse().unwrap(),
+            rhs: "?a".parse().unwrap(),
+            rewrite: egg::rewrite!("fabs-nonneg"; "(fabs ?a)" => "?a"
+                if interval_cond::<Math, _>("?a", Interval::is_nonnegative)),
+            validation: None,
+        });
+
+        let egraph = Workload::new(["x", "(fabs x)", "(fabs (fabs x))"]).to_egraph::<Math>();
+        let egraph = Scheduler::Simple(Limits::deriving()).run(&egraph, &rules);
+        let id = |s: &str| egraph.lookup_expr(&s.parse().unwrap()).unwrap();
+
+        assert_eq!(id("(fabs (fabs x))"), id("(fabs x)"));
+        assert_ne!(id("(fabs x)"), id("x"));
+    }
+
+    #[test]
+    fn capped_cvecs() {
+        let workload = Workload::new(["(+ a b)", "(* c d)"]);
+        assert_eq!(workload.to_egraph::<Math>().analysis.cvec_len, 625);
+
+        let egraph = workload.to_egraph_with::<Math>(CvecSampler::edge_cases(64));
+        assert_eq!(egraph.analysis.cvec_len, 64);
+        let a = egraph.lookup_expr(&"a".parse().unwrap()).unwrap();
+        assert_eq!(egraph[a].data.cvec.len(), 64);
+        assert_eq!(egraph[a].data.cvec[0], Some(mk_rat(-1, 1)));
+    }
+
+    #[test]
+    fn counterexamples_refine_cvecs() {
+        // Only a = -1 and a = 1 are sampled, where (* a a) and (fabs a) agree
+        let sampler = CvecSampler::new(Sampling::Exhaustive, 2);
+        let mut egraph = Workload::new(["(* a a)", "(fabs a)"]).to_egraph_with::<Math>(sampler);
+        let candidates = Ruleset::cvec_match(&egraph);
+        assert!(candidates.0.contains_key("(* ?a ?a) ==> (fabs ?a)"));
+
+        let refined = refine_candidates(
+            &mut egraph,
+            candidates,
+            3,
+            &DomainValidator,
+            Ruleset::cvec_match,
+        );
+        assert!(refined.is_empty());
+        assert!(egraph.analysis.cvec_len > 2);
+        for class in egraph.classes() {
+            assert_eq!(class.data.cvec.len(), egraph.analysis.cvec_len);
+        }
+    }
+
+    #[test]
+    fn refined_candidates_keep_validation() {
+        let sampler = CvecSampler::new(Sampling::Exhaustive, 2);
+        let mut egraph = Workload::new(["(+ a b)", "(+ b a)"]).to_egraph_with::<Math>(sampler);
+        let candidates = Ruleset::cvec_match(&egraph);
+        let refined = refine_candidates(
+            &mut egraph,
+            candidates,
+            3,
+            &DomainValidator,
+            Ruleset::cvec_match,
+        );
+        assert!(!refined.is_empty());
+        for rule in refined.iter() {
+            assert!(rule.validation.as_ref().unwrap().is_valid());
+        }
+    }
+
+    #[test]
+    fn invalid_rules_keep_validation() {
+        let mut candidates: Ruleset<Math> = Ruleset::new(["(* ?a ?a) ==> (fabs ?a)"]);
+        let (chosen, invalid) = candidates.minimize(
+            Ruleset::default(),
+            Scheduler::Compress(Limits::minimize()),
+            &DomainValidator,
+        );
+        assert!(chosen.is_empty());
+
+        let validation = invalid.0["(* ?a ?a) ==> (fabs ?a)"]
+            .validation
+            .as_ref()
+            .unwrap();
+        assert!(matches!(
+            validation.result,
+            ValidationResult::Invalid(Some(_))
+        ));
+        assert!(validation.validator.ends_with("Math"));
+    }
+
+    #[test]
+    fn nonlinear_validation() {
+        let check = |l: &str, r: &str| Math::validate(&l.parse().unwrap(), &r.parse().unwrap());
+        // Several queries on the same thread, so each one sees the solver
+        // the previous one left behind
+        for _ in 0..3 {
+            assert!(matches!(
+                check("(* ?a ?a)", "(fabs ?a)"),
+                ValidationResult::Invalid(_)
+            ));
+            assert!(matches!(
+                check("(* (/ ?a ?b) ?b)", "(if ?b ?a (/ ?a ?b))"),
+                ValidationResult::Valid
+            ));
+            assert!(matches!(
+                check(
+                    "(/ (* ?a ?b) (* ?b ?c))",
+                    "(if ?b (/ ?a ?c) (/ ?a (* ?b ?c)))"
+                ),
+                ValidationResult::Valid
+            ));
+        }
+    }
+
     // TODO write test that catches if cvecs are not initialized
 
     #[test]
@@ -914,8 +946,14 @@ pub mod test {
             match_: 200_000,
         };
         let test = Workload::new(&["(if a b b)", "b"]);
-        let test_rules: Ruleset<Math> =
-            run_workload(test, Ruleset::default(), limits, limits, false);
+        let test_rules: Ruleset<Math> = run_workload(
+            test,
+            Ruleset::default(),
+            limits,
+            limits,
+            false,
+            &DomainValidator,
+        );
         assert_eq!(test_rules.len(), 1);
     }
 }
@@ -15,6 +15,7 @@ pub fn bool_rules() -> Ruleset<Bool> {
             &[&["~"], &["&", "|", "^"]],
         ),
         Ruleset::default(),
+        &DomainValidator,
     );
     all.extend(r5);
 
@@ -34,6 +35,7 @@ pub fn bool_rules() -> Ruleset<Bool> {
         Limits::synthesis(),
         Limits::minimize(),
         true,
+        &DomainValidator,
     );
     all.extend(r7);
 
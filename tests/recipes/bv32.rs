@@ -14,5 +14,6 @@ pub fn bv32_rules() -> Ruleset<Bv> {
             &[&["~", "-"], &["&", "|", "*", "--", "+", "<<", ">>"]],
         ),
         Ruleset::default(),
+        &DomainValidator,
     )
 }
@@ -15,5 +15,6 @@ pub fn bv4_rules() -> Ruleset<Bv> {
             &[&["~", "-"], &["&", "|", "*", "--", "+", "<<", ">>"]],
         ),
         Ruleset::default(),
+        &DomainValidator,
     )
 }
@@ -17,6 +17,7 @@ pub fn bv4_fancy_rules() -> Ruleset<Bv> {
         5,
         lang.clone(),
         Ruleset::default(),
+        &DomainValidator,
     ));
 
     let a6_canon = iter_metric(base_lang(2), "EXPR", enumo::Metric::Atoms, 6)
@@ -37,6 +38,7 @@ pub fn bv4_fancy_rules() -> Ruleset<Bv> {
         Limits::synthesis(),
         Limits::minimize(),
         true,
+        &DomainValidator,
     ));
 
     rules
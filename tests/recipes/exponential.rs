@@ -29,7 +29,13 @@ fn constant_rules(prev_rules: &Ruleset) -> Ruleset {
         "(pow 1 a)",
     ]);
 
-    run_rule_lifting(terms, prev_rules.clone(), limits(), limits())
+    run_rule_lifting(
+        terms,
+        prev_rules.clone(),
+        limits(),
+        limits(),
+        &DomainValidator,
+    )
 }
 
 fn exp_rules(prev_rules: &Ruleset) -> Ruleset {
@@ -49,7 +55,13 @@ fn exp_rules(prev_rules: &Ruleset) -> Ruleset {
             "(log (log ?a))".parse().unwrap(),
         ))));
 
-    run_rule_lifting(upper_layer, prev_rules.clone(), limits(), limits())
+    run_rule_lifting(
+        upper_layer,
+        prev_rules.clone(),
+        limits(),
+        limits(),
+        &DomainValidator,
+    )
 }
 
 fn log_rules(prev_rules: &Ruleset) -> Ruleset {
@@ -69,7 +81,13 @@ fn log_rules(prev_rules: &Ruleset) -> Ruleset {
             "(log (log ?a))".parse().unwrap(),
         ))));
 
-    run_rule_lifting(upper_layer, prev_rules.clone(), limits(), limits())
+    run_rule_lifting(
+        upper_layer,
+        prev_rules.clone(),
+        limits(),
+        limits(),
+        &DomainValidator,
+    )
 }
 
 fn no_pow_rules(prev_rules: &Ruleset) -> Ruleset {
@@ -95,7 +113,13 @@ fn no_pow_rules(prev_rules: &Ruleset) -> Ruleset {
             "(log (log ?a))".parse().unwrap(),
         ))));
 
-    run_rule_lifting(upper_layer, prev_rules.clone(), limits(), limits())
+    run_rule_lifting(
+        upper_layer,
+        prev_rules.clone(),
+        limits(),
+        limits(),
+        &DomainValidator,
+    )
 }
 
 fn simple_rules(prev_rules: &Ruleset) -> Ruleset {
@@ -121,7 +145,13 @@ fn simple_rules(prev_rules: &Ruleset) -> Ruleset {
             "(log (log ?a))".parse().unwrap(),
         ))));
 
-    run_rule_lifting(upper_layer, prev_rules.clone(), limits(), limits())
+    run_rule_lifting(
+        upper_layer,
+        prev_rules.clone(),
+        limits(),
+        limits(),
+        &DomainValidator,
+    )
 }
 
 fn div_rules(prev_rules: &Ruleset) -> Ruleset {
@@ -142,7 +172,13 @@ fn div_rules(prev_rules: &Ruleset) -> Ruleset {
         .plug("bop", &bops)
         .filter(Filter::Canon(str_vec!["a", "b"]));
 
-    run_rule_lifting(upper_layer, prev_rules.clone(), limits(), limits())
+    run_rule_lifting(
+        upper_layer,
+        prev_rules.clone(),
+        limits(),
+        limits(),
+        &DomainValidator,
+    )
 }
 
 pub fn make_rules() -> Ruleset {
@@ -1,7 +1,7 @@
 use ruler::{
     enumo::{Filter, Metric, Ruleset, Workload},
     recipe_utils::{recursive_rules, run_workload, Lang},
-    Limits,
+    DomainValidator, Limits,
 };
 
 use crate::Pred;
@@ -13,6 +13,7 @@ pub fn halide_rules() -> Ruleset<Pred> {
         5,
         Lang::new(&["0", "1"], &["a", "b", "c"], &[&["!"], &["&&", "||", "^"]]),
         all_rules.clone(),
+        &DomainValidator,
     );
     all_rules.extend(bool_only);
     let rat_only = recursive_rules(
@@ -24,6 +25,7 @@ pub fn halide_rules() -> Ruleset<Pred> {
             &[&["-"], &["+", "-", "*", "min", "max"]],
         ),
         all_rules.clone(),
+        &DomainValidator,
     );
     all_rules.extend(rat_only.clone());
     let pred_only = recursive_rules(
@@ -35,6 +37,7 @@ pub fn halide_rules() -> Ruleset<Pred> {
             &[&["-"], &["<", "<=", "==", "!="], &["select"]],
         ),
         all_rules.clone(),
+        &DomainValidator,
     );
     all_rules.extend(pred_only);
 
@@ -53,6 +56,7 @@ pub fn halide_rules() -> Ruleset<Pred> {
             ],
         ),
         all_rules.clone(),
+        &DomainValidator,
     );
     all_rules.extend(full);
 
@@ -72,6 +76,7 @@ pub fn halide_rules() -> Ruleset<Pred> {
         Limits::synthesis(),
         Limits::minimize(),
         true,
+        &DomainValidator,
     );
     all_rules.extend(new);
     let nested_bops_full = Workload::new(&["(bop e e)", "v"])
@@ -93,6 +98,7 @@ pub fn halide_rules() -> Ruleset<Pred> {
         Limits::synthesis(),
         Limits::minimize(),
         true,
+        &DomainValidator,
     );
     all_rules.extend(new.clone());
 
@@ -120,6 +126,7 @@ pub fn halide_rules() -> Ruleset<Pred> {
             match_: 100_000,
         },
         true,
+        &DomainValidator,
     );
     all_rules.extend(new);
 
@@ -134,6 +141,7 @@ pub fn halide_rules() -> Ruleset<Pred> {
             match_: 100_000,
         },
         true,
+        &DomainValidator,
     );
     all_rules.extend(new);
 
@@ -28,6 +28,7 @@ pub fn replicate_ruler1_recipe() -> Ruleset<Math> {
         Limits::synthesis(),
         Limits::minimize(),
         false,
+        &DomainValidator,
     );
     rules.extend(layer1_rules);
 
@@ -50,6 +51,7 @@ pub fn replicate_ruler1_recipe() -> Ruleset<Math> {
         Limits::synthesis(),
         Limits::minimize(),
         true,
+        &DomainValidator,
     );
     rules.extend(layer2_rules);
 
@@ -0,0 +1,179 @@
+use ruler::*;
+
+// Halide-style predicates: booleans are encoded as 0/1 in the same i64
+// constant type as integers, so only the sorts keep them apart.
+define_synth_language! {
+    pub enum Pred {
+        "+" = Add([Id; 2]) {
+            eval: |x, y| Some(x + y),
+        },
+        "<" = Lt([Id; 2]) {
+            eval: |x, y| Some((x < y) as i64),
+        },
+        "&" = And([Id; 2]) {
+            eval: |x, y| Some(x & y),
+        },
+        "ite" = Ite([Id; 3]) {
+            eval: |c, x, y| Some(if *c != 0 { *x } else { *y }),
+        },
+        #[lit]
+        Lit(i64),
+        #[var]
+        Var(egg::Symbol),
+    }
+
+    impl {
+        fn sort_of<'a, F>(&'a self, mut get_sort: F) -> Sort
+        where
+            F: FnMut(&'a Id) -> &'a Sort,
+        {
+            match self {
+                Pred::Add(_) | Pred::Lit(_) => Sort::new("int"),
+                Pred::Lt(_) | Pred::And(_) => Sort::new("bool"),
+                Pred::Ite([_, x, _]) => *get_sort(x),
+                Pred::Var(v) => Sort::of_var(v.as_str()).expect("unsorted variable"),
+            }
+        }
+
+        fn sort_values(sort: &Sort) -> Vec<i64> {
+            match sort.name() {
+                "bool" => vec![0, 1],
+                _ => vec![-1, 0, 1, 2],
+            }
+        }
+
+        fn all_values(sort: &Sort) -> Option<Vec<i64>> {
+            match sort.name() {
+                "bool" => Some(vec![0, 1]),
+                _ => None,
+            }
+        }
+
+        fn validate(_lhs: &Pattern<Self>, _rhs: &Pattern<Self>) -> ValidationResult<Self> {
+            ValidationResult::Valid
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use ruler::enumo::{Rule, Ruleset, Scheduler, Workload};
+
+    fn workload() -> Workload {
+        Workload::new([
+            "x:int",
+            "y:int",
+            "p:bool",
+            "0",
+            "(+ x:int 0)",
+            "(< x:int x:int)",
+            "(& p:bool p:bool)",
+            "(ite p:bool x:int x:int)",
+            "(ite (< x:int x:int) x:int y:int)",
+        ])
+    }
+
+    #[test]
+    fn variable_sorts() {
+        let egraph = workload().to_egraph::<Pred>();
+        let sort_of = |s: &str| {
+            let id = egraph.lookup_expr(&s.parse().unwrap()).unwrap();
+            egraph[id].data.sort
+        };
+        assert_eq!(sort_of("x:int"), Sort::new("int"));
+        assert_eq!(sort_of("p:bool"), Sort::new("bool"));
+        assert_eq!(sort_of("(ite p:bool x:int x:int)"), Sort::new("int"));
+        // 4 ints for each of x and y, 2 bools for p
+        assert_eq!(egraph.analysis.cvec_len, 32);
+    }
+
+    #[test]
+    fn cvec_match_respects_sorts() {
+        let egraph = workload().to_egraph::<Pred>();
+        let candidates: Ruleset<Pred> = Ruleset::cvec_match(&egraph);
+
+        let names = candidates.to_str_vec();
+        assert!(names.contains(&"(+ ?a:int 0) ==> ?a:int".to_string()));
+        assert!(names.contains(&"(& ?a:bool ?a:bool) ==> ?a:bool".to_string()));
+        assert!(names
+            .iter()
+            .any(|n| n.starts_with("(ite ?") && n.contains(":bool")));
+        // (< x x) and 0 have the same cvec, but one is a bool and the other an int
+        assert!(!names
+            .iter()
+            .any(|n| n.contains("(< ?a:int ?a:int)") && n.contains('0')));
+
+        for rule in candidates.iter() {
+            let mut egraph: EGraph<Pred, SynthAnalysis> = Default::default();
+            let l = egraph.add_expr(&Pred::instantiate(&rule.lhs));
+            let r = egraph.add_expr(&Pred::instantiate(&rule.rhs));
+            assert_eq!(egraph[l].data.sort, egraph[r].data.sort, "{}", rule.name);
+        }
+    }
+
+    #[test]
+    fn sorted_variables_only_bind_their_sort() {
+        let egraph = Workload::new(["x:int", "p:bool"]).to_egraph::<Pred>();
+        let rules: Ruleset<Pred> = Ruleset::new(&["?a:int ==> (+ ?a:int 0)"]);
+        let egraph = Scheduler::Simple(Limits::deriving()).run(&egraph, &rules);
+
+        assert!(egraph
+            .lookup_expr(&"(+ x:int 0)".parse().unwrap())
+            .is_some());
+        assert!(egraph
+            .lookup_expr(&"(+ p:bool 0)".parse().unwrap())
+            .is_none());
+    }
+
+    #[test]
+    fn unsorted_rules_never_merge_sorts() {
+        let egraph = Workload::new(["(< x:int x:int)", "0"]).to_egraph::<Pred>();
+        let rules: Ruleset<Pred> = Ruleset::new(&["(< ?a ?a) ==> 0"]);
+        let egraph = Scheduler::Simple(Limits::deriving()).run(&egraph, &rules);
+
+        let lt = egraph
+            .lookup_expr(&"(< x:int x:int)".parse().unwrap())
+            .unwrap();
+        let zero = egraph.lookup_expr(&"0".parse().unwrap()).unwrap();
+        assert_ne!(egraph.find(lt), egraph.find(zero));
+    }
+
+    #[test]
+    fn evaluating_validators() {
+        let rule = |s: &str| Rule::<Pred>::from_string(s).unwrap().0;
+        let valid = rule("(& ?p:bool ?q:bool) ==> (& ?q:bool ?p:bool)");
+        let invalid = rule("(< ?p:bool ?q:bool) ==> (< ?q:bool ?p:bool)");
+
+        let exhaustive = Exhaustive::default();
+        let validators: [&dyn Validator<Pred>; 2] = [&exhaustive, &RandomTesting::new(8)];
+        for validator in validators {
+            assert!(valid.validate_with(validator).is_valid());
+            match invalid.validate_with(validator).result {
+                ValidationResult::Invalid(Some(cex)) => {
+                    assert_eq!(cex.len(), 2);
+                    assert_ne!(cex[0].1, cex[1].1);
+                }
+                result => panic!("{}: {}", validator.name(), result),
+            }
+        }
+
+        // Pred::validate accepts everything, but the validator decides
+        let mut candidates: Ruleset<Pred> = Ruleset::new(&[invalid.name.to_string()]);
+        let (chosen, rejected) = candidates.minimize(
+            Ruleset::default(),
+            Scheduler::Compress(Limits::minimize()),
+            &exhaustive,
+        );
+        assert!(chosen.is_empty());
+        let validation = rejected.0[&invalid.name].validation.as_ref().unwrap();
+        assert_eq!(validation.validator, "exhaustive");
+
+        // Ints can't be enumerated
+        let int_rule = rule("(+ ?x:int 0) ==> ?x:int");
+        assert!(matches!(
+            int_rule.validate_with(&exhaustive).result,
+            ValidationResult::Unknown(_)
+        ));
+    }
+}
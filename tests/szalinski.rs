@@ -198,7 +198,7 @@ impl SynthLanguage for CF {
         CF::Literal(c)
     }
 
-    fn validate(_lhs: &Pattern<Self>, _rhs: &Pattern<Self>) -> ValidationResult {
+    fn validate(_lhs: &Pattern<Self>, _rhs: &Pattern<Self>) -> ValidationResult<Self> {
         ValidationResult::Valid
     }
 }
@@ -274,8 +274,11 @@ mod tests {
             let atoms = iter_szalinski(i);
             let egraph = atoms.to_egraph::<CF>();
             let mut candidates = Ruleset::allow_forbid_actual(egraph, all_rules.clone(), limits);
-            let (chosen, _) =
-                candidates.minimize(learned_rules.clone(), Scheduler::Compress(limits));
+            let (chosen, _) = candidates.minimize(
+                learned_rules.clone(),
+                Scheduler::Compress(limits),
+                &DomainValidator,
+            );
 
             all_rules.extend(chosen.clone());
             learned_rules.extend(chosen);
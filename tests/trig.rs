@@ -1,6 +1,8 @@
 use num::rational::Ratio;
 use num::BigInt;
-use num::{Signed, Zero};
+use num::{Signed, ToPrimitive, Zero};
+use rand::Rng;
+use rand_pcg::Pcg64;
 use ruler::enumo::Ruleset;
 use ruler::*;
 use std::fmt;
@@ -307,8 +309,45 @@ impl SynthLanguage for Trig {
         }
     }
 
-    fn validate(_lhs: &Pattern<Self>, _rhs: &Pattern<Self>) -> ValidationResult {
-        ValidationResult::Valid
+    fn sort_values(_sort: &Sort) -> Vec<Self::Constant> {
+        ["-2", "-1", "-1/3", "0", "1/4", "1/2", "1", "3/2", "2", "3"]
+            .iter()
+            .map(Real::from)
+            .collect()
+    }
+
+    // Fractions in [-10, 10] with denominators up to 16
+    fn random_value(_sort: &Sort, rng: &mut Pcg64) -> Option<Self::Constant> {
+        let denom: i64 = rng.gen_range(1, 17);
+        let numer: i64 = rng.gen_range(-10 * denom, 10 * denom + 1);
+        Some(Real::from(Ratio::new(numer, denom).to_string()))
+    }
+
+    // Complex values (`I`, `cis`) have no floating-point meaning
+    fn eval_f64(&self, args: &[f64]) -> Option<f64> {
+        Some(match (self, args) {
+            (Trig::Sin(_), [x]) => x.sin(),
+            (Trig::Cos(_), [x]) => x.cos(),
+            (Trig::Tan(_), [x]) => x.tan(),
+            (Trig::Neg(_), [x]) => -x,
+            (Trig::Add(_), [x, y]) => x + y,
+            (Trig::Sub(_), [x, y]) => x - y,
+            (Trig::Mul(_), [x, y]) => x * y,
+            (Trig::Div(_), [x, y]) => x / y,
+            (Trig::Sqr(_), [x]) => x * x,
+            (Trig::Pi, []) => std::f64::consts::PI,
+            (Trig::RealConst(c), []) => Self::constant_to_f64(c)?,
+            _ => return None,
+        })
+    }
+
+    fn constant_to_f64(c: &Self::Constant) -> Option<f64> {
+        c.as_str().parse::<Rational>().ok()?.to_f64()
+    }
+
+    // no exact validation possible, so test on random points
+    fn validate(lhs: &Pattern<Self>, rhs: &Pattern<Self>) -> ValidationResult<Self> {
+        RandomTesting::new(64).validate(lhs, rhs)
     }
 }
 
@@ -362,7 +401,38 @@ mod test {
         let rules = trig_rules();
         let duration = start.elapsed();
 
-        logger::write_baseline(&rules, "trig", &herbie, "herbie", duration);
+        logger::write_baseline(
+            &logger::Logger::nightly(),
+            &rules,
+            "trig",
+            &herbie,
+            "herbie",
+            duration,
+        )
+        .unwrap();
+    }
+
+    #[test]
+    fn random_testing() {
+        let validator = RandomTesting::new(64);
+        let check = |rule: &str| {
+            let rule: enumo::Rule<Trig> = enumo::Rule::from_string(rule).unwrap().0;
+            validator.validate_with_confidence(&rule.lhs, &rule.rhs)
+        };
+
+        let (result, confidence) = check("(tan ?a) ==> (/ (sin ?a) (cos ?a))");
+        assert!(matches!(result, ValidationResult::Valid));
+        assert!(0.0 < confidence && confidence < 1.0);
+
+        let (result, confidence) = check("(sin (/ PI 6)) ==> (/ 1 2)");
+        assert!(matches!(result, ValidationResult::Valid));
+        assert_eq!(confidence, 1.0);
+
+        let (result, _) = check("(sin (+ ?a ?b)) ==> (+ (sin ?a) (sin ?b))");
+        assert!(matches!(result, ValidationResult::Invalid(Some(_))));
+
+        let (result, _) = check("(cis ?a) ==> (cos ?a)");
+        assert!(matches!(result, ValidationResult::Unknown(_)));
     }
 
     #[test]
@@ -390,7 +460,7 @@ mod test {
         let mut all = complex;
         all.extend(prior_rules());
 
-        let rules = run_rule_lifting(terms, all, limits, limits);
+        let rules = run_rule_lifting(terms, all, limits, limits, &DomainValidator);
 
         let expected: Ruleset<Trig> =
             Ruleset::new(&["(sin (* PI 2)) <=> 0", "0 <=> (sin 0)", "0 <=> (sin PI)"]);
@@ -432,9 +502,17 @@ mod test {
         candidates.extend(Ruleset::extract_candidates(&g2, &g3));
 
         // let (sound, _) = candidates.partition(|r| r.is_valid());
-        let (sound, _) = candidates.minimize(minimize.rules, minimize.scheduler);
-
-        logger::write_lifting_phase(phase1, phase2, phase3, start.elapsed(), &sound);
+        let (sound, _) = candidates.minimize(minimize.rules, minimize.scheduler, &DomainValidator);
+
+        logger::write_lifting_phase(
+            &logger::Logger::nightly(),
+            phase1,
+            phase2,
+            phase3,
+            start.elapsed(),
+            &sound,
+        )
+        .unwrap();
 
         (sound, start.elapsed())
     }